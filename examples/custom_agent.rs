@@ -0,0 +1,43 @@
+//! Implements a simple agent and plays it against alpha-beta search.
+//!
+//! ```text
+//! cargo run --example custom_agent
+//! ```
+
+use neutrino::agents::minimax::MinimaxAgent;
+use neutrino::match_runner::play_game;
+use neutrino::{Agent, GameBoard, Player, TurnMove};
+
+/**
+ * Plays a winning move when there is one and otherwise the move leaving the opponent the fewest replies.
+ */
+struct Greedy;
+
+impl Agent for Greedy {
+    fn get_move(&mut self, board: &GameBoard) -> TurnMove {
+        let me = board.to_move();
+        let score = |turn_move: &TurnMove| {
+            let next = board.result(turn_move.clone());
+            match next.utility(me) {
+                Some(utility) => utility * 1000.0,
+                None => -(next.actions().len() as f64),
+            }
+        };
+        board.actions()
+            .into_iter()
+            .max_by(|a, b| score(a).total_cmp(&score(b)))
+            .expect("agents are only asked for moves on boards with legal moves")
+    }
+}
+
+fn main() {
+    let mut greedy = Greedy;
+    let mut alpha_beta = MinimaxAgent::new(1);
+    let record = play_game(&mut greedy, &mut alpha_beta, 100);
+    print!("{record}");
+    match record.result_for(Player::Player1) {
+        Some(1.0) => println!("the greedy agent wins"),
+        Some(_) => println!("alpha-beta wins"),
+        None => println!("no result after 100 moves"),
+    }
+}
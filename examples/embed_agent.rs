@@ -0,0 +1,34 @@
+//! Asks MCTS for the best moves of a position given in notation.
+//!
+//! ```text
+//! cargo run --example embed_agent -- "11111/...../..X../...../22222 1"
+//! ```
+
+use std::{env, time::Duration};
+
+use neutrino::agents::analysis::Analyze;
+use neutrino::agents::monte_carlo_tree_search::MonteCarloTreeSearch;
+use neutrino::{Agent, GameBoard};
+
+fn main() {
+    let board: GameBoard = match env::args().nth(1) {
+        Some(position) => position.parse().unwrap_or_else(|error| panic!("{error}")),
+        None => GameBoard::default(),
+    };
+    if board.is_terminal() {
+        println!("the game is over");
+        return
+    }
+
+    let mut engine = MonteCarloTreeSearch::new(&board, Duration::from_millis(500));
+    //an agent only needs the board to choose a move
+    let turn_move = engine.get_move(&board);
+    println!("mcts plays {}", turn_move.to_notation());
+
+    //analysing agents also report scores and principal variations while they search
+    let analysis = engine.analyze(&board, 3, &mut |update| eprintln!("{} nodes", update.nodes));
+    println!("{analysis}");
+
+    let next = board.try_result(&turn_move).expect("agents play legal moves");
+    println!("{next}");
+}
@@ -1,9 +1,157 @@
-use crate::neutrino_board::{GameBoard, TurnMove};
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
 
+use crate::{agents::analysis::SearchReport, neutrino_board::{GameBoard, TurnMove}};
 
-pub(crate) trait Agent {
+/**
+ * A shared flag interrupting searches. Clones share the flag, so a UI, the engine protocol's `stop` command
+ * or a clock can keep one while an agent searches on another thread; the agent then returns the best move
+ * found so far. The flag stays set until `reset`, so later searches of the agent also end at once.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct StopHandle(Arc<AtomicBool>);
+
+impl StopHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+/**
+ * A player choosing moves. Implement `get_move` to plug a new player into matches, engines and servers.
+ */
+pub trait Agent {
     /**
      * Returns the move that the agent chooses for a given board.
      */
     fn get_move(&mut self, board: &GameBoard) -> TurnMove;
-}
\ No newline at end of file
+
+    /**
+     * Returns the move that the agent chooses, or `None` if the agent forfeits the game
+     * (e.g. an external engine that crashed or ran out of time).
+     */
+    fn try_get_move(&mut self, board: &GameBoard) -> Option<TurnMove> {
+        Some(self.get_move(board))
+    }
+
+    /**
+     * Chooses a move like `try_get_move` and reports how it was found.
+     * Searching agents call `on_progress` with the best move so far while they search.
+     */
+    fn search(&mut self, board: &GameBoard, on_progress: &mut dyn FnMut(&SearchReport)) -> Option<SearchReport> {
+        let _ = on_progress;
+        self.try_get_move(board).map(SearchReport::from_move)
+    }
+
+    /**
+     * Makes the agent's searches end early once the handle is stopped. Agents that do not search ignore it.
+     */
+    fn set_stop_handle(&mut self, stop_handle: StopHandle) {
+        let _ = stop_handle;
+    }
+}
+
+impl<A: Agent + ?Sized> Agent for Box<A> {
+    fn get_move(&mut self, board: &GameBoard) -> TurnMove {
+        (**self).get_move(board)
+    }
+
+    fn try_get_move(&mut self, board: &GameBoard) -> Option<TurnMove> {
+        (**self).try_get_move(board)
+    }
+
+    fn search(&mut self, board: &GameBoard, on_progress: &mut dyn FnMut(&SearchReport)) -> Option<SearchReport> {
+        (**self).search(board, on_progress)
+    }
+
+    fn set_stop_handle(&mut self, stop_handle: StopHandle) {
+        (**self).set_stop_handle(stop_handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::{Duration, Instant}};
+
+    use super::*;
+    use crate::agents::{minimax::MinimaxAgent, monte_carlo_tree_search::MonteCarloTreeSearch, random_agent::RandomAgent};
+
+    /**
+     * Searches that would take far too long, stopped before and while they run.
+     */
+    fn assert_stops_quickly(mut agent: Box<dyn Agent>) {
+        let board = GameBoard::default();
+        let stop_handle = StopHandle::new();
+        agent.set_stop_handle(stop_handle.clone());
+
+        stop_handle.stop();
+        let start = Instant::now();
+        let turn_move = agent.get_move(&board);
+        assert!(start.elapsed() < Duration::from_secs(1), "took {:?} although stopped", start.elapsed());
+        assert!(board.actions().contains(&turn_move));
+
+        stop_handle.reset();
+        let board = board.result(turn_move);
+        let start = Instant::now();
+        let turn_move = thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(100));
+                stop_handle.stop();
+            });
+            agent.get_move(&board)
+        });
+        assert!(start.elapsed() < Duration::from_secs(2), "took {:?} after the stop", start.elapsed());
+        assert!(board.actions().contains(&turn_move));
+    }
+
+    #[test]
+    fn stopped_searches_return_legal_moves() {
+        assert_stops_quickly(Box::new(MonteCarloTreeSearch::new(&GameBoard::default(), Duration::from_secs(600))));
+        assert_stops_quickly(Box::new(MinimaxAgent::new(20)));
+    }
+
+    #[test]
+    fn searches_report_their_progress() {
+        let board = GameBoard::default();
+        let mut reports = vec![];
+        let report = MinimaxAgent::new(3)
+            .search(&board, &mut |report| reports.push(report.clone()))
+            .unwrap();
+        //one report per completed depth, the last being the result
+        assert_eq!(
+            reports
+                .iter()
+                .map(|report| report.depth)
+                .collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        //at depth 1 every move is a single node
+        assert_eq!(reports[0].nodes, board.actions().len());
+        assert!(reports.windows(2).all(|pair| pair[0].nodes < pair[1].nodes));
+        let last = reports.last().unwrap();
+        assert_eq!(
+            (&last.turn_move, last.nodes, &last.pv),
+            (&report.turn_move, report.nodes, &report.pv)
+        );
+        assert_eq!(report.pv.len(), 3);
+
+        //agents that do not search report only their move
+        let report = RandomAgent::default()
+            .search(&board, &mut |_| panic!("no progress without a search"))
+            .unwrap();
+        assert!(board.actions().contains(&report.turn_move));
+        assert_eq!((report.depth, report.nodes, report.score), (0, 0, None));
+        assert_eq!(report.pv, std::slice::from_ref(&report.turn_move));
+    }
+}
@@ -0,0 +1,129 @@
+use std::{fmt::Display, time::Duration};
+
+use crate::{agents::agent::Agent, neutrino_board::{GameBoard, TurnMove}};
+
+/**
+ * One candidate move of an analysis with its principal variation.
+ * The score is the expected utility for the player to move, between 0 (loss) and 1 (win).
+ */
+#[derive(Debug, Clone)]
+pub struct PvLine {
+    pub turn_move: TurnMove,
+    pub score: f64,
+    pub visits: usize,
+    /// The expected continuation, starting with `turn_move`.
+    pub pv: Vec<TurnMove>,
+}
+
+/**
+ * The best moves found for a position, best first.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    pub lines: Vec<PvLine>,
+    /// Search depth for alpha-beta, length of the longest line for MCTS.
+    pub depth: usize,
+    pub nodes: usize,
+    pub elapsed: Duration,
+}
+
+impl Analysis {
+    /**
+     * The move of the best line, `None` for a terminal board.
+     */
+    pub fn best_move(&self) -> Option<&TurnMove> {
+        self.lines.first().map(|line| &line.turn_move)
+    }
+}
+
+impl Display for Analysis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "depth {} nodes {} time {}ms", self.depth, self.nodes, self.elapsed.as_millis())?;
+        for (index, line) in self.lines.iter().enumerate() {
+            let pv: Vec<String> = line.pv.iter().map(TurnMove::to_notation).collect();
+            writeln!(f, "{:>2}. score {:.3} visits {:>7} pv {}", index + 1, line.score, line.visits, pv.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+/**
+ * How an agent found its move: the evaluation, depth, node count, search time and principal variation.
+ * Agents that do not search report only the move.
+ */
+#[derive(Debug, Clone)]
+pub struct SearchReport {
+    pub turn_move: TurnMove,
+    /// Expected utility of the move for the player to move, between 0 (loss) and 1 (win).
+    pub score: Option<f64>,
+    pub depth: usize,
+    /// Nodes searched, the size of the tree for MCTS (about one node per playout).
+    pub nodes: usize,
+    pub elapsed: Duration,
+    /// The expected continuation, starting with `turn_move`.
+    pub pv: Vec<TurnMove>,
+}
+
+impl SearchReport {
+    /**
+     * The report of an agent that chose a move without searching.
+     */
+    pub fn from_move(turn_move: TurnMove) -> Self {
+        Self { pv: vec![turn_move.clone()], turn_move, score: None, depth: 0, nodes: 0, elapsed: Duration::ZERO }
+    }
+
+    /**
+     * The report for the best line of an analysis, `None` for a terminal board.
+     */
+    pub fn from_analysis(analysis: &Analysis) -> Option<Self> {
+        let line = analysis.lines.first()?;
+        Some(Self {
+            turn_move: line.turn_move.clone(),
+            score: Some(line.score),
+            depth: analysis.depth,
+            nodes: analysis.nodes,
+            elapsed: analysis.elapsed,
+            pv: line.pv.clone(),
+        })
+    }
+
+    /**
+     * Nodes searched per second, for MCTS about its playout rate.
+     */
+    pub fn nodes_per_second(&self) -> f64 {
+        if self.elapsed.is_zero() {0.} else {self.nodes as f64 / self.elapsed.as_secs_f64()}
+    }
+}
+
+impl Display for SearchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(score) = self.score {
+            write!(f, "score {score:.3} ")?;
+        }
+        let pv: Vec<String> = self.pv.iter().map(TurnMove::to_notation).collect();
+        write!(f, "depth {} nodes {} nps {:.0} time {}ms pv {}", self.depth, self.nodes, self.nodes_per_second(), self.elapsed.as_millis(), pv.join(" "))
+    }
+}
+
+/**
+ * Runs an analysis for a single best move as an `Agent::search`, passing the intermediate results on as reports.
+ */
+pub(crate) fn search_by_analysis<A: Analyze + ?Sized>(agent: &mut A, board: &GameBoard, on_progress: &mut dyn FnMut(&SearchReport)) -> Option<SearchReport> {
+    let analysis = agent.analyze(board, 1, &mut |analysis| {
+        if let Some(report) = SearchReport::from_analysis(analysis) {
+            on_progress(&report);
+        }
+    });
+    SearchReport::from_analysis(&analysis)
+}
+
+/**
+ * Search agents that can report their top moves instead of only the best one.
+ */
+pub trait Analyze: Agent {
+    /**
+     * Searches the board and returns the best `multi_pv` moves.
+     * `on_update` is called with intermediate results while the search runs.
+     */
+    fn analyze(&mut self, board: &GameBoard, multi_pv: usize, on_update: &mut dyn FnMut(&Analysis)) -> Analysis;
+}
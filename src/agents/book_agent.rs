@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use rand::rngs::ThreadRng;
+
+use crate::{agents::{agent::{Agent, StopHandle}, analysis::SearchReport}, neutrino_board::{GameBoard, TurnMove}, opening_book::{BookSelection, OpeningBook}};
+
+/**
+ * Plays from an opening book while the position is in it, and asks the inner agent otherwise.
+ */
+pub struct BookAgent<A: Agent> {
+    book: Arc<OpeningBook>,
+    inner: A,
+    selection: BookSelection,
+    rng: ThreadRng
+}
+
+impl<A: Agent> BookAgent<A> {
+    pub fn new(book: Arc<OpeningBook>, inner: A, selection: BookSelection) -> Self {
+        Self { book, inner, selection, rng: ThreadRng::default() }
+    }
+}
+
+impl<A: Agent> Agent for BookAgent<A> {
+    fn get_move(&mut self, board: &GameBoard) -> TurnMove {
+        match self.book.select(board, self.selection, &mut self.rng) {
+            Some(book_move) => book_move,
+            None => self.inner.get_move(board),
+        }
+    }
+
+    fn search(&mut self, board: &GameBoard, on_progress: &mut dyn FnMut(&SearchReport)) -> Option<SearchReport> {
+        match self.book.select(board, self.selection, &mut self.rng) {
+            Some(book_move) => Some(SearchReport::from_move(book_move)),
+            None => self.inner.search(board, on_progress),
+        }
+    }
+
+    fn set_stop_handle(&mut self, stop_handle: StopHandle) {
+        self.inner.set_stop_handle(stop_handle);
+    }
+}
@@ -0,0 +1,241 @@
+use std::{fmt::Display, io::{self, BufRead, BufReader, Write}, process::{Child, ChildStdin, Command, Stdio}, sync::mpsc::{self, Receiver, RecvTimeoutError}, thread, time::{Duration, Instant}};
+
+use crate::{agents::agent::Agent, engine_protocol::{EngineMessage, InfoLine}, neutrino_board::{GameBoard, MoveError, TurnMove}, rules::RuleSet};
+
+/// Extra time an engine gets beyond its time limit before it is sent `stop`, and again before it forfeits.
+const GRACE_PERIOD: Duration = Duration::from_millis(500);
+/// How long an engine may take to answer the `neutrino` handshake.
+const HANDSHAKE_TIME_OUT: Duration = Duration::from_secs(5);
+
+/**
+ * Why an external engine forfeited.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineError {
+    /// The process could not be started or its pipes closed.
+    Crashed(String),
+    TimedOut,
+    /// The engine played the given move, which is illegal for the given reason.
+    IllegalMove(String, MoveError),
+    /// The engine answered `bestmove none` for a board with legal moves.
+    NoMove,
+}
+
+impl Display for EngineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EngineError::Crashed(reason) => write!(f, "engine crashed: {reason}"),
+            EngineError::TimedOut => write!(f, "engine timed out"),
+            EngineError::IllegalMove(turn_move, error) => write!(f, "engine played an illegal move {turn_move}: {error}"),
+            EngineError::NoMove => write!(f, "engine returned no move"),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+/**
+ * An agent played by another program speaking the engine protocol (see `engine_protocol`) over its stdin/stdout.
+ * Once the engine crashes, times out or plays an illegal move it forfeits every following move.
+ */
+pub struct ExternalEngineAgent {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    time_limit: Duration,
+    name: String,
+    last_info: Option<InfoLine>,
+    error: Option<EngineError>,
+}
+
+impl ExternalEngineAgent {
+    /**
+     * Starts the engine and waits for it to answer the handshake.
+     */
+    pub fn spawn(program: &str, args: &[String], time_limit: Duration) -> Result<Self, EngineError> {
+        let crashed = |error: io::Error| EngineError::Crashed(error.to_string());
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(crashed)?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        //read on a separate thread so that reads can time out
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break
+                }
+            }
+        });
+
+        let mut agent = Self { child, stdin, lines, time_limit, name: program.to_string(), last_info: None, error: None };
+        agent.send("neutrino")?;
+        let deadline = Instant::now() + HANDSHAKE_TIME_OUT;
+        loop {
+            match agent.receive(deadline)? {
+                EngineMessage::Id(key, value) if key == "name" => agent.name = value,
+                EngineMessage::NeutrinoOk => return Ok(agent),
+                _ => {},
+            }
+        }
+    }
+
+    /**
+     * The last `info` line the engine sent during its latest search.
+     */
+    pub fn last_info(&self) -> Option<&InfoLine> {
+        self.last_info.as_ref()
+    }
+
+    /**
+     * Asks the engine for a move within its time limit, checking that the move is legal.
+     */
+    pub fn request_move(&mut self, board: &GameBoard) -> Result<TurnMove, EngineError> {
+        if let Some(error) = &self.error {
+            return Err(error.clone())
+        }
+        let result = self.search(board);
+        if let Err(error) = &result {
+            self.error = Some(error.clone());
+            let _ = self.child.kill();
+        }
+        result
+    }
+
+    fn search(&mut self, board: &GameBoard) -> Result<TurnMove, EngineError> {
+        self.last_info = None;
+        match board.rules() == RuleSet::STANDARD {
+            true => self.send(&format!("position {}", board.to_notation()))?,
+            false => self.send(&format!("position rules {} {}", board.rules(), board.to_notation()))?,
+        }
+        self.send(&format!("go time {}", self.time_limit.as_millis()))?;
+
+        let mut deadline = Instant::now() + self.time_limit + GRACE_PERIOD;
+        let mut stopped = false;
+        loop {
+            let message = match self.receive(deadline) {
+                Err(EngineError::TimedOut) if !stopped => {
+                    self.send("stop")?;
+                    stopped = true;
+                    deadline = Instant::now() + GRACE_PERIOD;
+                    continue
+                },
+                message => message?,
+            };
+            match message {
+                EngineMessage::Info(info) if info.string.is_none() => self.last_info = Some(info),
+                EngineMessage::BestMove(Some(turn_move)) => {
+                    return match board.try_result(&turn_move) {
+                        Ok(_) => Ok(turn_move),
+                        Err(error) => Err(EngineError::IllegalMove(turn_move.to_notation(), error)),
+                    }
+                },
+                EngineMessage::BestMove(None) => return Err(EngineError::NoMove),
+                _ => {},
+            }
+        }
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), EngineError> {
+        writeln!(self.stdin, "{command}")
+            .and_then(|_| self.stdin.flush())
+            .map_err(|error| EngineError::Crashed(error.to_string()))
+    }
+
+    /**
+     * The next message the engine sends before the deadline. Lines that are not protocol messages are skipped.
+     */
+    fn receive(&mut self, deadline: Instant) -> Result<EngineMessage, EngineError> {
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.lines.recv_timeout(remaining) {
+                Ok(line) => if let Ok(message) = line.parse() {
+                    return Ok(message)
+                },
+                Err(RecvTimeoutError::Timeout) => return Err(EngineError::TimedOut),
+                Err(RecvTimeoutError::Disconnected) => return Err(EngineError::Crashed("the engine closed its output".to_string())),
+            }
+        }
+    }
+}
+
+impl Agent for ExternalEngineAgent {
+    fn get_move(&mut self, board: &GameBoard) -> TurnMove {
+        self.request_move(board).unwrap_or_else(|error| panic!("{} forfeited: {error}", self.name))
+    }
+
+    fn try_get_move(&mut self, board: &GameBoard) -> Option<TurnMove> {
+        match self.request_move(board) {
+            Ok(turn_move) => Some(turn_move),
+            Err(error) => {
+                eprintln!("{} forfeits: {error}", self.name);
+                None
+            }
+        }
+    }
+}
+
+impl Drop for ExternalEngineAgent {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        //give the engine a moment to exit on its own before killing it
+        let deadline = Instant::now() + GRACE_PERIOD;
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::neutrino_board::Position;
+
+    /**
+     * Starts a fake engine: a shell script that shakes hands and runs `on_go` for every `go`.
+     */
+    fn fake_engine(on_go: &str) -> Result<ExternalEngineAgent, EngineError> {
+        let script = format!(r#"while read line; do case "$line" in neutrino) echo "id name fake"; echo neutrinook;; go*) {on_go};; quit) exit 0;; esac; done"#);
+        ExternalEngineAgent::spawn("sh", &["-c".to_string(), script], Duration::from_millis(20))
+    }
+
+    #[test]
+    fn engines_play_legal_moves() {
+        let mut engine = fake_engine(r#"echo "info depth 1 nodes 5 pv a1a4"; echo "bestmove a1a4""#).unwrap();
+        assert_eq!(engine.name, "fake");
+        let board = GameBoard::default();
+        assert_eq!(engine.try_get_move(&board), Some("a1a4".parse().unwrap()));
+        assert_eq!(engine.last_info().and_then(|info| info.nodes), Some(5));
+    }
+
+    #[test]
+    fn engines_forfeit() {
+        let board = GameBoard::default();
+        let mut illegal = fake_engine(r#"echo "bestmove a1a2""#).unwrap();
+        assert_eq!(illegal.request_move(&board), Err(EngineError::IllegalMove("a1a2".to_string(), MoveError::NotSliding(Position::new(1, 0)))));
+        //a forfeited engine is not asked again
+        assert!(matches!(illegal.request_move(&board), Err(EngineError::IllegalMove(_, _))));
+        assert_eq!(illegal.try_get_move(&board), None);
+
+        let mut silent = fake_engine(":").unwrap();
+        assert_eq!(silent.request_move(&board), Err(EngineError::TimedOut));
+
+        let mut crashing = fake_engine("exit 1").unwrap();
+        assert!(matches!(crashing.request_move(&board), Err(EngineError::Crashed(_))));
+
+        let mut resigning = fake_engine(r#"echo "bestmove none""#).unwrap();
+        assert_eq!(resigning.request_move(&board), Err(EngineError::NoMove));
+
+        assert!(matches!(ExternalEngineAgent::spawn("no-such-engine", &[], Duration::from_millis(20)), Err(EngineError::Crashed(_))));
+    }
+}
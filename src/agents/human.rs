@@ -1,75 +1,308 @@
-use std::{collections::HashMap, io::{stdin, stdout, Write}};
+use std::io::{stdin, stdout, BufRead, BufReader, Write};
 
 use crate::{agents::agent::Agent, neutrino_board::{GameBoard, MoveType, TurnMove}};
 
+const HELP: &str = "\
+Enter a move as <neutrino move>/<piece move>, e.g. c3c4/a1a2, or just the piece move
+on a piece-only first turn. Or:
+  <number> or <neutrino move>  pick a neutrino move from the list, then the piece move
+  (empty line)                 list the neutrino moves
+  legal                        show all legal moves
+  hint                         ask the hint engine for a move
+  undo                         take back your last move
+  draw                         offer a draw
+  resign                       resign the game
+  board                        show the board
+  help                         show this help";
 
-pub(crate) struct Human {
+/**
+ * What a human player chose to do on their turn.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum HumanAction {
+    Move(TurnMove),
+    Undo,
+    OfferDraw,
+    Resign,
+}
+
+/**
+ * A player entering moves on a terminal. Invalid input is reported and asked for again.
+ */
+pub struct Human {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    hint_engine: Option<Box<dyn Agent>>,
 }
 
 impl Human {
-    pub(crate) fn new() -> Self {
-        Self {  }
+    pub fn new() -> Self {
+        Self::with_io(Box::new(BufReader::new(stdin())), Box::new(stdout()))
+    }
+
+    pub fn with_io(input: Box<dyn BufRead>, output: Box<dyn Write>) -> Self {
+        Self { input, output, hint_engine: None }
     }
-    
-    fn get_move_map(board: &GameBoard) -> HashMap<MoveType, Vec<TurnMove>> {
-        let mut map: HashMap<MoveType, Vec<TurnMove>> = HashMap::new();
+
+    /**
+     * The engine asked for a move when the player types `hint`.
+     */
+    pub fn with_hint_engine(mut self, engine: Box<dyn Agent>) -> Self {
+        self.hint_engine = Some(engine);
+        self
+    }
+
+    /**
+     * The legal moves grouped by neutrino move, sorted by notation so the numbering is stable.
+     * A piece-only first turn has a single group without a neutrino move.
+     */
+    fn get_move_groups(board: &GameBoard) -> Vec<(Option<MoveType>, Vec<TurnMove>)> {
+        let mut groups: Vec<(Option<MoveType>, Vec<TurnMove>)> = vec![];
         for action in board.actions() {
-            if map.contains_key(&action.neutrino_move) {
-                map.get_mut(&action.neutrino_move).unwrap().push(action);
-            } else {
-                map.insert(action.neutrino_move.clone(), vec![action]);
+            match groups.iter_mut().find(|(neutrino_move, _)| *neutrino_move == action.neutrino_move) {
+                Some((_, moves)) => moves.push(action),
+                None => groups.push((action.neutrino_move.clone(), vec![action])),
+            }
+        }
+        groups.sort_by_key(|(neutrino_move, _)| neutrino_label(neutrino_move.as_ref()));
+        for (_, moves) in &mut groups {
+            moves.sort_by_key(|turn_move| turn_move.piece_move.to_notation());
+        }
+        groups
+    }
+
+    /**
+     * Prompts and reads a line. `None` when the input is closed.
+     */
+    fn prompt(&mut self, text: &str) -> Option<String> {
+        let _ = write!(self.output, "{text}");
+        let _ = self.output.flush();
+        let mut line = String::new();
+        match self.input.read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim().to_lowercase()),
+        }
+    }
+
+    fn say(&mut self, text: &str) {
+        let _ = writeln!(self.output, "{text}");
+    }
+
+    fn list_neutrino_moves(&mut self, groups: &[(Option<MoveType>, Vec<TurnMove>)]) {
+        for (index, (neutrino_move, moves)) in groups.iter().enumerate() {
+            self.say(&format!("{index:>3}: {} ({} piece moves)", neutrino_label(neutrino_move.as_ref()), moves.len()));
+        }
+    }
+
+    fn list_legal_moves(&mut self, groups: &[(Option<MoveType>, Vec<TurnMove>)]) {
+        for (neutrino_move, moves) in groups {
+            let piece_moves: Vec<String> = moves.iter().map(|turn_move| turn_move.piece_move.to_notation()).collect();
+            self.say(&format!("{}: {}", neutrino_label(neutrino_move.as_ref()), piece_moves.join(" ")));
+        }
+    }
+
+    fn hint(&mut self, board: &GameBoard) {
+        let hint = self.hint_engine.as_mut().map(|engine| engine.get_move(board));
+        match hint {
+            Some(hint) => self.say(&format!("hint: {}", hint.to_notation())),
+            None => self.say("no hint engine configured"),
+        }
+    }
+
+    /**
+     * Asks for the piece move after the neutrino move has been chosen.
+     * `None` goes back to choosing the neutrino move.
+     */
+    fn choose_piece_move(&mut self, neutrino_move: Option<&MoveType>, moves: &[TurnMove]) -> Option<TurnMove> {
+        self.say(&format!("Piece moves after {}:", neutrino_label(neutrino_move)));
+        for (index, turn_move) in moves.iter().enumerate() {
+            self.say(&format!("{index:>3}: {}", turn_move.piece_move.to_notation()));
+        }
+        loop {
+            let line = self.prompt("Piece move (number or e.g. a1a2, empty to go back): ")?;
+            if line.is_empty() {
+                return None
+            }
+            let chosen = match line.parse::<usize>() {
+                Ok(index) => moves.get(index),
+                Err(_) => line.parse::<MoveType>().ok()
+                    .and_then(|piece_move| moves.iter().find(|turn_move| turn_move.piece_move == piece_move)),
+            };
+            match chosen {
+                Some(turn_move) => return Some(turn_move.clone()),
+                None => self.say(&format!("'{line}' is not one of the piece moves")),
+            }
+        }
+    }
+
+    /**
+     * Reads input until the player enters a legal move or a command that ends their turn.
+     * A closed input resigns.
+     */
+    pub fn get_action(&mut self, board: &GameBoard) -> HumanAction {
+        let groups = Self::get_move_groups(board);
+        self.say(&format!("Current board:\n{board}"));
+        loop {
+            let Some(line) = self.prompt("Your move ('help' for commands): ") else {
+                return HumanAction::Resign
+            };
+            match line.as_str() {
+                "" => self.list_neutrino_moves(&groups),
+                "help" => self.say(HELP),
+                "board" => self.say(&board.to_string()),
+                "legal" | "show legal" => self.list_legal_moves(&groups),
+                "hint" => self.hint(board),
+                "undo" => return HumanAction::Undo,
+                "draw" | "offer draw" => return HumanAction::OfferDraw,
+                "resign" => return HumanAction::Resign,
+                _ => {
+                    //without a slash the input is a neutrino move to pick, unless the turn moves only a piece
+                    if (line.contains('/') || board.is_piece_only_turn())
+                        && let Ok(turn_move) = line.parse::<TurnMove>() {
+                        match board.try_result(&turn_move) {
+                            Ok(_) => return HumanAction::Move(turn_move),
+                            Err(error) => self.say(&format!("{line} is not a legal move: {error}. Type 'legal' to see them")),
+                        }
+                        continue
+                    }
+                    let group = match line.parse::<usize>() {
+                        Ok(index) => groups.get(index),
+                        Err(_) => line.parse::<MoveType>().ok()
+                            .and_then(|neutrino_move| groups.iter().find(|(candidate, _)| candidate.as_ref() == Some(&neutrino_move))),
+                    };
+                    match group {
+                        Some((neutrino_move, moves)) => {
+                            let (neutrino_move, moves) = (neutrino_move.clone(), moves.clone());
+                            if let Some(turn_move) = self.choose_piece_move(neutrino_move.as_ref(), &moves) {
+                                return HumanAction::Move(turn_move)
+                            }
+                        },
+                        None => self.say(&format!("could not understand '{line}', type 'help' for the commands")),
+                    }
+                },
+            }
+        }
+    }
+
+    /**
+     * Asks whether the player accepts the opponent's draw offer.
+     */
+    pub fn accept_draw(&mut self) -> bool {
+        loop {
+            match self.prompt("Your opponent offers a draw, accept? (y/n) ").as_deref() {
+                Some("y" | "yes") => return true,
+                Some("n" | "no") | None => return false,
+                Some(_) => {},
             }
         }
-        map
     }
 }
 
 impl Default for Human {
     fn default() -> Self {
-        Self {  }
+        Self::new()
     }
 }
 
-impl Agent for Human {
+/**
+ * A neutrino move in notation, or a note that the turn moves only a piece.
+ */
+fn neutrino_label(neutrino_move: Option<&MoveType>) -> String {
+    neutrino_move.map_or("no neutrino move".to_string(), MoveType::to_notation)
+}
 
+impl Agent for Human {
     fn get_move(&mut self, board: &GameBoard) -> TurnMove {
-        let actions: HashMap<MoveType, Vec<TurnMove>> = Self::get_move_map(board);
-
-        let mut action: Option<TurnMove> = None;
-        let stdin = stdin();
-        let mut user_selection = String::new();
-        while action.is_none() {
-            println!("Current board:\n{}", board);
-            println!("Available Neutrino Moves:");
-            
-            //Assumes keys() is stable
-            let neutrino_moves: Vec<&MoveType> = actions.keys().collect();
-
-            for (idx, neutrino) in neutrino_moves.iter().enumerate() {
-                println!("Neutrino Move {}: {}", idx, neutrino);
-            }
-            user_selection.clear();
-            print!("Please select a Neutrino move ");
-            let _ = stdout().flush();
-
-            let _ = stdin.read_line(&mut user_selection);
-            //TODO continue on error
-            let neutrino_move: &MoveType = neutrino_moves[user_selection.trim().parse::<usize>().unwrap()];
-            let piece_moves: &Vec<TurnMove> = actions.get(neutrino_move).unwrap();
-
-            for (idx, turn_move) in piece_moves.iter().enumerate() {
-                println!("Move {}: {}", idx, turn_move);
-                println!("{}", board.result(turn_move.clone()))
+        self.try_get_move(board).expect("The human player resigned")
+    }
+
+    /**
+     * Only moves and resigning make sense when the human is used as a plain agent; other commands are refused.
+     */
+    fn try_get_move(&mut self, board: &GameBoard) -> Option<TurnMove> {
+        loop {
+            match self.get_action(board) {
+                HumanAction::Move(turn_move) => return Some(turn_move),
+                HumanAction::Resign => return None,
+                _ => self.say("that is not available in this game, please enter a move"),
             }
-            user_selection.clear();
-            print!("Please select the rest of the move ");
-            let _ = stdout().flush();
-
-            let _ = stdin.read_line(&mut user_selection);
-            let piece_move_index: usize = user_selection.trim().parse().unwrap();
-            let parsed_move: TurnMove = piece_moves.get(piece_move_index).unwrap().clone();
-            action = Some(parsed_move)
         }
-        action.unwrap()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    /**
+     * Output that stays readable after the player took ownership of it.
+     */
+    #[derive(Clone, Default)]
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedOutput {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    fn human(input: &'static str) -> (Human, SharedOutput) {
+        let output = SharedOutput::default();
+        (Human::with_io(Box::new(input.as_bytes()), Box::new(output.clone())), output)
+    }
+
+    #[test]
+    fn bad_input_is_asked_for_again() {
+        let (mut player, output) = human("hello\nx9x9\nc3c4/a1a2\na1a2\nA1A4\n");
+        assert_eq!(player.get_move(&GameBoard::default()), "a1a4".parse().unwrap());
+        let output = output.text();
+        assert!(output.contains("could not understand 'hello'"));
+        assert!(output.contains("x9x9 is not a legal move: x9 is off the board"));
+        assert!(output.contains("c3c4/a1a2 is not a legal move: the first turn"));
+        assert!(output.contains("a1a2 is not a legal move"));
+    }
+
+    #[test]
+    fn moves_are_picked_from_the_lists() {
+        let board = GameBoard::default().result("a1a4".parse().unwrap());
+        let (mut player, output) = human("99\nc3c4\nzz\n\nc3c4\n0\n");
+        let first_piece_move = board.actions().into_iter()
+            .filter(|turn_move| turn_move.neutrino_move == Some("c3c4".parse().unwrap()))
+            .min_by_key(|turn_move| turn_move.piece_move.to_notation())
+            .unwrap();
+        assert_eq!(player.try_get_move(&board), Some(first_piece_move));
+        let output = output.text();
+        assert!(output.contains("could not understand '99'"));
+        assert!(output.contains("'zz' is not one of the piece moves"));
+        assert_eq!(output.matches("Piece moves after c3c4:").count(), 2);
+    }
+
+    #[test]
+    fn commands_end_the_turn() {
+        let board = GameBoard::default();
+        assert_eq!(human("undo\n").0.get_action(&board), HumanAction::Undo);
+        assert_eq!(human("offer draw\n").0.get_action(&board), HumanAction::OfferDraw);
+        assert_eq!(human("resign\n").0.get_action(&board), HumanAction::Resign);
+        //a closed input resigns
+        assert_eq!(human("").0.try_get_move(&board), None);
+        //as a plain agent only moves and resigning are accepted
+        let (mut player, output) = human("undo\na1a4\n");
+        assert_eq!(player.try_get_move(&board), Some("a1a4".parse().unwrap()));
+        assert!(output.text().contains("that is not available in this game"));
+
+        assert!(human("maybe\ny\n").0.accept_draw());
+        assert!(!human("").0.accept_draw());
+    }
+}
@@ -1,60 +1,167 @@
-use crate::{neutrino_board::{GameBoard, Player, TurnMove}, Agent};
+use std::{iter, time::Instant};
 
-pub(crate) struct MinimaxAgent {
-    //TODO maybe add max-depth as a field
-    player: Player,
-    max_depth: usize
+use crate::{agents::{agent::StopHandle, analysis::{search_by_analysis, Analysis, Analyze, PvLine, SearchReport}}, neutrino_board::{GameBoard, Player, TurnMove}, Agent};
+
+/**
+ * Depth-limited minimax with alpha-beta pruning.
+ * Boards at the depth limit are scored with a heuristic, and repeated positions as draws.
+ * A stopped search plays the best move of the last depth it completed.
+ */
+pub struct MinimaxAgent {
+    max_depth: usize,
+    nodes: usize,
+    stop_handle: StopHandle,
 }
 
 impl MinimaxAgent {
-    pub(crate) fn new(player: Player, max_depth: usize) -> Self {
-        Self {player, max_depth}
-    } 
+    pub fn new(max_depth: usize) -> Self {
+        Self {max_depth, nodes: 0, stop_handle: StopHandle::new()}
+    }
+
+    /**
+     * Heuristic value of a non-terminal board: a neutrino close to the row the player wins on is good,
+     * and one close to the opponent's winning row is bad.
+     */
+    fn evaluate(board: &GameBoard, player: Player) -> f64 {
+        let neutrino_row = board.neutrino_position().row;
+        let distance = |player: Player| neutrino_row.abs_diff(board.winning_row(player)) as f64;
+        let player1_value = 0.5 + 0.025 * (distance(Player::Player2) - distance(Player::Player1));
+        match player {
+            Player::Player1 => player1_value,
+            Player::Player2 => 1.0 - player1_value,
+        }
+    }
 
-    fn max_value(&self, board: &GameBoard, depth: usize) -> (f64, Option<TurnMove>) {
+    /**
+     * The value of the board for `player` when it is `player`'s turn, and the principal variation leading to it.
+     * The board is searched in place and restored before returning.
+     */
+    fn max_value(&mut self, board: &mut GameBoard, player: Player, depth: usize, mut alpha: f64, beta: f64) -> (f64, Vec<TurnMove>) {
+        self.nodes += 1;
         if board.is_terminal() {
-            return (board.utility(self.player).unwrap(), None)
+            return (board.utility(player).unwrap(), vec![])
+        }
+        //a position repeating in the tree is scored as a draw: a player who can do better avoids the cycle
+        if board.repetitions() > 0 {
+            return (0.5, vec![])
+        }
+        if depth == 0 {
+            return (Self::evaluate(board, player), vec![])
+        }
+        //the value is not used, the unfinished depth is discarded
+        if self.stop_handle.is_stopped() {
+            return (0.5, vec![])
         }
 
         let mut value = f64::NEG_INFINITY;
-        let mut best_action: Option<TurnMove> = None;
-        for action in board.actions() {
-            let (subtree_value, _) = self.min_value(board, depth + 1);
+        let mut principal_variation = vec![];
+        for action in &board.legal_moves() {
+            let undo = board.make_move(&action);
+            let (subtree_value, subtree_variation) = self.min_value(board, player, depth - 1, alpha, beta);
+            board.unmake_move(undo);
             if subtree_value > value {
                 value = subtree_value;
-                best_action = Some(action);
+                principal_variation = iter::once(action).chain(subtree_variation).collect();
             }
+            if value >= beta {
+                break
+            }
+            alpha = alpha.max(value);
         }
-        return (value, best_action)
+        (value, principal_variation)
     }
 
-    fn min_value(&self, board: &GameBoard, depth: usize) -> (f64, Option<TurnMove>) {
+    /**
+     * The value of the board for `player` when it is the opponent's turn, and the principal variation leading to it.
+     */
+    fn min_value(&mut self, board: &mut GameBoard, player: Player, depth: usize, alpha: f64, mut beta: f64) -> (f64, Vec<TurnMove>) {
+        self.nodes += 1;
         if board.is_terminal() {
-            return (board.utility(self.player).unwrap(), None)
+            return (board.utility(player).unwrap(), vec![])
         }
+        if board.repetitions() > 0 {
+            return (0.5, vec![])
+        }
+        if depth == 0 {
+            return (Self::evaluate(board, player), vec![])
+        }
+        //the value is not used, the unfinished depth is discarded
+        if self.stop_handle.is_stopped() {
+            return (0.5, vec![])
+        }
+
         let mut value = f64::INFINITY;
-        let mut best_action: Option<TurnMove> = None;
-        for action in board.actions() {
-            let (subtree_value, _) = self.max_value(board, depth + 1);
+        let mut principal_variation = vec![];
+        for action in &board.legal_moves() {
+            let undo = board.make_move(&action);
+            let (subtree_value, subtree_variation) = self.max_value(board, player, depth - 1, alpha, beta);
+            board.unmake_move(undo);
             if subtree_value < value {
                 value = subtree_value;
-                best_action = Some(action);
+                principal_variation = iter::once(action).chain(subtree_variation).collect();
             }
+            if value <= alpha {
+                break
+            }
+            beta = beta.min(value);
         }
-        return (value, best_action)
+        (value, principal_variation)
     }
+}
+
+impl Analyze for MinimaxAgent {
     /**
-     * Return a move
+     * Iterative deepening up to the maximum depth. Every root move is searched with a full window,
+     * so the scores of all lines are exact; `on_update` is called after each depth.
+     * Depth 1 always completes, so a stopped search still has a move.
      */
-    fn get_move_optional(&mut self, board: &GameBoard) -> Option<TurnMove> {
-            let (_, action) = self.max_value(board, 0);
-            action
+    fn analyze(&mut self, board: &GameBoard, multi_pv: usize, on_update: &mut dyn FnMut(&Analysis)) -> Analysis {
+        let start_time = Instant::now();
+        let player = board.to_move();
+        //searched in place with make_move/unmake_move
+        let mut board = board.clone();
+        self.nodes = 0;
+        let mut analysis = Analysis::default();
+        for depth in 1..=self.max_depth.max(1) {
+            let mut lines: Vec<PvLine> = vec![];
+            for action in board.actions() {
+                if depth > 1 && self.stop_handle.is_stopped() {
+                    break
+                }
+                let nodes_before = self.nodes;
+                let undo = board.make_move(&action);
+                let (score, variation) = self.min_value(&mut board, player, depth - 1, f64::NEG_INFINITY, f64::INFINITY);
+                board.unmake_move(undo);
+                lines.push(PvLine {
+                    turn_move: action.clone(),
+                    score,
+                    visits: self.nodes - nodes_before,
+                    pv: iter::once(action).chain(variation).collect(),
+                });
+            }
+            if depth > 1 && self.stop_handle.is_stopped() {
+                break
+            }
+            lines.sort_by(|x, y| y.score.total_cmp(&x.score));
+            lines.truncate(multi_pv.max(1));
+            analysis = Analysis { lines, depth, nodes: self.nodes, elapsed: start_time.elapsed() };
+            on_update(&analysis);
         }
+        analysis
+    }
 }
 
 impl Agent for MinimaxAgent {
     fn get_move(&mut self, board: &GameBoard) -> TurnMove {
-        let (_, action) = self.max_value(board, 0);
-        action.expect("Expected to find possible moves, yet max_depth was reached.")
+        let analysis = self.analyze(board, 1, &mut |_| {});
+        analysis.best_move().expect("Expected to find possible moves, yet the board is terminal.").clone()
+    }
+
+    fn search(&mut self, board: &GameBoard, on_progress: &mut dyn FnMut(&SearchReport)) -> Option<SearchReport> {
+        search_by_analysis(self, board, on_progress)
     }
-}
\ No newline at end of file
+
+    fn set_stop_handle(&mut self, stop_handle: StopHandle) {
+        self.stop_handle = stop_handle;
+    }
+}
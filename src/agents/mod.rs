@@ -1,7 +1,21 @@
-pub(crate) mod agent;
-pub(crate) mod human;
-pub(crate) mod random_agent;
-pub(crate) mod minimax;
-pub(crate) mod monte_carlo_tree_search;
-mod tree;
+/// The `Agent` trait every player implements.
+pub mod agent;
+/// Search results with scores and principal variations.
+pub mod analysis;
+/// Playing from an opening book before handing over to another agent.
+pub mod book_agent;
+/// Agents played by other programs speaking the engine protocol.
+pub mod external_engine;
+/// A human entering moves on a terminal.
+pub mod human;
+/// Uniformly random moves.
+pub mod random_agent;
+/// An opponent playing through a game server.
+pub mod remote_agent;
+/// Depth-limited alpha-beta search.
+pub mod minimax;
+/// Monte Carlo tree search.
+pub mod monte_carlo_tree_search;
 
+/// Building agents by name from descriptions with typed parameters, e.g. in TOML or JSON files.
+pub mod registry;
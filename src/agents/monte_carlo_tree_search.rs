@@ -1,13 +1,21 @@
-use std::{collections::HashMap, f64::consts::SQRT_2, time::{Duration, Instant}, ops::{Index, IndexMut}};
+use std::{collections::HashMap, f64::consts::SQRT_2, fmt::Write as _, fs, io, path::PathBuf, time::{Duration, Instant}, ops::{Index, IndexMut}};
 use rand::{rng, seq::IteratorRandom};
+use serde_json::{json, Value};
 
-use crate::{agents::{agent::Agent, random_agent::{self, RandomAgent}}, neutrino_board::{GameBoard, Player, TurnMove}};
+use crate::{agents::{agent::{Agent, StopHandle}, analysis::{search_by_analysis, Analysis, Analyze, PvLine, SearchReport}, random_agent::RandomAgent}, neutrino_board::{GameBoard, Player, TurnMove}};
 
 type NodeIndex = usize;
 
+/// How often `analyze` reports intermediate results.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+
+/**
+ * A node of the search tree, reached through indexing the search with a node index.
+ */
 #[derive(Clone)]
 pub struct Node {
-    utility: usize,
+    /// Sum of the playout results from the perspective of the player who moved into this node.
+    utility: f64,
     number_of_playouts: usize,
     board: GameBoard,
     children: HashMap<TurnMove, NodeIndex>,
@@ -16,11 +24,11 @@ pub struct Node {
 
 impl Node {
     fn new(board: GameBoard, parent: Option<NodeIndex>) -> Self {    
-        Self { utility: 0, number_of_playouts: 0, board, children: HashMap::new(), parent }
+        Self { utility: 0., number_of_playouts: 0, board, children: HashMap::new(), parent }
     }
     
     fn is_leaf(&self) -> bool {
-        self.board.actions().len() == self.children.len() /*|| self.board.is_terminal() */
+        self.board.moves().count() == self.children.len() /*|| self.board.is_terminal() */
     }
 }
 
@@ -29,16 +37,61 @@ enum SelectionResult {
     Terminal(NodeIndex, f64)
 
 }
-pub(crate) struct MonteCarloTreeSearch  {
+/**
+ * An agent searching with Monte Carlo tree search for a fixed time per move, or until its stop handle is stopped.
+ * The tree is kept between moves: when the next board is in the tree at most two moves below the root,
+ * i.e. after the agent's move and the reply, the search goes on from there with the subtree searched so far.
+ */
+pub struct MonteCarloTreeSearch  {
     nodes: Vec<Node>,
     root: NodeIndex,
-    time_out: Duration
+    time_out: Duration,
+    /// The weight of exploration in UCB1, √2 by default.
+    exploration: f64,
+    max_playouts: Option<usize>,
+    heavy_playouts: bool,
+    stop_handle: StopHandle,
+    tree_dump: Option<TreeDump>,
+    moves_played: usize,
 }
 
 impl MonteCarloTreeSearch {
-    pub(crate) fn new(board: &GameBoard, time_out: Duration) -> Self {
+    pub fn new(board: &GameBoard, time_out: Duration) -> Self {
         let root = Node::new(board.clone(), None);
-        Self { nodes: vec![root], root: 0, time_out }
+        Self { nodes: vec![root], root: 0, time_out, exploration: SQRT_2, max_playouts: None, heavy_playouts: false, stop_handle: StopHandle::new(), tree_dump: None, moves_played: 0 }
+    }
+
+    /**
+     * Sets the exploration constant of UCB1: larger values spread the playouts over more moves.
+     */
+    pub fn with_exploration(mut self, exploration: f64) -> Self {
+        self.exploration = exploration;
+        self
+    }
+
+    /**
+     * Ends each search after this many playouts, or at the time out if that comes first.
+     */
+    pub fn with_max_playouts(mut self, max_playouts: usize) -> Self {
+        self.max_playouts = Some(max_playouts);
+        self
+    }
+
+    /**
+     * Makes playouts take an immediate win whenever there is one instead of only at their first move.
+     * Heavy playouts are slower but their results are closer to real play.
+     */
+    pub fn with_heavy_playouts(mut self, heavy_playouts: bool) -> Self {
+        self.heavy_playouts = heavy_playouts;
+        self
+    }
+
+    /**
+     * Writes the search tree after every move the agent plays, see `TreeDump`.
+     */
+    pub fn with_tree_dump(mut self, tree_dump: TreeDump) -> Self {
+        self.tree_dump = Some(tree_dump);
+        self
     }
     
     fn root_player(&self) -> Player {
@@ -53,19 +106,25 @@ impl MonteCarloTreeSearch {
             return SelectionResult::NonTerminal(node_index)
         }
         
-        while self[node_index].is_leaf() {
+        loop {
             //if node is terminal, return it should not be expanded.
             let node = &self[node_index];
             if node.board.is_terminal() {
                 let result = node.board.utility(self.root_player()).expect("We should have a winner here");
                 return SelectionResult::Terminal(node_index, result);
             }
-            //buggy
+            //a position repeating below the root is a draw, the cycle is not searched further
+            if node_index != self.root && node.board.repetitions() > 0 {
+                return SelectionResult::Terminal(node_index, 0.5);
+            }
+            if !node.is_leaf() {
+                return SelectionResult::NonTerminal(node_index)
+            }
             
-            let actions = node.board.actions();
+            let actions = node.board.legal_moves();
             assert!(!actions.is_empty(), "Trying to find actions from terminal state node:\n {}", self[node_index].board);
             //let action: TurnMove = random_agent.get_move(&self.nodes[node].board);
-            let choosen_action = actions.into_iter().max_by(|x,y | {
+            let choosen_action = actions.iter().max_by(|x,y | {
                 let x_node = node.children.get(x).unwrap();
                 let x_value = self.ucb1(*x_node);
                 let y_node = node.children.get(y).unwrap();
@@ -77,7 +136,6 @@ impl MonteCarloTreeSearch {
             let child = node.children.get(&choosen_action).unwrap();
             node_index = *child
         }
-        SelectionResult::NonTerminal(node_index)
     }
 
     /**
@@ -88,8 +146,7 @@ impl MonteCarloTreeSearch {
         //To be selected the node must have an action that is not yet a child.
         let children = &self.nodes[node].children;
         let mut rng = rng();
-        let selected_action = self.nodes[node].board.actions()
-            .into_iter()
+        let selected_action = self.nodes[node].board.moves()
             .filter(|action| !children.contains_key(action))
             .choose(&mut rng)
             .expect("Node must have an action that is not yet a child, yet choose failed!!");
@@ -104,60 +161,63 @@ impl MonteCarloTreeSearch {
     /// Simulates moves until a winner is found, with random_agent as playout policy, returns the utility from the POV of the player at the root node.
     fn simulate(&self, node_index: NodeIndex) -> f64 {
         let root_player = self.root_player();
-        let game_board = self.nodes[node_index].board.clone();
-        //playout policy
-        let terminal_state = game_board.actions().into_iter().find(|action| game_board.result(action.clone().clone()).is_terminal());
-        match terminal_state {
+        if self.nodes[node_index].board.repetitions() > 0 {
+            return 0.5
+        }
+        let mut game_board = self.nodes[node_index].board.clone();
+        //playout policy: take an immediate win if there is one
+        match Self::winning_move(&mut game_board) {
             Some(action) => {
                 let terminal_game_board = game_board.result(action);
                 terminal_game_board.utility(root_player).unwrap()
             },
-            None => Self::random_playout(game_board, root_player)
+            None => Self::random_playout(game_board, root_player, self.heavy_playouts)
         }
     }
 
+    /// A move winning the game at once for the player to move, if there is one.
+    fn winning_move(game_board: &mut GameBoard) -> Option<TurnMove> {
+        let mover = game_board.to_move();
+        game_board.legal_moves().iter().find(|action| {
+            let undo = game_board.make_move(action);
+            let wins = game_board.utility(mover).is_some_and(|utility| utility == 1.);
+            game_board.unmake_move(undo);
+            wins
+        })
+    }
+
     
+    /// Adds the result (from the root player's perspective) to every node from `from_node` up to the root.
     fn back_propagate(&mut self, from_node: NodeIndex, result: f64) {
-        let root_player  =self.nodes[self.root].board.to_move();
-        //scary floating point eq compare!!
-        let winner_is_root = result == 1.;
-        let mut path_to_root: Vec<NodeIndex> = vec![from_node];
-        let mut node_index = from_node;
-        loop {
-            path_to_root.push(node_index);
-            let node = &self.nodes[node_index];
-            match node.parent {
-                Some(parent) => node_index = parent,
-                None => break,
-            }
-        }
-        for (_, node) in self.nodes.iter_mut()
-            .enumerate()
-            .filter(|(index, _node)| path_to_root.contains(index)) {
-                node.number_of_playouts += 1;
-                if winner_is_root && node.board.to_move() == root_player {
-                    node.utility += 1
-                } else if !winner_is_root && node.board.to_move() != root_player {
-                    node.utility += 1
-                }
+        let root_player = self.root_player();
+        let mut node_index = Some(from_node);
+        while let Some(index) = node_index {
+            let node = &mut self.nodes[index];
+            node.number_of_playouts += 1;
+            //the player who moved into the node is the one not to move in it
+            node.utility += if node.board.to_move() == root_player {1. - result} else {result};
+            node_index = node.parent;
         }
     }
 
-    fn random_playout(mut game_board: GameBoard, root_player: Player) -> f64 {
+    /// Plays random moves until the game ends; heavy playouts take immediate wins.
+    fn random_playout(mut game_board: GameBoard, root_player: Player, heavy: bool) -> f64 {
         let mut random_agent = RandomAgent::default();
         while !game_board.is_terminal() {
-            let random_move = random_agent.get_move(&game_board);
-            game_board = game_board.result(random_move);
+            let playout_move = match heavy {
+                true => Self::winning_move(&mut game_board).unwrap_or_else(|| random_agent.get_move(&game_board)),
+                false => random_agent.get_move(&game_board),
+            };
+            game_board.make_move(&playout_move);
         }
         game_board.utility(root_player).expect("Game board should be in a terminal state.")
     }
 
     fn ucb1(&self, node_index: NodeIndex) -> f64 {
         let node = &self.nodes[node_index];
-        let exploration_constant = SQRT_2;
         let parent = &self.nodes[node.parent.expect("ucb doesn't work on the root...")];
 
-        (node.utility / node.number_of_playouts) as f64 + exploration_constant * f64::sqrt(f64::ln(parent.number_of_playouts as f64) / node.number_of_playouts as f64)
+        node.utility / node.number_of_playouts as f64 + self.exploration * f64::sqrt(f64::ln(parent.number_of_playouts as f64) / node.number_of_playouts as f64)
     }
     
 
@@ -177,10 +237,17 @@ impl Index<usize> for MonteCarloTreeSearch {
     }
 }
 
-impl Agent for MonteCarloTreeSearch {
-    fn get_move(&mut self, board: &GameBoard) -> TurnMove {
+impl MonteCarloTreeSearch {
+    /**
+     * Runs select/expand/simulate/back-propagate iterations until the time out, the playout limit or a stop,
+     * calling `on_update` periodically. At least one iteration runs, so the root has a move to play even when stopped at once.
+     */
+    fn run(&mut self, on_update: &mut dyn FnMut(&Self)) {
         let start_time = Instant::now();
-        while start_time.elapsed() < self.time_out {
+        let mut last_update = start_time;
+        let mut playouts = 0;
+        loop {
+            playouts += 1;
             match self.select() {
                 SelectionResult::NonTerminal(selected_node) => {
                     let expanded_node = self.expand(selected_node);
@@ -189,14 +256,359 @@ impl Agent for MonteCarloTreeSearch {
                 },
                 SelectionResult::Terminal(expanded_node, result) => self.back_propagate(expanded_node, result),
             }
-            
-        };
-        
-        let action = self.nodes[self.root].children
+            if start_time.elapsed() >= self.time_out || self.stop_handle.is_stopped() || self.max_playouts.is_some_and(|max| playouts >= max) {
+                break
+            }
+            if last_update.elapsed() >= UPDATE_INTERVAL {
+                on_update(self);
+                last_update = Instant::now();
+            }
+        }
+    }
+
+    /**
+     * The children of the root ordered by number of playouts, as analysis lines.
+     */
+    fn root_lines(&self, multi_pv: usize) -> Vec<PvLine> {
+        let mut children: Vec<(&TurnMove, &NodeIndex)> = self.nodes[self.root].children.iter().collect();
+        children.sort_by_key(|(_, index)| std::cmp::Reverse(self.nodes[**index].number_of_playouts));
+        children.into_iter()
+            .take(multi_pv.max(1))
+            .map(|(turn_move, index)| {
+                let node = &self.nodes[*index];
+                PvLine {
+                    turn_move: turn_move.clone(),
+                    score: node.utility / node.number_of_playouts as f64,
+                    visits: node.number_of_playouts,
+                    pv: std::iter::once(turn_move.clone()).chain(self.principal_variation(*index)).collect(),
+                }
+            })
+            .collect()
+    }
+
+    /**
+     * The most visited path below a node.
+     */
+    fn principal_variation(&self, mut node_index: NodeIndex) -> Vec<TurnMove> {
+        let mut variation = vec![];
+        while let Some((turn_move, child)) = self.nodes[node_index].children
             .iter()
-            .max_by_key(|(_, index)| self.nodes[**index].number_of_playouts)
-            .expect("We should have found at least one move")
-            .0;
-        action.clone()
+            .max_by_key(|(_, index)| self.nodes[**index].number_of_playouts) {
+            variation.push(turn_move.clone());
+            node_index = *child;
+        }
+        variation
     }
-}
\ No newline at end of file
+
+    /**
+     * Makes the node with the board at most two moves below the root the new root, keeping its subtree
+     * and dropping the rest of the tree. Returns whether the board was found.
+     */
+    fn reroot(&mut self, board: &GameBoard) -> bool {
+        let children = |index: NodeIndex| self.nodes[index].children.values().copied();
+        let Some(new_root) = std::iter::once(self.root)
+            .chain(children(self.root))
+            .chain(children(self.root).flat_map(children))
+            .find(|index| self.nodes[*index].board == *board) else {
+            return false
+        };
+        //the subtree in breadth first order, so that parents come before their children
+        let mut order = vec![new_root];
+        let mut next = 0;
+        while let Some(&index) = order.get(next) {
+            order.extend(children(index));
+            next += 1;
+        }
+        let mut new_indices = vec![None; self.nodes.len()];
+        for (new_index, &index) in order.iter().enumerate() {
+            new_indices[index] = Some(new_index);
+        }
+        let mut old_nodes: Vec<Option<Node>> = std::mem::take(&mut self.nodes).into_iter().map(Some).collect();
+        self.nodes = order.iter()
+            .map(|&index| {
+                let mut node = old_nodes[index].take().expect("every node is in the subtree once");
+                node.parent = node.parent.and_then(|parent| new_indices[parent]);
+                node.children.values_mut().for_each(|child| *child = new_indices[*child].expect("children are in the subtree"));
+                node
+            })
+            .collect();
+        self.root = 0;
+        true
+    }
+
+    fn analysis(&self, multi_pv: usize, start_time: Instant) -> Analysis {
+        let lines = self.root_lines(multi_pv);
+        let depth = lines.iter().map(|line| line.pv.len()).max().unwrap_or(0);
+        Analysis { lines, depth, nodes: self.nodes.len(), elapsed: start_time.elapsed() }
+    }
+}
+
+impl Analyze for MonteCarloTreeSearch {
+    fn analyze(&mut self, board: &GameBoard, multi_pv: usize, on_update: &mut dyn FnMut(&Analysis)) -> Analysis {
+        //boards are only found in the tree when reached by the same moves
+        if !self.reroot(board) {
+            self.nodes = vec![Node::new(board.clone(), None)];
+            self.root = 0;
+        }
+        let start_time = Instant::now();
+        self.run(&mut |search| on_update(&search.analysis(multi_pv, start_time)));
+        self.analysis(multi_pv, start_time)
+    }
+}
+
+impl Agent for MonteCarloTreeSearch {
+    fn get_move(&mut self, board: &GameBoard) -> TurnMove {
+        self.search(board, &mut |_| {}).expect("We should have found at least one move").turn_move
+    }
+
+    fn search(&mut self, board: &GameBoard, on_progress: &mut dyn FnMut(&SearchReport)) -> Option<SearchReport> {
+        let report = search_by_analysis(self, board, on_progress);
+        self.moves_played += 1;
+        if let Some(tree_dump) = &self.tree_dump
+            && let Err(error) = tree_dump.write(self, self.moves_played) {
+            eprintln!("could not write the search tree to {}: {error}", tree_dump.directory.display());
+        }
+        report
+    }
+
+    fn set_stop_handle(&mut self, stop_handle: StopHandle) {
+        self.stop_handle = stop_handle;
+    }
+}
+
+/**
+ * Which part of the search tree an export contains: the nodes at most `max_depth` moves below the root
+ * that were visited at least `min_visits` times. The root is always included.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeExportLimits {
+    pub max_depth: usize,
+    pub min_visits: usize,
+}
+
+impl Default for TreeExportLimits {
+    fn default() -> Self {
+        Self { max_depth: 2, min_visits: 1 }
+    }
+}
+
+/**
+ * Where an agent writes its search tree after each move: `<name>-<n>.dot` and `<name>-<n>.json`
+ * in the directory for the agent's nth move.
+ */
+#[derive(Debug, Clone)]
+pub struct TreeDump {
+    pub directory: PathBuf,
+    pub name: String,
+    pub limits: TreeExportLimits,
+}
+
+impl TreeDump {
+    fn write(&self, search: &MonteCarloTreeSearch, move_number: usize) -> io::Result<()> {
+        fs::create_dir_all(&self.directory)?;
+        let path = self.directory.join(format!("{}-{move_number}", self.name));
+        fs::write(path.with_extension("dot"), search.to_dot(self.limits))?;
+        fs::write(path.with_extension("json"), search.to_json(self.limits).to_string())
+    }
+}
+
+impl MonteCarloTreeSearch {
+    /**
+     * The children of a node within the limits, by decreasing visits.
+     */
+    fn exported_children(&self, index: NodeIndex, depth: usize, limits: TreeExportLimits) -> Vec<(&TurnMove, NodeIndex)> {
+        if depth >= limits.max_depth {
+            return vec![]
+        }
+        let mut children: Vec<(&TurnMove, NodeIndex)> = self.nodes[index].children.iter()
+            .map(|(turn_move, child)| (turn_move, *child))
+            .filter(|(_, child)| self.nodes[*child].number_of_playouts >= limits.min_visits)
+            .collect();
+        children.sort_by_key(|(turn_move, child)| (std::cmp::Reverse(self.nodes[*child].number_of_playouts), turn_move.to_notation()));
+        children
+    }
+
+    /**
+     * The average result of a node for the player who moved into it, and its UCB score; the root has no UCB score.
+     */
+    fn node_scores(&self, index: NodeIndex) -> (f64, Option<f64>) {
+        let node = &self.nodes[index];
+        if node.number_of_playouts == 0 {
+            return (0., None)
+        }
+        let ucb = (index != self.root).then(|| self.ucb1(index));
+        (node.utility / node.number_of_playouts as f64, ucb)
+    }
+
+    /**
+     * The search tree within the limits as a Graphviz graph, each node labelled with its move, visits, value and UCB score,
+     * and the board as its tooltip.
+     */
+    pub fn to_dot(&self, limits: TreeExportLimits) -> String {
+        let mut dot = String::from("digraph search_tree {\n    node [shape=box, fontname=monospace];\n");
+        let mut stack: Vec<(NodeIndex, Option<&TurnMove>, usize)> = vec![(self.root, None, 0)];
+        while let Some((index, turn_move, depth)) = stack.pop() {
+            let node = &self.nodes[index];
+            let (value, ucb) = self.node_scores(index);
+            let turn_move = turn_move.map_or("root".to_string(), TurnMove::to_notation);
+            let ucb = ucb.map_or("-".to_string(), |ucb| format!("{ucb:.3}"));
+            let _ = writeln!(dot, "    n{index} [label=\"{turn_move}\\nvisits {}\\nvalue {value:.3}\\nucb {ucb}\", tooltip=\"{}\"];",
+                node.number_of_playouts, node.board.to_notation());
+            let children = self.exported_children(index, depth, limits);
+            for (_, child) in &children {
+                let _ = writeln!(dot, "    n{index} -> n{child};");
+            }
+            //reversed so the most visited child is written first
+            stack.extend(children.into_iter().rev().map(|(turn_move, child)| (child, Some(turn_move), depth + 1)));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /**
+     * The search tree within the limits as nested JSON objects with the move, visits, value, UCB score, board and children of each node.
+     */
+    pub fn to_json(&self, limits: TreeExportLimits) -> Value {
+        self.node_to_json(self.root, None, 0, limits)
+    }
+
+    fn node_to_json(&self, index: NodeIndex, turn_move: Option<&TurnMove>, depth: usize, limits: TreeExportLimits) -> Value {
+        let node = &self.nodes[index];
+        let (value, ucb) = self.node_scores(index);
+        let children: Vec<Value> = self.exported_children(index, depth, limits)
+            .into_iter()
+            .map(|(turn_move, child)| self.node_to_json(child, Some(turn_move), depth + 1, limits))
+            .collect();
+        json!({
+            "move": turn_move.map(TurnMove::to_notation),
+            "visits": node.number_of_playouts,
+            "value": value,
+            "ucb": ucb,
+            "board": node.board.to_notation(),
+            "children": children,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_tree_is_kept_after_a_move_and_the_reply() {
+        let board = GameBoard::default();
+        let mut search = MonteCarloTreeSearch::new(&board, Duration::from_secs(60)).with_max_playouts(500);
+        let turn_move = search.get_move(&board);
+        let after_move = board.result(turn_move.clone());
+        let child = search[search.root].children[&turn_move];
+        let (reply, grandchild) = search[child].children.iter()
+            .max_by_key(|(_, index)| search[**index].number_of_playouts)
+            .map(|(reply, index)| (reply.clone(), *index))
+            .unwrap();
+        let playouts = search[grandchild].number_of_playouts;
+        let subtree_nodes = {
+            let mut stack = vec![grandchild];
+            let mut count = 0;
+            while let Some(index) = stack.pop() {
+                count += 1;
+                stack.extend(search[index].children.values());
+            }
+            count
+        };
+
+        let analysis = search.analyze(&after_move.result(reply), 1, &mut |_| {});
+        assert_eq!(search[search.root].parent, None);
+        assert_eq!(search[search.root].number_of_playouts, playouts + 500);
+        assert!(analysis.nodes >= subtree_nodes);
+        for (index, node) in search.nodes.iter().enumerate().skip(1) {
+            assert_eq!(search[node.parent.unwrap()].children.values().filter(|child| **child == index).count(), 1);
+        }
+
+        //a board that is not in the tree starts a new one
+        search.analyze(&board, 1, &mut |_| {});
+        assert_eq!(search[search.root].number_of_playouts, 500);
+    }
+
+    #[test]
+    fn searched_trees_are_exported() {
+        fn json_counts(node: &Value) -> (usize, usize) {
+            let children = node["children"].as_array().unwrap();
+            children.iter().map(json_counts).fold(
+                (1, children.len()),
+                |(nodes, edges), (child_nodes, child_edges)| {
+                    (nodes + child_nodes, edges + child_edges)
+                },
+            )
+        }
+        fn dot_counts(dot: &str) -> (usize, usize) {
+            let edges = dot.lines().filter(|line| line.contains(" -> ")).count();
+            let nodes = dot.lines().filter(|line| line.contains("[label=")).count();
+            (nodes, edges)
+        }
+
+        let board = GameBoard::default();
+        let mut search =
+            MonteCarloTreeSearch::new(&board, Duration::from_secs(60)).with_max_playouts(100);
+        search.get_move(&board);
+
+        let everything = TreeExportLimits {
+            max_depth: usize::MAX,
+            min_visits: 0,
+        };
+        let all = (search.nodes.len(), search.nodes.len() - 1);
+        assert_eq!(dot_counts(&search.to_dot(everything)), all);
+        assert_eq!(json_counts(&search.to_json(everything)), all);
+
+        //the root and its children visited at least twice
+        let limits = TreeExportLimits {
+            max_depth: 1,
+            min_visits: 2,
+        };
+        let children = search[search.root]
+            .children
+            .values()
+            .filter(|child| search[**child].number_of_playouts >= 2)
+            .count();
+        let expected = (1 + children, children);
+        assert!(expected.0 < all.0);
+        assert_eq!(dot_counts(&search.to_dot(limits)), expected);
+        let json = search.to_json(limits);
+        assert_eq!(json_counts(&json), expected);
+        assert_eq!(json["visits"], 100);
+        assert_eq!(json["move"], Value::Null);
+    }
+
+    #[test]
+    fn fixed_searches_report_their_visits_and_nodes() {
+        let board = GameBoard::default();
+        let mut search =
+            MonteCarloTreeSearch::new(&board, Duration::from_secs(60)).with_max_playouts(300);
+        let report = search.search(&board, &mut |_| {}).unwrap();
+        assert_eq!(search[search.root].number_of_playouts, 300);
+        //at most one node is added per playout
+        assert!(search.nodes.len() <= 301);
+        assert_eq!(report.nodes, search.nodes.len());
+        assert_eq!(report.pv.first(), Some(&report.turn_move));
+
+        let mut search =
+            MonteCarloTreeSearch::new(&board, Duration::from_secs(60)).with_max_playouts(300);
+        let analysis = search.analyze(&board, usize::MAX, &mut |_| {});
+        assert_eq!(analysis.nodes, search.nodes.len());
+        assert_eq!(analysis.lines.len(), search[search.root].children.len());
+        //every playout goes through one of the moves at the root
+        assert_eq!(
+            analysis.lines.iter().map(|line| line.visits).sum::<usize>(),
+            300
+        );
+        assert!(analysis.lines.is_sorted_by(|x, y| x.visits >= y.visits));
+        assert_eq!(
+            analysis.depth,
+            analysis
+                .lines
+                .iter()
+                .map(|line| line.pv.len())
+                .max()
+                .unwrap()
+        );
+    }
+}
@@ -1,29 +1,26 @@
-use rand::{rngs::ThreadRng, seq::IndexedRandom};
+use rand::{rngs::ThreadRng, Rng};
 
 use crate::{agents::agent::Agent, neutrino_board::{GameBoard, TurnMove}};
 
 
-
-pub(crate) struct RandomAgent {
+/**
+ * An agent playing uniformly random legal moves.
+ */
+#[derive(Default)]
+pub struct RandomAgent {
     seed: ThreadRng
 }
 
 impl RandomAgent {
-    fn new(seed: ThreadRng) -> Self {
+    pub fn new(seed: ThreadRng) -> Self {
         Self {seed}
     }
 }
 
-impl Default for RandomAgent {
-    fn default() -> Self {
-        Self { seed: Default::default() }
-    }
-}
-
 impl Agent for RandomAgent {
     fn get_move(&mut self, board: &GameBoard) -> TurnMove {
-        let possible_move = board.actions();
-        let random_move = possible_move.choose(&mut self.seed).unwrap();
-        random_move.clone()
+        let possible_moves = board.legal_moves();
+        let index = self.seed.random_range(0..possible_moves.len());
+        possible_moves.get(index).unwrap()
     }
 }
\ No newline at end of file
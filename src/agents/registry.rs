@@ -0,0 +1,469 @@
+use std::{collections::BTreeMap, fmt::Display, fs, path::{Path, PathBuf}, sync::Arc, time::Duration};
+
+use serde_json::Value;
+
+use crate::{
+    agents::{external_engine::ExternalEngineAgent, minimax::MinimaxAgent, monte_carlo_tree_search::{MonteCarloTreeSearch, TreeDump, TreeExportLimits}, random_agent::RandomAgent},
+    engine_protocol::{AgentFactory, EngineAgent, GoLimits},
+    neutrino_board::GameBoard,
+};
+
+/*
+ * Agents are described by a name and parameters, in a file or on the command line:
+ *
+ *   mcts.toml               agent = "mcts"
+ *                           c = 1.2
+ *                           playouts = 5000
+ *                           heavy = true
+ *   mcts.json               {"agent": "mcts", "c": 1.2, "playouts": 5000, "heavy": true}
+ *   command line            mcts:c=1.2,playouts=5000,heavy=true
+ *
+ * The registry checks the parameters against the agent's schema and builds the agent.
+ */
+
+/**
+ * The type of an agent parameter.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterKind {
+    /// A whole number of at least 0.
+    Integer,
+    Number,
+    Boolean,
+    Text,
+}
+
+impl Display for ParameterKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            ParameterKind::Integer => "a whole number",
+            ParameterKind::Number => "a number",
+            ParameterKind::Boolean => "true or false",
+            ParameterKind::Text => "text",
+        };
+        write!(f, "{kind}")
+    }
+}
+
+/**
+ * A parameter an agent accepts.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct Parameter {
+    pub name: &'static str,
+    pub kind: ParameterKind,
+    pub description: &'static str,
+}
+
+/**
+ * A checked parameter value.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParameterValue {
+    Integer(u64),
+    Number(f64),
+    Boolean(bool),
+    Text(String),
+}
+
+/**
+ * The parameters given for an agent, checked against its schema. Parameters not given use the agent's defaults.
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AgentParameters(BTreeMap<String, ParameterValue>);
+
+impl AgentParameters {
+    pub fn integer(&self, name: &str) -> Option<u64> {
+        match self.0.get(name)? {
+            ParameterValue::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn number(&self, name: &str) -> Option<f64> {
+        match self.0.get(name)? {
+            ParameterValue::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn boolean(&self, name: &str) -> Option<bool> {
+        match self.0.get(name)? {
+            ParameterValue::Boolean(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn text(&self, name: &str) -> Option<&str> {
+        match self.0.get(name)? {
+            ParameterValue::Text(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * Builds an agent for a search of the board from its parameters. Limits given with the search
+ * (e.g. by a `go` command) win over the parameters, which apply where the search sets no limit.
+ */
+pub type BuildAgent = fn(&AgentParameters, &GameBoard, GoLimits) -> Result<EngineAgent, String>;
+
+/**
+ * An agent the registry can build: its name, what it does, the parameters it accepts and how to build it.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct AgentDefinition {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub parameters: &'static [Parameter],
+    pub build: BuildAgent,
+    /// Whether building the agent can fail, e.g. because it starts a program. Factories build such an agent once up front
+    /// so that a bad description is reported when the factory is made rather than on a search thread.
+    pub fallible: bool,
+}
+
+/**
+ * Why an agent description was rejected.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// The description could not be read or parsed.
+    Parse(String),
+    UnknownAgent(String),
+    UnknownParameter { agent: String, parameter: String },
+    InvalidValue { parameter: String, expected: ParameterKind },
+    /// Building the agent failed, e.g. an external engine did not start.
+    Build(String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Parse(error) => write!(f, "{error}"),
+            ConfigError::UnknownAgent(agent) => write!(f, "unknown agent {agent}"),
+            ConfigError::UnknownParameter { agent, parameter } => write!(f, "{agent} has no parameter {parameter}"),
+            ConfigError::InvalidValue { parameter, expected } => write!(f, "{parameter} expects {expected}"),
+            ConfigError::Build(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/**
+ * An agent and its parameters as written, before they are checked against a registry.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentConfig {
+    pub agent: String,
+    pub parameters: BTreeMap<String, Value>,
+}
+
+impl AgentConfig {
+    /**
+     * An agent with its default parameters.
+     */
+    pub fn new(agent: &str) -> Self {
+        Self { agent: agent.to_string(), parameters: BTreeMap::new() }
+    }
+
+    pub fn with_parameter(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.parameters.insert(name.to_string(), value.into());
+        self
+    }
+
+    /**
+     * Reads an object with the agent's name under `agent` and the parameters beside it.
+     */
+    fn from_value(value: Value) -> Result<Self, ConfigError> {
+        let Value::Object(mut object) = value else {
+            return Err(ConfigError::Parse("an agent is described by a table of parameters".to_string()))
+        };
+        match object.remove("agent") {
+            Some(Value::String(agent)) => Ok(Self { agent, parameters: object.into_iter().collect() }),
+            _ => Err(ConfigError::Parse("the description does not name the agent".to_string())),
+        }
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, ConfigError> {
+        Self::from_value(serde_json::from_str(text).map_err(|error| ConfigError::Parse(error.to_string()))?)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        Self::from_value(toml::from_str(text).map_err(|error| ConfigError::Parse(error.to_string()))?)
+    }
+
+    /**
+     * Reads a `.toml` or `.json` file.
+     */
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|error| ConfigError::Parse(format!("could not read {}: {error}", path.display())))?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Self::from_toml(&text),
+            Some("json") => Self::from_json(&text),
+            _ => Err(ConfigError::Parse(format!("{} is neither a .toml nor a .json file", path.display()))),
+        }
+    }
+
+    /**
+     * Reads a command line description: a `.toml` or `.json` file, or `<agent>[:<parameter>=<value>,...]`,
+     * e.g. `mcts:c=1.2,heavy=true`. Values that are not numbers or booleans are text.
+     * `external:<program>[,<argument>...]` starts an external engine.
+     */
+    pub fn from_spec(spec: &str) -> Result<Self, ConfigError> {
+        if spec.ends_with(".toml") || spec.ends_with(".json") {
+            return Self::load(spec)
+        }
+        let (agent, parameters) = spec.split_once(':').unwrap_or((spec, ""));
+        if agent == "external" && !parameters.contains('=') {
+            let (command, args) = parameters.split_once(',').unwrap_or((parameters, ""));
+            let config = Self::new(agent).with_parameter("command", command);
+            return Ok(if args.is_empty() {config} else {config.with_parameter("args", args)})
+        }
+        let mut config = Self::new(agent);
+        for parameter in parameters.split(',').filter(|parameter| !parameter.is_empty()) {
+            let Some((name, value)) = parameter.split_once('=') else {
+                return Err(ConfigError::Parse(format!("expected <parameter>=<value> instead of {parameter}")))
+            };
+            let value = serde_json::from_str::<Value>(value).ok()
+                .filter(|value| value.is_number() || value.is_boolean())
+                .unwrap_or_else(|| Value::String(value.to_string()));
+            config.parameters.insert(name.to_string(), value);
+        }
+        Ok(config)
+    }
+}
+
+/**
+ * The agents that can be built by name. The standard registry holds `random`, `mcts`, `alphabeta` and `external`;
+ * `register` adds others, which the command line, matches and servers then accept like the built-in ones.
+ */
+#[derive(Clone, Default)]
+pub struct AgentRegistry {
+    agents: Vec<AgentDefinition>,
+}
+
+impl AgentRegistry {
+    pub fn standard() -> Self {
+        let mut registry = Self::default();
+        registry.register(RANDOM);
+        registry.register(MCTS);
+        registry.register(ALPHA_BETA);
+        registry.register(EXTERNAL);
+        registry
+    }
+
+    /**
+     * Adds an agent, replacing any agent of the same name.
+     */
+    pub fn register(&mut self, definition: AgentDefinition) {
+        self.agents.retain(|agent| agent.name != definition.name);
+        self.agents.push(definition);
+    }
+
+    pub fn definitions(&self) -> &[AgentDefinition] {
+        &self.agents
+    }
+
+    pub fn get(&self, name: &str) -> Option<&AgentDefinition> {
+        self.agents.iter().find(|agent| agent.name == name)
+    }
+
+    /**
+     * Checks the agent and its parameters against the registry. Integers are accepted for numbers.
+     */
+    pub fn validate(&self, config: &AgentConfig) -> Result<(AgentDefinition, AgentParameters), ConfigError> {
+        let definition = *self.get(&config.agent).ok_or_else(|| ConfigError::UnknownAgent(config.agent.clone()))?;
+        let mut parameters = AgentParameters::default();
+        for (name, value) in &config.parameters {
+            let parameter = definition.parameters.iter()
+                .find(|parameter| parameter.name == name)
+                .ok_or_else(|| ConfigError::UnknownParameter { agent: config.agent.clone(), parameter: name.clone() })?;
+            let checked = match (parameter.kind, value) {
+                (ParameterKind::Integer, Value::Number(number)) => number.as_u64().map(ParameterValue::Integer),
+                (ParameterKind::Number, Value::Number(number)) => number.as_f64().map(ParameterValue::Number),
+                (ParameterKind::Boolean, Value::Bool(value)) => Some(ParameterValue::Boolean(*value)),
+                (ParameterKind::Text, Value::String(value)) => Some(ParameterValue::Text(value.clone())),
+                _ => None,
+            };
+            let checked = checked.ok_or_else(|| ConfigError::InvalidValue { parameter: name.clone(), expected: parameter.kind })?;
+            parameters.0.insert(name.clone(), checked);
+        }
+        Ok((definition, parameters))
+    }
+
+    /**
+     * Builds the described agent for searching the board.
+     */
+    pub fn build(&self, config: &AgentConfig, board: &GameBoard, limits: GoLimits) -> Result<EngineAgent, ConfigError> {
+        let (definition, parameters) = self.validate(config)?;
+        (definition.build)(&parameters, board, limits).map_err(ConfigError::Build)
+    }
+
+    /**
+     * A factory building the described agent on search threads, for engines, servers and self-play.
+     * The description is checked once here, and agents whose building can fail are built once to check it works.
+     * Every search gets an agent of its own, so an external engine is started for each search.
+     * Building can still fail for a search, e.g. because the engine program was removed since; the factory
+     * then returns the error for the caller to report.
+     */
+    pub fn factory(&self, config: &AgentConfig) -> Result<AgentFactory, ConfigError> {
+        let (definition, parameters) = self.validate(config)?;
+        if definition.fallible {
+            (definition.build)(&parameters, &GameBoard::default(), GoLimits::default()).map_err(ConfigError::Build)?;
+        }
+        Ok(Arc::new(move |board, limits| (definition.build)(&parameters, board, limits).map_err(ConfigError::Build)))
+    }
+}
+
+/// The time a search may take: the search's limit, else the `time` parameter in milliseconds, else a second.
+fn search_time(parameters: &AgentParameters, limits: GoLimits) -> Duration {
+    limits.time.or(parameters.integer("time").map(Duration::from_millis)).unwrap_or(Duration::from_secs(1))
+}
+
+pub const RANDOM: AgentDefinition = AgentDefinition {
+    name: "random",
+    description: "uniformly random moves",
+    parameters: &[],
+    build: |_, _, _| Ok(EngineAgent::Plain(Box::new(RandomAgent::default()))),
+    fallible: false,
+};
+
+pub const MCTS: AgentDefinition = AgentDefinition {
+    name: "mcts",
+    description: "Monte Carlo tree search",
+    parameters: &[
+        Parameter { name: "time", kind: ParameterKind::Integer, description: "milliseconds per move" },
+        Parameter { name: "c", kind: ParameterKind::Number, description: "the exploration constant of UCB1, √2 by default" },
+        Parameter { name: "playouts", kind: ParameterKind::Integer, description: "the most playouts per move" },
+        Parameter { name: "heavy", kind: ParameterKind::Boolean, description: "playouts take immediate wins" },
+        Parameter { name: "tree_dump", kind: ParameterKind::Text, description: "a directory to write the search tree to after each move" },
+        Parameter { name: "tree_name", kind: ParameterKind::Text, description: "the file name prefix of the search trees, mcts by default" },
+        Parameter { name: "tree_depth", kind: ParameterKind::Integer, description: "how many moves deep search trees are written" },
+        Parameter { name: "tree_min_visits", kind: ParameterKind::Integer, description: "the fewest visits of a node in a written search tree" },
+    ],
+    build: |parameters, board, limits| {
+        let mut search = MonteCarloTreeSearch::new(board, search_time(parameters, limits))
+            .with_heavy_playouts(parameters.boolean("heavy").unwrap_or(false));
+        if let Some(exploration) = parameters.number("c") {
+            search = search.with_exploration(exploration);
+        }
+        if let Some(playouts) = parameters.integer("playouts") {
+            search = search.with_max_playouts(playouts as usize);
+        }
+        if let Some(directory) = parameters.text("tree_dump") {
+            let defaults = TreeExportLimits::default();
+            search = search.with_tree_dump(TreeDump {
+                directory: PathBuf::from(directory),
+                name: parameters.text("tree_name").unwrap_or("mcts").to_string(),
+                limits: TreeExportLimits {
+                    max_depth: parameters.integer("tree_depth").map_or(defaults.max_depth, |depth| depth as usize),
+                    min_visits: parameters.integer("tree_min_visits").map_or(defaults.min_visits, |visits| visits as usize),
+                },
+            });
+        }
+        Ok(EngineAgent::Analyzing(Box::new(search)))
+    },
+    fallible: false,
+};
+
+pub const ALPHA_BETA: AgentDefinition = AgentDefinition {
+    name: "alphabeta",
+    description: "depth-limited alpha-beta search",
+    parameters: &[
+        Parameter { name: "depth", kind: ParameterKind::Integer, description: "the search depth in plies, 2 by default" },
+    ],
+    build: |parameters, _, limits| {
+        let depth = limits.depth.or(parameters.integer("depth").map(|depth| depth as usize)).unwrap_or(2);
+        Ok(EngineAgent::Analyzing(Box::new(MinimaxAgent::new(depth))))
+    },
+    fallible: false,
+};
+
+pub const EXTERNAL: AgentDefinition = AgentDefinition {
+    name: "external",
+    description: "an engine program speaking the engine protocol",
+    parameters: &[
+        Parameter { name: "command", kind: ParameterKind::Text, description: "the program to start" },
+        Parameter { name: "args", kind: ParameterKind::Text, description: "its arguments, separated by commas" },
+        Parameter { name: "time", kind: ParameterKind::Integer, description: "milliseconds per move" },
+    ],
+    build: |parameters, _, limits| {
+        let program = parameters.text("command").ok_or("external needs a command")?;
+        let args: Vec<String> = parameters.text("args").unwrap_or_default()
+            .split(',')
+            .filter(|arg| !arg.is_empty())
+            .map(str::to_string)
+            .collect();
+        ExternalEngineAgent::spawn(program, &args, search_time(parameters, limits))
+            .map(|agent| EngineAgent::Plain(Box::new(agent)))
+            .map_err(|error| format!("could not start {program}: {error}"))
+    },
+    fallible: true,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::agent::Agent;
+
+    #[test]
+    fn descriptions_are_read_and_validated() {
+        let toml = "agent = \"mcts\"\nc = 1.2\nplayouts = 5000\nheavy = true\n";
+        let json = r#"{"agent": "mcts", "c": 1.2, "playouts": 5000, "heavy": true}"#;
+        let spec = "mcts:c=1.2,playouts=5000,heavy=true";
+        let config = AgentConfig::from_toml(toml).unwrap();
+        assert_eq!(AgentConfig::from_json(json).unwrap(), config);
+        assert_eq!(AgentConfig::from_spec(spec).unwrap(), config);
+
+        let registry = AgentRegistry::standard();
+        let (definition, parameters) = registry.validate(&config).unwrap();
+        assert_eq!(definition.name, "mcts");
+        assert_eq!((parameters.number("c"), parameters.integer("playouts"), parameters.boolean("heavy")), (Some(1.2), Some(5000), Some(true)));
+
+        let unknown = AgentConfig::from_spec("mcts:playouts=10,depth=3").unwrap();
+        assert_eq!(registry.validate(&unknown).unwrap_err(), ConfigError::UnknownParameter { agent: "mcts".to_string(), parameter: "depth".to_string() });
+        let invalid = AgentConfig::from_spec("alphabeta:depth=deep").unwrap();
+        assert_eq!(registry.validate(&invalid).unwrap_err(), ConfigError::InvalidValue { parameter: "depth".to_string(), expected: ParameterKind::Integer });
+        assert_eq!(registry.validate(&AgentConfig::new("stockfish")).unwrap_err(), ConfigError::UnknownAgent("stockfish".to_string()));
+        assert!(AgentConfig::from_toml("c = 1.2").is_err());
+        assert!(matches!(registry.factory(&AgentConfig::from_spec("external:no-such-engine").unwrap()), Err(ConfigError::Build(_))));
+
+        let factory = registry.factory(&AgentConfig::from_spec("mcts:playouts=50").unwrap()).unwrap();
+        let board = GameBoard::default();
+        let turn_move = factory(&board, GoLimits { time: Some(Duration::from_secs(60)), depth: None }).unwrap().into_agent().get_move(&board);
+        assert!(board.actions().contains(&turn_move));
+    }
+
+    #[test]
+    fn factories_report_agents_failing_to_build() {
+        let mut registry = AgentRegistry::standard();
+        registry.register(AgentDefinition {
+            name: "shallow",
+            description: "random moves, for searches without a depth limit",
+            parameters: &[],
+            build: |_, _, limits| match limits.depth {
+                None => Ok(EngineAgent::Plain(Box::new(RandomAgent::default()))),
+                Some(_) => Err("shallow searches have no depth".to_string()),
+            },
+            fallible: true,
+        });
+        //the trial build has no limits and succeeds, a later search fails
+        let factory = registry.factory(&AgentConfig::new("shallow")).unwrap();
+        let board = GameBoard::default();
+        assert!(factory(&board, GoLimits::default()).is_ok());
+        let error = factory(&board, GoLimits { time: None, depth: Some(3) }).err().unwrap();
+        assert_eq!(error, ConfigError::Build("shallow searches have no depth".to_string()));
+    }
+
+    #[test]
+    fn search_limits_win_over_parameters() {
+        let registry = AgentRegistry::standard();
+        let (_, parameters) = registry.validate(&AgentConfig::from_spec("mcts:time=500").unwrap()).unwrap();
+        let limits = GoLimits { time: Some(Duration::from_millis(20)), depth: None };
+        assert_eq!(search_time(&parameters, limits), Duration::from_millis(20));
+        assert_eq!(search_time(&parameters, GoLimits::default()), Duration::from_millis(500));
+        assert_eq!(search_time(&AgentParameters::default(), GoLimits::default()), Duration::from_secs(1));
+    }
+}
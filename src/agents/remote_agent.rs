@@ -0,0 +1,164 @@
+use std::{io::{BufRead, BufReader, Write}, net::{TcpStream, ToSocketAddrs}, time::Duration};
+
+use crate::{agents::agent::Agent, network::{ClientMessage, Role, ServerMessage}, neutrino_board::{GameBoard, Player, TurnMove}, outcome::Outcome, rules::RuleSet};
+
+/**
+ * The opponent in a game hosted by a server (see `network`).
+ * The agent joins the game as the local side; every time it is asked for a move it submits
+ * the local side's move that led to the given board and waits for the opponent's answer.
+ * It forfeits once the server ends the game or the connection drops.
+ */
+pub struct RemoteAgent {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    local: Player,
+    /// The board as the server has reported it.
+    board: GameBoard,
+    clocks: Option<(Duration, Duration)>,
+    result: Option<Outcome>,
+}
+
+impl RemoteAgent {
+    /**
+     * Connects to a server and joins the game as `local`, the side played on this end.
+     */
+    pub fn connect(address: impl ToSocketAddrs, game: &str, local: Player) -> Result<Self, String> {
+        let writer = TcpStream::connect(address).map_err(|error| format!("could not connect: {error}"))?;
+        let reader = BufReader::new(writer.try_clone().map_err(|error| error.to_string())?);
+        let mut agent = Self { reader, writer, local, board: GameBoard::default(), clocks: None, result: None };
+        agent.send(&ClientMessage::Join(game.to_string(), Role::Player(local)))?;
+        //the server answers with the start position right after joining
+        loop {
+            match agent.receive()? {
+                ServerMessage::Position(board) => {
+                    agent.board = *board;
+                    return Ok(agent)
+                },
+                ServerMessage::Error(error) => return Err(error),
+                _ => {},
+            }
+        }
+    }
+
+    /**
+     * The rules the server hosts the game by.
+     */
+    pub fn rules(&self) -> RuleSet {
+        self.board.rules()
+    }
+
+    /**
+     * The clocks of Player1 and Player2 as last reported by the server.
+     */
+    pub fn clocks(&self) -> Option<(Duration, Duration)> {
+        self.clocks
+    }
+
+    fn send(&mut self, message: &ClientMessage) -> Result<(), String> {
+        writeln!(self.writer, "{message}").map_err(|error| format!("connection lost: {error}"))
+    }
+
+    /**
+     * The next message from the server. Lines that are not protocol messages are skipped.
+     */
+    fn receive(&mut self) -> Result<ServerMessage, String> {
+        loop {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return Err("the server closed the connection".to_string()),
+                Err(error) => return Err(format!("connection lost: {error}")),
+                Ok(_) => if let Ok(message) = line.parse() {
+                    return Ok(message)
+                },
+            }
+        }
+    }
+
+    /**
+     * Sends the local side's move that led from the server's board to `board`, if there is one.
+     */
+    fn submit_local_move(&mut self, board: &GameBoard) -> Result<(), String> {
+        if board.hash() == self.board.hash() {
+            return Ok(())
+        }
+        let local_move = self.board.actions().into_iter()
+            .find(|turn_move| self.board.result(turn_move.clone()).hash() == board.hash())
+            .ok_or("the board does not follow from the server's position")?;
+        self.send(&ClientMessage::Move(local_move))
+    }
+
+    /**
+     * Submits the local side's move that ended the game, or resigns if the game ended without one,
+     * and waits for the server to confirm the result.
+     */
+    pub fn finish(&mut self, board: &GameBoard) -> Result<Outcome, String> {
+        if self.result.is_none() {
+            match board.is_terminal() {
+                true => self.submit_local_move(board)?,
+                false => self.send(&ClientMessage::Resign)?,
+            }
+        }
+        while self.result.is_none() {
+            match self.receive()? {
+                ServerMessage::Clock(player1, player2) => self.clocks = Some((player1, player2)),
+                ServerMessage::GameOver(outcome) => self.result = Some(outcome),
+                ServerMessage::Error(error) => return Err(error),
+                _ => {},
+            }
+        }
+        Ok(self.result.expect("the loop ends with a result"))
+    }
+
+    fn request_move(&mut self, board: &GameBoard) -> Result<TurnMove, String> {
+        if let Some(outcome) = self.result {
+            return Err(format!("the game is over: {outcome}"))
+        }
+        self.submit_local_move(board)?;
+        loop {
+            match self.receive()? {
+                ServerMessage::Move(turn_move) => {
+                    self.board.play(&turn_move)
+                        .map_err(|error| format!("the server sent an illegal move {}: {error}", turn_move.to_notation()))?;
+                    //the echo of the local move leaves the opponent to move
+                    if self.board.to_move() == self.local {
+                        return Ok(turn_move)
+                    }
+                },
+                ServerMessage::Clock(player1, player2) => self.clocks = Some((player1, player2)),
+                ServerMessage::GameOver(outcome) => {
+                    self.result = Some(outcome);
+                    return Err(format!("the game is over: {outcome}"))
+                },
+                ServerMessage::Error(error) => return Err(error),
+                _ => {},
+            }
+        }
+    }
+}
+
+impl Agent for RemoteAgent {
+    fn get_move(&mut self, board: &GameBoard) -> TurnMove {
+        self.request_move(board).unwrap_or_else(|error| panic!("the remote player forfeited: {error}"))
+    }
+
+    fn try_get_move(&mut self, board: &GameBoard) -> Option<TurnMove> {
+        match self.request_move(board) {
+            Ok(turn_move) => Some(turn_move),
+            Err(error) => {
+                eprintln!("the remote player forfeits: {error}");
+                None
+            }
+        }
+    }
+}
+
+impl Drop for RemoteAgent {
+    /**
+     * Leaving a running game resigns it.
+     */
+    fn drop(&mut self) {
+        if self.result.is_none() {
+            let _ = self.send(&ClientMessage::Resign);
+        }
+    }
+}
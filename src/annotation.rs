@@ -0,0 +1,236 @@
+use std::fmt::Display;
+
+use crate::{agents::{analysis::Analyze, minimax::MinimaxAgent}, game_record::GameRecord, neutrino_board::{GameBoard, Player, TurnMove}};
+
+/**
+ * How much a move changed the evaluation for the player who made it.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveQuality {
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+    /// The player had a forced win and played a move that does not keep it.
+    MissedWin,
+}
+
+impl MoveQuality {
+    fn glyph(self) -> Option<&'static str> {
+        match self {
+            MoveQuality::Good => None,
+            MoveQuality::Inaccuracy => Some("?!"),
+            MoveQuality::Mistake => Some("?"),
+            MoveQuality::Blunder | MoveQuality::MissedWin => Some("??"),
+        }
+    }
+}
+
+impl Display for MoveQuality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            MoveQuality::Good => "good",
+            MoveQuality::Inaccuracy => "inaccuracy",
+            MoveQuality::Mistake => "mistake",
+            MoveQuality::Blunder => "blunder",
+            MoveQuality::MissedWin => "missed forced win",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/**
+ * The minimum drop in score (between 0 and 1) for each classification.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct Thresholds {
+    pub inaccuracy: f64,
+    pub mistake: f64,
+    pub blunder: f64,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self { inaccuracy: 0.1, mistake: 0.2, blunder: 0.35 }
+    }
+}
+
+/**
+ * The verdict on one move of a game. Scores are for the player who made the move.
+ */
+#[derive(Debug, Clone)]
+pub struct MoveAnnotation {
+    pub ply: usize,
+    pub played: TurnMove,
+    pub quality: MoveQuality,
+    pub score_before: f64,
+    pub score_after: f64,
+    /// The engine's preferred move, if it differs from the played one.
+    pub suggestion: Option<TurnMove>,
+}
+
+/**
+ * Replays a game and analyses every position with an engine to find the moves where a side went wrong.
+ * The engine's budget (time or depth) is whatever it was constructed with.
+ */
+pub struct Annotator<A: Analyze> {
+    engine: A,
+    thresholds: Thresholds,
+    /// Depth of the exhaustive search used to prove forced wins.
+    win_search_depth: usize,
+}
+
+impl<A: Analyze> Annotator<A> {
+    pub fn new(engine: A, thresholds: Thresholds, win_search_depth: usize) -> Self {
+        Self { engine, thresholds, win_search_depth }
+    }
+
+    /**
+     * Classifies every move of the record.
+     */
+    pub fn annotate(&mut self, record: &GameRecord) -> Vec<MoveAnnotation> {
+        let positions = record.positions();
+        //score of each position and the engine's best move, for the player to move
+        let evaluations: Vec<(f64, Option<TurnMove>)> = positions.iter().map(|board| self.evaluate(board)).collect();
+
+        record.moves.iter().enumerate().map(|(ply, played)| {
+            let board = &positions[ply];
+            let (score_before, best_move) = evaluations[ply].clone();
+            let next_board = &positions[ply + 1];
+            let score_after = next_board
+                .utility(board.to_move())
+                .unwrap_or_else(|| 1.0 - evaluations[ply + 1].0);
+            let quality = self.classify(board, next_board, score_before, score_after);
+            let suggestion = best_move.filter(|best_move| best_move != played);
+            MoveAnnotation { ply, played: played.clone(), quality, score_before, score_after, suggestion }
+        }).collect()
+    }
+
+    /**
+     * A copy of the record with glyphs and comments for every move that is not `Good`.
+     */
+    pub fn annotated_record(&mut self, record: &GameRecord) -> (GameRecord, Vec<MoveAnnotation>) {
+        let annotations = self.annotate(record);
+        let mut annotated = record.clone();
+        for annotation in annotations.iter().filter(|annotation| annotation.quality != MoveQuality::Good) {
+            if let Some(glyph) = annotation.quality.glyph() {
+                annotated.glyphs.insert(annotation.ply, glyph.to_string());
+            }
+            let mut comment = format!("{} ({:.2} -> {:.2})", annotation.quality, annotation.score_before, annotation.score_after);
+            if let Some(suggestion) = &annotation.suggestion {
+                comment.push_str(&format!(", better {}", suggestion.to_notation()));
+            }
+            annotated.comments.insert(annotation.ply, comment);
+        }
+        annotated.set_tag("Annotator", "neutrino");
+        (annotated, annotations)
+    }
+
+    fn evaluate(&mut self, board: &GameBoard) -> (f64, Option<TurnMove>) {
+        if let Some(utility) = board.utility(board.to_move()) {
+            return (utility, None)
+        }
+        let analysis = self.engine.analyze(board, 1, &mut |_| {});
+        let line = analysis.lines.first().expect("Non-terminal boards have moves");
+        (line.score, Some(line.turn_move.clone()))
+    }
+
+    fn classify(&self, board: &GameBoard, next_board: &GameBoard, score_before: f64, score_after: f64) -> MoveQuality {
+        if self.has_forced_win(board) && !self.keeps_forced_win(next_board, board.to_move()) {
+            return MoveQuality::MissedWin
+        }
+        let loss = score_before - score_after;
+        if loss >= self.thresholds.blunder {
+            MoveQuality::Blunder
+        } else if loss >= self.thresholds.mistake {
+            MoveQuality::Mistake
+        } else if loss >= self.thresholds.inaccuracy {
+            MoveQuality::Inaccuracy
+        } else {
+            MoveQuality::Good
+        }
+    }
+
+    /**
+     * Whether the player to move can force a win within the win search depth.
+     * Heuristic scores are always below 1, so a score of 1 is a proof.
+     */
+    fn has_forced_win(&self, board: &GameBoard) -> bool {
+        if self.win_search_depth == 0 {
+            return false
+        }
+        let analysis = MinimaxAgent::new(self.win_search_depth).analyze(board, 1, &mut |_| {});
+        analysis.lines.first().is_some_and(|line| line.score >= 1.0)
+    }
+
+    /**
+     * Whether `player`, who just moved, can still force a win on the next board.
+     */
+    fn keeps_forced_win(&self, next_board: &GameBoard, player: Player) -> bool {
+        if let Some(utility) = next_board.utility(player) {
+            return utility == 1.0
+        }
+        if self.win_search_depth <= 1 {
+            return false
+        }
+        let analysis = MinimaxAgent::new(self.win_search_depth - 1).analyze(next_board, 1, &mut |_| {});
+        analysis.lines.first().is_some_and(|line| line.score <= 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turn(notation: &str) -> TurnMove {
+        notation.parse().unwrap()
+    }
+
+    #[test]
+    fn moves_are_classified_by_the_score_they_lose() {
+        let annotator = Annotator::new(MinimaxAgent::new(1), Thresholds::default(), 1);
+        let board = GameBoard::default();
+        let next_board = board.result(turn("a1a4"));
+        for (score_before, score_after, quality) in [
+            (0.5, 0.6, MoveQuality::Good),
+            (0.5, 0.45, MoveQuality::Good),
+            (0.5, 0.35, MoveQuality::Inaccuracy),
+            (0.5, 0.25, MoveQuality::Mistake),
+            (0.5, 0.1, MoveQuality::Blunder),
+            (1.0, 0.0, MoveQuality::Blunder),
+        ] {
+            assert_eq!(annotator.classify(&board, &next_board, score_before, score_after), quality, "{score_before} -> {score_after}");
+        }
+        //a loss equal to a threshold reaches it
+        let strict = Annotator::new(MinimaxAgent::new(1), Thresholds { inaccuracy: 0.25, mistake: 0.5, blunder: 0.75 }, 1);
+        for (score_after, quality) in [(0.875, MoveQuality::Good), (0.75, MoveQuality::Inaccuracy), (0.5, MoveQuality::Mistake), (0.25, MoveQuality::Blunder)] {
+            assert_eq!(strict.classify(&board, &next_board, 1.0, score_after), quality, "1 -> {score_after}");
+        }
+
+        //Player1 wins at once with c3c1 and any other move misses it, however little it loses
+        let board: GameBoard = "11.11/...../..X../...../22222 1".parse().unwrap();
+        let winning = board.result(turn("c3c1/a1a4"));
+        let other = board.result(turn("c3a3/a1a2"));
+        assert_eq!(annotator.classify(&board, &winning, 1.0, 1.0), MoveQuality::Good);
+        assert_eq!(annotator.classify(&board, &other, 1.0, 0.95), MoveQuality::MissedWin);
+        //without the win search only the score counts
+        let annotator = Annotator::new(MinimaxAgent::new(1), Thresholds::default(), 0);
+        assert_eq!(annotator.classify(&board, &other, 1.0, 0.95), MoveQuality::Good);
+    }
+
+    #[test]
+    fn records_are_annotated_with_glyphs_and_comments() {
+        let mut record = GameRecord::new();
+        record.moves = ["a1a4", "c3c4/b5b2", "c4b5/b1a1"].map(turn).to_vec();
+        let (annotated, annotations) = Annotator::new(MinimaxAgent::new(2), Thresholds::default(), 2).annotated_record(&record);
+        //Player1 moves the neutrino onto Player2's home row
+        let qualities: Vec<MoveQuality> = annotations.iter().map(|annotation| annotation.quality).collect();
+        assert_eq!(qualities, [MoveQuality::Good, MoveQuality::Good, MoveQuality::Blunder]);
+        assert_eq!((annotations[2].score_after, annotations[2].suggestion.as_ref()), (0.0, Some(&turn("c4e4/d1d4"))));
+        assert_eq!(annotated.moves, record.moves);
+        assert_eq!(annotated.glyphs.iter().collect::<Vec<_>>(), [(&2, &"??".to_string())]);
+        assert_eq!(annotated.comments.iter().collect::<Vec<_>>(), [(&2, &"blunder (0.55 -> 0.00), better c4e4/d1d4".to_string())]);
+        assert!(annotated.to_string().contains("[Annotator \"neutrino\"]"));
+        assert!(annotated.to_string().contains("c4b5/b1a1?? {blunder (0.55 -> 0.00), better c4e4/d1d4}"));
+    }
+}
@@ -0,0 +1,551 @@
+use std::{fmt::Display, io::{BufRead, Write}, str::FromStr, sync::{Arc, Mutex}, thread::{self, JoinHandle}, time::Duration};
+
+use crate::{agents::{agent::{Agent, StopHandle}, analysis::{Analysis, Analyze}, registry::ConfigError}, neutrino_board::{GameBoard, TurnMove}, notation::ParseNotationError, rules::RuleSet};
+
+/*
+ * A line-oriented protocol for driving an engine as a subprocess, modelled on UCI.
+ *
+ * Commands sent to the engine:
+ *   neutrino                              identify; the engine answers with `id` lines and `neutrinook`
+ *   isready                               the engine answers `readyok`
+ *   newgame                               forget the current game
+ *   position [rules <rules>] startpos|<position> [moves <move>...]   standard rules unless given
+ *   go [time <ms>] [depth <n>]            search the current position
+ *   stop                                  answer `bestmove` now with the best move found so far
+ *   quit
+ *
+ * Messages sent by the engine:
+ *   id name <name>
+ *   neutrinook
+ *   readyok
+ *   info [multipv <n>] [depth <n>] [nodes <n>] [time <ms>] [score <0..1>] [visits <n>] [pv <move>...]
+ *   info string <text>
+ *   bestmove <move>|none                  none when the game is over or the agent could not be built
+ */
+
+/**
+ * Search limits given with `go`.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GoLimits {
+    pub time: Option<Duration>,
+    pub depth: Option<usize>,
+}
+
+impl GoLimits {
+    /**
+     * These limits, with `defaults` for the ones not set.
+     */
+    pub fn or(self, defaults: GoLimits) -> Self {
+        Self { time: self.time.or(defaults.time), depth: self.depth.or(defaults.depth) }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Neutrino,
+    IsReady,
+    NewGame,
+    Position(Box<GameBoard>),
+    Go(GoLimits),
+    Stop,
+    Quit,
+}
+
+/**
+ * Parses a position and the moves played from it, e.g. `startpos moves a1a2 c3c4/e5e4`.
+ */
+fn parse_position(tokens: &[&str]) -> Result<GameBoard, ParseNotationError> {
+    let (rules, tokens) = match tokens {
+        ["rules", rules, rest @ ..] => (rules.parse::<RuleSet>()?, rest),
+        _ => (RuleSet::default(), tokens),
+    };
+    let (mut board, moves) = match tokens {
+        ["startpos", rest @ ..] => (GameBoard::start(rules), rest),
+        [rows, to_move, rest @ ..] => (format!("{rows} {to_move}").parse::<GameBoard>()?.with_rules(rules), rest),
+        _ => return Err(ParseNotationError::new(&tokens.join(" "), "expected startpos or a position")),
+    };
+    let moves = match moves {
+        [] => moves,
+        ["moves", moves @ ..] => moves,
+        _ => return Err(ParseNotationError::new(&moves.join(" "), "expected moves")),
+    };
+    for turn_move in moves {
+        let parsed: TurnMove = turn_move.parse()?;
+        board.play(&parsed).map_err(|_| ParseNotationError::new(turn_move, "illegal move"))?;
+    }
+    Ok(board)
+}
+
+impl FromStr for Command {
+    type Err = ParseNotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens: Vec<&str> = s.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["neutrino"] => Ok(Command::Neutrino),
+            ["isready"] => Ok(Command::IsReady),
+            ["newgame"] => Ok(Command::NewGame),
+            ["position", position @ ..] => Ok(Command::Position(Box::new(parse_position(position)?))),
+            ["go", limits @ ..] => {
+                let mut go_limits = GoLimits::default();
+                for pair in limits.chunks(2) {
+                    let [key, value] = pair else {
+                        return Err(ParseNotationError::new(s, "expected go [time <ms>] [depth <n>]"))
+                    };
+                    let value: u64 = value.parse().map_err(|_| ParseNotationError::new(s, "expected a number"))?;
+                    match *key {
+                        "time" => go_limits.time = Some(Duration::from_millis(value)),
+                        "depth" => go_limits.depth = Some(value as usize),
+                        _ => return Err(ParseNotationError::new(s, "unknown go limit")),
+                    }
+                }
+                Ok(Command::Go(go_limits))
+            },
+            ["stop"] => Ok(Command::Stop),
+            ["quit"] => Ok(Command::Quit),
+            _ => Err(ParseNotationError::new(s, "unknown command")),
+        }
+    }
+}
+
+/**
+ * One `info` line: statistics of a search and optionally one of its principal variations.
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InfoLine {
+    pub multi_pv: Option<usize>,
+    pub depth: Option<usize>,
+    pub nodes: Option<usize>,
+    pub time: Option<Duration>,
+    pub score: Option<f64>,
+    pub visits: Option<usize>,
+    pub pv: Vec<TurnMove>,
+    pub string: Option<String>,
+}
+
+impl InfoLine {
+    /**
+     * One info line per principal variation of the analysis.
+     */
+    pub fn from_analysis(analysis: &Analysis) -> Vec<InfoLine> {
+        analysis.lines.iter().enumerate().map(|(index, line)| InfoLine {
+            multi_pv: Some(index + 1),
+            depth: Some(analysis.depth),
+            nodes: Some(analysis.nodes),
+            time: Some(analysis.elapsed),
+            score: Some(line.score),
+            visits: Some(line.visits),
+            pv: line.pv.clone(),
+            string: None,
+        }).collect()
+    }
+
+    pub fn string(text: &str) -> Self {
+        Self { string: Some(text.to_string()), ..Default::default() }
+    }
+}
+
+impl Display for InfoLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "info")?;
+        if let Some(string) = &self.string {
+            return write!(f, " string {string}")
+        }
+        if let Some(multi_pv) = self.multi_pv {
+            write!(f, " multipv {multi_pv}")?;
+        }
+        if let Some(depth) = self.depth {
+            write!(f, " depth {depth}")?;
+        }
+        if let Some(nodes) = self.nodes {
+            write!(f, " nodes {nodes}")?;
+        }
+        if let Some(time) = self.time {
+            write!(f, " time {}", time.as_millis())?;
+        }
+        if let Some(score) = self.score {
+            write!(f, " score {score:.4}")?;
+        }
+        if let Some(visits) = self.visits {
+            write!(f, " visits {visits}")?;
+        }
+        if !self.pv.is_empty() {
+            let pv: Vec<String> = self.pv.iter().map(TurnMove::to_notation).collect();
+            write!(f, " pv {}", pv.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for InfoLine {
+    type Err = ParseNotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s.strip_prefix("info").ok_or_else(|| ParseNotationError::new(s, "expected info"))?.trim();
+        if let Some(string) = rest.strip_prefix("string") {
+            return Ok(InfoLine::string(string.trim()))
+        }
+        let mut info = InfoLine::default();
+        let mut tokens = rest.split_whitespace();
+        let number = |value: Option<&str>| value
+            .and_then(|value| value.parse::<f64>().ok())
+            .ok_or_else(|| ParseNotationError::new(s, "expected a number"));
+        while let Some(key) = tokens.next() {
+            match key {
+                "multipv" => info.multi_pv = Some(number(tokens.next())? as usize),
+                "depth" => info.depth = Some(number(tokens.next())? as usize),
+                "nodes" => info.nodes = Some(number(tokens.next())? as usize),
+                "time" => info.time = Some(Duration::from_millis(number(tokens.next())? as u64)),
+                "score" => info.score = Some(number(tokens.next())?),
+                "visits" => info.visits = Some(number(tokens.next())? as usize),
+                "pv" => {
+                    info.pv = tokens.by_ref().map(str::parse).collect::<Result<_, _>>()?;
+                },
+                //unknown keys are skipped so that engines can send extra statistics
+                _ => {
+                    tokens.next();
+                },
+            }
+        }
+        Ok(info)
+    }
+}
+
+/**
+ * Messages the engine writes to its output.
+ */
+#[derive(Debug, Clone)]
+pub enum EngineMessage {
+    Id(String, String),
+    NeutrinoOk,
+    ReadyOk,
+    Info(InfoLine),
+    BestMove(Option<TurnMove>),
+}
+
+impl Display for EngineMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EngineMessage::Id(key, value) => write!(f, "id {key} {value}"),
+            EngineMessage::NeutrinoOk => write!(f, "neutrinook"),
+            EngineMessage::ReadyOk => write!(f, "readyok"),
+            EngineMessage::Info(info) => write!(f, "{info}"),
+            EngineMessage::BestMove(Some(turn_move)) => write!(f, "bestmove {}", turn_move.to_notation()),
+            EngineMessage::BestMove(None) => write!(f, "bestmove none"),
+        }
+    }
+}
+
+impl FromStr for EngineMessage {
+    type Err = ParseNotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.split_once(' ').unwrap_or((s, "")) {
+            ("id", rest) => {
+                let (key, value) = rest.split_once(' ').unwrap_or((rest, ""));
+                Ok(EngineMessage::Id(key.to_string(), value.to_string()))
+            },
+            ("neutrinook", _) => Ok(EngineMessage::NeutrinoOk),
+            ("readyok", _) => Ok(EngineMessage::ReadyOk),
+            ("info", _) => Ok(EngineMessage::Info(s.parse()?)),
+            ("bestmove", "none") => Ok(EngineMessage::BestMove(None)),
+            ("bestmove", turn_move) => Ok(EngineMessage::BestMove(Some(turn_move.trim().parse()?))),
+            _ => Err(ParseNotationError::new(s, "unknown engine message")),
+        }
+    }
+}
+
+/**
+ * The agent an engine searches with. Agents that can analyse also report `info` lines.
+ */
+pub enum EngineAgent {
+    Plain(Box<dyn Agent>),
+    Analyzing(Box<dyn Analyze>),
+}
+
+impl EngineAgent {
+    /**
+     * The agent for playing games, which need no analysis.
+     */
+    pub fn into_agent(self) -> Box<dyn Agent> {
+        match self {
+            EngineAgent::Plain(agent) => agent,
+            EngineAgent::Analyzing(agent) => agent,
+        }
+    }
+}
+
+/**
+ * Creates the agent for a `go` command from its limits, or says why it could not. It is called on the search thread.
+ */
+pub type AgentFactory = Arc<dyn Fn(&GameBoard, GoLimits) -> Result<EngineAgent, ConfigError> + Send + Sync>;
+
+/**
+ * A search running on its own thread. `best_move` holds the best move reported so far,
+ * and `answered` is set once `bestmove` has been sent, by the search or by `stop`.
+ * `stop_handle` ends the search early.
+ */
+struct RunningSearch {
+    best_move: Arc<Mutex<Option<TurnMove>>>,
+    answered: Arc<Mutex<bool>>,
+    stop_handle: StopHandle,
+    handle: JoinHandle<()>,
+}
+
+/**
+ * Serves the engine protocol on the given input and output until `quit` or the end of the input.
+ */
+pub fn run_engine<R: BufRead, W: Write + Send + 'static>(name: &str, input: R, output: W, factory: AgentFactory) {
+    let output = Arc::new(Mutex::new(output));
+    let mut board = GameBoard::default();
+    let mut search: Option<RunningSearch> = None;
+
+    for line in input.lines() {
+        let Ok(line) = line else { break };
+        if line.trim().is_empty() {
+            continue
+        }
+        let command = match line.parse::<Command>() {
+            Ok(command) => command,
+            Err(error) => {
+                send(&output, EngineMessage::Info(InfoLine::string(&error.to_string())));
+                continue
+            }
+        };
+        match command {
+            Command::Neutrino => {
+                send(&output, EngineMessage::Id("name".to_string(), name.to_string()));
+                send(&output, EngineMessage::NeutrinoOk);
+            },
+            Command::IsReady => send(&output, EngineMessage::ReadyOk),
+            Command::NewGame => board = GameBoard::default(),
+            Command::Position(new_board) => board = *new_board,
+            Command::Go(limits) => {
+                //a search still running is stopped and answers with its best move before the next one starts
+                if let Some(running) = search.take() {
+                    running.stop_handle.stop();
+                    let _ = running.handle.join();
+                }
+                if board.is_terminal() {
+                    send(&output, EngineMessage::BestMove(None));
+                    continue
+                }
+                search = Some(start_search(&board, limits, Arc::clone(&factory), Arc::clone(&output)));
+            },
+            Command::Stop => {
+                if let Some(running) = &search {
+                    running.stop_handle.stop();
+                    let mut answered = running.answered.lock().expect("search lock poisoned");
+                    if !*answered {
+                        //a search that has not reported anything yet answers with any legal move
+                        let best_move = running.best_move.lock().expect("search lock poisoned").clone()
+                            .or_else(|| board.actions().into_iter().next());
+                        send(&output, EngineMessage::BestMove(best_move));
+                        *answered = true;
+                    }
+                }
+            },
+            Command::Quit => break,
+        }
+    }
+    //a search still running is abandoned without answering
+    if let Some(running) = search {
+        *running.answered.lock().expect("search lock poisoned") = true;
+        running.stop_handle.stop();
+    }
+}
+
+fn send<W: Write>(output: &Mutex<W>, message: EngineMessage) {
+    let mut output = output.lock().expect("engine output lock poisoned");
+    let _ = writeln!(output, "{message}");
+    let _ = output.flush();
+}
+
+fn start_search<W: Write + Send + 'static>(board: &GameBoard, limits: GoLimits, factory: AgentFactory, output: Arc<Mutex<W>>) -> RunningSearch {
+    let best_move: Arc<Mutex<Option<TurnMove>>> = Arc::new(Mutex::new(None));
+    let answered = Arc::new(Mutex::new(false));
+    let board = board.clone();
+    let stop_handle = StopHandle::new();
+    let (thread_best_move, thread_answered, thread_stop_handle) = (Arc::clone(&best_move), Arc::clone(&answered), stop_handle.clone());
+    let handle = thread::spawn(move || {
+        let turn_move = match factory(&board, limits) {
+            Err(error) => {
+                send(&output, EngineMessage::Info(InfoLine::string(&error.to_string())));
+                None
+            },
+            Ok(EngineAgent::Plain(mut agent)) => {
+                agent.set_stop_handle(thread_stop_handle);
+                Some(agent.get_move(&board))
+            },
+            Ok(EngineAgent::Analyzing(mut agent)) => {
+                agent.set_stop_handle(thread_stop_handle);
+                let mut report = |analysis: &Analysis| {
+                    if *thread_answered.lock().expect("search lock poisoned") {
+                        return
+                    }
+                    *thread_best_move.lock().expect("search lock poisoned") = analysis.best_move().cloned();
+                    for info in InfoLine::from_analysis(analysis) {
+                        send(&output, EngineMessage::Info(info));
+                    }
+                };
+                let analysis = agent.analyze(&board, 1, &mut report);
+                report(&analysis);
+                Some(analysis.best_move().cloned().expect("Non-terminal boards have moves"))
+            },
+        };
+        let mut answered = thread_answered.lock().expect("search lock poisoned");
+        if !*answered {
+            send(&output, EngineMessage::BestMove(turn_move));
+            *answered = true;
+        }
+    });
+    RunningSearch { best_move, answered, stop_handle, handle }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    fn position(command: &str) -> GameBoard {
+        match command.parse() {
+            Ok(Command::Position(board)) => *board,
+            other => panic!("{command} parsed as {other:?}"),
+        }
+    }
+
+    #[test]
+    fn commands_are_parsed() {
+        assert!(matches!("neutrino".parse(), Ok(Command::Neutrino)));
+        assert!(matches!(" isready ".parse(), Ok(Command::IsReady)));
+        assert!(matches!("newgame".parse(), Ok(Command::NewGame)));
+        assert!(matches!("stop".parse(), Ok(Command::Stop)));
+        assert!(matches!("quit".parse(), Ok(Command::Quit)));
+
+        assert_eq!(position("position startpos"), GameBoard::default());
+        let mut board = GameBoard::default();
+        board.play(&"a1a4".parse().unwrap()).unwrap();
+        board.play(&"c3c4/b5b2".parse().unwrap()).unwrap();
+        assert_eq!(position("position startpos moves a1a4 c3c4/b5b2"), board);
+        assert_eq!(position("position 1..../...../..X../...../....2 2").to_notation(), "1..../...../..X../...../....2 2");
+        let rules: RuleSet = "full-first-turn".parse().unwrap();
+        assert_eq!(position("position rules full-first-turn startpos moves c3c4/a1a4"), GameBoard::start(rules).result("c3c4/a1a4".parse().unwrap()));
+
+        let go = |command: &str| match command.parse() {
+            Ok(Command::Go(limits)) => limits,
+            other => panic!("{command} parsed as {other:?}"),
+        };
+        assert_eq!(go("go"), GoLimits::default());
+        assert_eq!(go("go time 1500"), GoLimits { time: Some(Duration::from_millis(1500)), depth: None });
+        assert_eq!(go("go depth 3 time 20"), GoLimits { time: Some(Duration::from_millis(20)), depth: Some(3) });
+    }
+
+    #[test]
+    fn malformed_commands_are_rejected() {
+        for command in [
+            "",
+            "ready",
+            //engines take no options, agents are configured when they are started
+            "setoption name threads value 2",
+            "position",
+            "position moves a1a4",
+            "position startpos a1a4",
+            "position startpos moves a1a9",
+            "position startpos moves c3c4/a1a4",
+            "position rules fast startpos",
+            "position 11111/...../..X../..... 1",
+            "go time",
+            "go time soon",
+            "go nodes 100",
+            "go depth -1",
+            "stop now",
+        ] {
+            assert!(command.parse::<Command>().is_err(), "{command}");
+        }
+    }
+
+    #[test]
+    fn engine_messages_round_trip() {
+        let info = InfoLine {
+            multi_pv: Some(1),
+            depth: Some(2),
+            nodes: Some(300),
+            time: Some(Duration::from_millis(40)),
+            score: Some(0.625),
+            visits: Some(120),
+            pv: vec!["a1a4".parse().unwrap(), "c3c4/b5b2".parse().unwrap()],
+            string: None,
+        };
+        assert_eq!(info.to_string(), "info multipv 1 depth 2 nodes 300 time 40 score 0.6250 visits 120 pv a1a4 c3c4/b5b2");
+        assert_eq!(info.to_string().parse::<InfoLine>().unwrap(), info);
+        assert_eq!("info hashfull 10 depth 4".parse::<InfoLine>().unwrap(), InfoLine { depth: Some(4), ..Default::default() });
+        assert_eq!("info string hello there".parse::<InfoLine>().unwrap(), InfoLine::string("hello there"));
+        assert!("info depth deep".parse::<InfoLine>().is_err());
+
+        assert!(matches!("bestmove none".parse(), Ok(EngineMessage::BestMove(None))));
+        assert!(matches!("bestmove a1a4".parse(), Ok(EngineMessage::BestMove(Some(_)))));
+        assert!(matches!("id name neutrino mcts".parse(), Ok(EngineMessage::Id(key, value)) if key == "name" && value == "neutrino mcts"));
+        assert!("bestmove".parse::<EngineMessage>().is_err());
+        assert!("hello".parse::<EngineMessage>().is_err());
+    }
+
+    /**
+     * Output that stays readable after the engine took ownership of it.
+     */
+    #[derive(Clone, Default)]
+    struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /**
+     * Searches until it is stopped, or gives up after a while so that a missed stop fails instead of hanging.
+     */
+    struct UntilStopped(StopHandle);
+
+    impl Agent for UntilStopped {
+        fn get_move(&mut self, board: &GameBoard) -> TurnMove {
+            let start = Instant::now();
+            while !self.0.is_stopped() && start.elapsed() < Duration::from_secs(5) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            board.actions().into_iter().next().unwrap()
+        }
+
+        fn set_stop_handle(&mut self, stop_handle: StopHandle) {
+            self.0 = stop_handle;
+        }
+    }
+
+    #[test]
+    fn a_new_search_stops_the_running_one() {
+        let factory: AgentFactory = Arc::new(|_, _| Ok(EngineAgent::Plain(Box::new(UntilStopped(StopHandle::new())))));
+        let output = SharedOutput::default();
+        let start = Instant::now();
+        run_engine("test", "go\ngo\nisready\nquit\n".as_bytes(), output.clone(), factory);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        let first_move = GameBoard::default().actions().into_iter().next().unwrap();
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(output, format!("bestmove {}\nreadyok\n", first_move.to_notation()));
+    }
+
+    #[test]
+    fn agents_that_cannot_be_built_answer_no_move() {
+        let factory: AgentFactory = Arc::new(|_, _| Err(ConfigError::Build("could not start engine: not found".to_string())));
+        let output = Arc::new(Mutex::new(vec![]));
+        let search = start_search(&GameBoard::default(), GoLimits::default(), factory, Arc::clone(&output));
+        search.handle.join().unwrap();
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert_eq!(output, "info string could not start engine: not found\nbestmove none\n");
+    }
+}
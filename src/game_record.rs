@@ -0,0 +1,318 @@
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
+
+use crate::{neutrino_board::{GameBoard, Player, TurnMove}, notation::ParseNotationError, outcome::{Outcome, Reason}, rules::RuleSet};
+
+/**
+ * A played game: the moves from the default start position and, if the game finished, its result.
+ *
+ * Records are written as tag lines followed by the moves in notation, e.g.
+ * ```text
+ * [Player1 "mcts"]
+ * [Termination "home-row"]
+ * [Result "1-0"]
+ * c3b4/a1a3 b4d2/e5e4? {mistake} ...
+ * ```
+ * A move can be followed by a glyph (`!`, `?!`, `?`, `??`) and a comment in braces.
+ * Games played by other than the standard rules have a `Rules` tag, e.g. `[Rules "full-first-turn"]`.
+ */
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GameRecord {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<TurnMove>,
+    /// Glyphs such as `?` or `??`, keyed by move index.
+    pub glyphs: BTreeMap<usize, String>,
+    /// Comments, keyed by move index.
+    pub comments: BTreeMap<usize, String>,
+    /// The utility of the final position for Player1, if the game finished.
+    pub result: Option<f64>,
+}
+
+impl GameRecord {
+    /**
+     * An empty record of an unfinished game.
+     */
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * Sets a tag, replacing its value if it is already set.
+     */
+    pub fn set_tag(&mut self, key: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == key) {
+            Some((_, old_value)) => *old_value = value.to_string(),
+            None => self.tags.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    /**
+     * The rules of the game, from the `Rules` tag.
+     */
+    pub fn rules(&self) -> RuleSet {
+        self.tags.iter()
+            .find(|(key, _)| key == "Rules")
+            .and_then(|(_, value)| value.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /**
+     * Sets the `Rules` tag, leaving it out for the standard rules.
+     */
+    pub fn set_rules(&mut self, rules: RuleSet) {
+        match rules == RuleSet::STANDARD {
+            true => self.tags.retain(|(key, _)| key != "Rules"),
+            false => self.set_tag("Rules", &rules.to_notation()),
+        }
+    }
+
+    /**
+     * The board before each move, followed by the final board.
+     * The moves must be legal, as they are in parsed records and games played by the match runner.
+     */
+    pub fn positions(&self) -> Vec<GameBoard> {
+        let mut board = GameBoard::start(self.rules());
+        let mut positions = vec![board.clone()];
+        for turn_move in &self.moves {
+            board = board.result(turn_move.clone());
+            positions.push(board.clone());
+        }
+        positions
+    }
+
+    /**
+     * The utility of the result from the given player's perspective.
+     */
+    pub fn result_for(&self, player: Player) -> Option<f64> {
+        self.result.map(|result| match player {
+            Player::Player1 => result,
+            Player::Player2 => 1.0 - result,
+        })
+    }
+
+    /**
+     * Records how the game ended: the result and, in the `Termination` tag, the reason.
+     */
+    pub fn set_outcome(&mut self, outcome: &Outcome) {
+        self.result = outcome.utility(Player::Player1);
+        match outcome.reason() {
+            Some(reason) => self.set_tag("Termination", reason.to_notation()),
+            None => self.tags.retain(|(key, _)| key != "Termination"),
+        }
+    }
+
+    /**
+     * How the game ended, from the result and the `Termination` tag.
+     * Finished games without a known reason are taken to have ended by the rules on the final board,
+     * and are reported as ongoing if that board is not over either.
+     */
+    pub fn outcome(&self) -> Outcome {
+        let Some(result) = self.result else {
+            return Outcome::Ongoing
+        };
+        let reason = self.tags.iter()
+            .find(|(key, _)| key == "Termination")
+            .and_then(|(_, value)| value.parse::<Reason>().ok());
+        let reason = match reason {
+            Some(reason) => reason,
+            None => match self.positions().pop().expect("positions include the final board").outcome().reason() {
+                Some(reason) => reason,
+                None => return Outcome::Ongoing,
+            },
+        };
+        match result {
+            1.0 => Outcome::Win(Player::Player1, reason),
+            0.0 => Outcome::Win(Player::Player2, reason),
+            _ => Outcome::Draw(reason),
+        }
+    }
+
+    /**
+     * Parses every record in a text containing several games, each starting with its tags.
+     * Fails on the first record that cannot be parsed, see `parse_each` to skip those.
+     */
+    pub fn parse_all(input: &str) -> Result<Vec<GameRecord>, ParseNotationError> {
+        Self::parse_each(input).into_iter().collect()
+    }
+
+    /**
+     * Parses each record in a text containing several games, each starting with its tags,
+     * so that records with errors can be reported and the others used.
+     */
+    pub fn parse_each(input: &str) -> Vec<Result<GameRecord, ParseNotationError>> {
+        let mut records = vec![];
+        let mut current = String::new();
+        let mut seen_moves = false;
+        for line in input.lines() {
+            let is_tag = line.trim_start().starts_with('[');
+            if is_tag && seen_moves {
+                records.push(current.parse());
+                current.clear();
+                seen_moves = false;
+            }
+            if !is_tag && !line.trim().is_empty() {
+                seen_moves = true;
+            }
+            current.push_str(line);
+            current.push('\n');
+        }
+        if !current.trim().is_empty() {
+            records.push(current.parse());
+        }
+        records
+    }
+}
+
+/**
+ * The result as written in the `Result` tag: `1-0`, `0-1`, `1/2-1/2` or `*` for an unfinished game.
+ */
+pub fn result_to_notation(result: Option<f64>) -> &'static str {
+    match result {
+        Some(1.0) => "1-0",
+        Some(0.0) => "0-1",
+        Some(_) => "1/2-1/2",
+        None => "*",
+    }
+}
+
+fn result_from_notation(notation: &str) -> Result<Option<f64>, ParseNotationError> {
+    match notation {
+        "1-0" => Ok(Some(1.0)),
+        "0-1" => Ok(Some(0.0)),
+        "1/2-1/2" => Ok(Some(0.5)),
+        "*" => Ok(None),
+        _ => Err(ParseNotationError::new(notation, "unknown result")),
+    }
+}
+
+impl Display for GameRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (key, value) in self.tags.iter().filter(|(key, _)| key != "Result") {
+            writeln!(f, "[{key} \"{value}\"]")?;
+        }
+        writeln!(f, "[Result \"{}\"]", result_to_notation(self.result))?;
+        let mut line = String::new();
+        for (index, turn_move) in self.moves.iter().enumerate() {
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&turn_move.to_notation());
+            if let Some(glyph) = self.glyphs.get(&index) {
+                line.push_str(glyph);
+            }
+            //commented moves end the line to keep annotated records readable
+            if let Some(comment) = self.comments.get(&index) {
+                writeln!(f, "{line} {{{comment}}}")?;
+                line.clear();
+            }
+        }
+        if !line.is_empty() {
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for GameRecord {
+    type Err = ParseNotationError;
+
+    /**
+     * Parses a record, checking that its moves are legal by its rules.
+     */
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut record = GameRecord::new();
+        let mut move_text = String::new();
+        for line in s.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            if let Some(tag) = line.strip_prefix('[') {
+                let (key, value) = tag
+                    .strip_suffix(']')
+                    .and_then(|tag| tag.split_once(' '))
+                    .ok_or_else(|| ParseNotationError::new(line, "expected [Key \"value\"]"))?;
+                let value = value.trim().trim_matches('"');
+                if key == "Result" {
+                    record.result = result_from_notation(value)?;
+                }
+                if key == "Rules" {
+                    value.parse::<RuleSet>()?;
+                }
+                record.set_tag(key, value);
+                continue;
+            }
+            move_text.push_str(line);
+            move_text.push('\n');
+        }
+
+        let mut board = GameBoard::start(record.rules());
+        let mut rest = move_text.trim_start();
+        while !rest.is_empty() {
+            if let Some(comment) = rest.strip_prefix('{') {
+                let (comment, after) = comment
+                    .split_once('}')
+                    .ok_or_else(|| ParseNotationError::new(rest, "unterminated comment"))?;
+                let index = record.moves.len()
+                    .checked_sub(1)
+                    .ok_or_else(|| ParseNotationError::new(rest, "comment before the first move"))?;
+                record.comments.insert(index, comment.trim().to_string());
+                rest = after.trim_start();
+                continue;
+            }
+            let token_end = rest.find(|c: char| c.is_whitespace() || c == '{').unwrap_or(rest.len());
+            let (token, after) = rest.split_at(token_end);
+            let turn_move = token.trim_end_matches(['!', '?']);
+            let glyph = &token[turn_move.len()..];
+            let parsed: TurnMove = turn_move.parse()?;
+            board = board.try_result(&parsed)
+                .map_err(|move_error| ParseNotationError::new(&format!("{turn_move} ({move_error})"), "illegal move"))?;
+            record.moves.push(parsed);
+            if !glyph.is_empty() {
+                record.glyphs.insert(record.moves.len() - 1, glyph.to_string());
+            }
+            rest = after.trim_start();
+        }
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECORD: &str = "[Player1 \"mcts\"]\n[Termination \"home-row\"]\n[Result \"0-1\"]\na1a4 c3c4/b5b2? {mistake}\nc4b5/b1a1\n";
+
+    #[test]
+    fn records_are_parsed_and_replayed() {
+        let record: GameRecord = RECORD.parse().unwrap();
+        assert_eq!(record.moves.iter().map(TurnMove::to_notation).collect::<Vec<_>>(), ["a1a4", "c3c4/b5b2", "c4b5/b1a1"]);
+        assert_eq!(record.glyphs[&1], "?");
+        assert_eq!(record.comments[&1], "mistake");
+        assert_eq!(record.outcome(), Outcome::Win(Player::Player2, Reason::NeutrinoHomeRow));
+
+        let positions = record.positions();
+        assert_eq!(positions.len(), 4);
+        assert_eq!(positions[0], GameBoard::default());
+        assert_eq!(positions[3].outcome(), Outcome::Win(Player::Player2, Reason::NeutrinoHomeRow));
+
+        //the written record reads back the same
+        let reparsed: GameRecord = record.to_string().parse().unwrap();
+        assert_eq!(reparsed.to_string(), record.to_string());
+        //without the tag the reason comes from the final board
+        let untagged: GameRecord = RECORD.replace("[Termination \"home-row\"]\n", "").parse().unwrap();
+        assert_eq!(untagged.outcome(), record.outcome());
+    }
+
+    #[test]
+    fn illegal_moves_are_rejected() {
+        //the first turn moves only a piece by the standard rules
+        assert!("[Result \"*\"]\nc3c5/a1a2\n".parse::<GameRecord>().is_err());
+        assert!("[Rules \"full-first-turn\"]\n[Result \"*\"]\nc3c4/a1a4\n".parse::<GameRecord>().is_ok());
+        assert!("[Result \"*\"]\na1a4 c3c4/a1a2\n".parse::<GameRecord>().is_err());
+        assert!("[Result \"*\"]\na1q3\n".parse::<GameRecord>().is_err());
+        assert!("[Result \"won\"]\na1a4\n".parse::<GameRecord>().is_err());
+        assert!("[Result \"*\"]\n{comment} a1a4\n".parse::<GameRecord>().is_err());
+
+        let text = format!("{RECORD}\n[Result \"*\"]\nc3c5/a1a2\n\n{RECORD}");
+        let records = GameRecord::parse_each(&text);
+        assert_eq!(records.iter().map(Result::is_ok).collect::<Vec<_>>(), [true, false, true]);
+        assert!(GameRecord::parse_all(&text).is_err());
+    }
+}
@@ -0,0 +1,423 @@
+use std::{collections::HashMap, io::{self, BufRead, BufReader, Read, Write}, net::{TcpListener, TcpStream}, sync::{Arc, Mutex}, thread, time::Duration};
+
+use serde_json::{json, Value};
+
+use crate::{agents::registry::ConfigError, engine_protocol::{AgentFactory, EngineAgent, GoLimits}, game_record::{result_to_notation, GameRecord}, neutrino_board::{GameBoard, Player, TurnMove}, rules::RuleSet};
+
+/*
+ * A JSON API over HTTP/1.1. Boards, moves and records are written in their notations.
+ *
+ *   POST /games                          create a game from the start position, {"rules": "full-first-turn"} for house rules
+ *   GET  /games/<id>                     the game's position, legal moves and moves played
+ *   POST /games/<id>/moves               play {"move": "c3c4/a1a2"}
+ *   POST /games/<id>/engine-move         let the engine move, for {"time_ms": <ms>} or the engine's own time
+ *   GET  /games/<id>/record              the game record, as tags and moves and as text
+ *   GET  /legal-moves?position=<pos>     the legal moves of any position, by other rules with &rules=<rules>
+ *
+ * Errors are answered as {"error": "<text>"} with a 4xx status, or 500 if the engine cannot be started.
+ * An engine move is only played if no other move was played during its search, and is answered with 409 otherwise.
+ */
+
+/// The longest time budget a client may ask the engine for.
+const MAX_ENGINE_TIME: Duration = Duration::from_secs(60);
+/// The largest request body accepted; larger ones are answered with 413 before they are read.
+const MAX_BODY_LENGTH: usize = 64 * 1024;
+/// The longest request line or header line accepted, in bytes without the line break.
+const MAX_LINE_LENGTH: usize = 8 * 1024;
+/// The most header lines accepted in a request.
+const MAX_HEADERS: usize = 100;
+
+type Games = Arc<Mutex<HashMap<usize, GameRecord>>>;
+
+/**
+ * A response: the status code and its JSON body.
+ */
+type Response = (u16, Value);
+
+fn error(status: u16, text: &str) -> Response {
+    (status, json!({ "error": text }))
+}
+
+/**
+ * Answers API requests for the clients connecting to the listener, until the listener fails.
+ * Engine moves are searched by agents from `engine` on the requesting connection's thread.
+ */
+pub fn serve(listener: TcpListener, engine: AgentFactory) {
+    let games: Games = Arc::new(Mutex::new(HashMap::new()));
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else { break };
+        let games = Arc::clone(&games);
+        let engine = Arc::clone(&engine);
+        thread::spawn(move || handle_connection(stream, &games, &engine));
+    }
+}
+
+/**
+ * Reads one request, answers it and closes the connection.
+ */
+fn handle_connection(stream: TcpStream, games: &Games, engine: &AgentFactory) {
+    let Ok(mut writer) = stream.try_clone() else { return };
+    let mut reader = BufReader::new(stream);
+    let (status, body) = match read_request(&mut reader) {
+        Ok((method, target, body)) => route(&method, &target, &body, games, engine),
+        Err((status, text)) => error(status, &text),
+    };
+    let body = body.to_string();
+    let reason = match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Content Too Large",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        _ => "",
+    };
+    let _ = write!(writer, "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n{body}", body.len());
+}
+
+/**
+ * The method, target and body of a request, or the status and text of the error to answer with.
+ */
+fn read_request(reader: &mut impl BufRead) -> Result<(String, String, String), (u16, String)> {
+    let bad_request = |text: String| (400, text);
+    let request_line = read_line(reader).map_err(|error| bad_request(error.to_string()))?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(bad_request("malformed request line".to_string()))
+    };
+    let mut content_length = 0;
+    for header_count in 0.. {
+        let header = read_line(reader).map_err(|error| match error.kind() {
+            io::ErrorKind::InvalidData => (431, error.to_string()),
+            _ => bad_request(error.to_string()),
+        })?;
+        let header = header.trim();
+        if header.is_empty() {
+            break
+        }
+        if header_count == MAX_HEADERS {
+            return Err((431, format!("a request has at most {MAX_HEADERS} header lines")))
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length") {
+            content_length = value.trim().parse().map_err(|_| bad_request("invalid Content-Length".to_string()))?;
+        }
+    }
+    if content_length > MAX_BODY_LENGTH {
+        return Err((413, format!("the body is longer than {MAX_BODY_LENGTH} bytes")))
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).map_err(|error| bad_request(error.to_string()))?;
+    let body = String::from_utf8(body).map_err(|_| bad_request("the body is not UTF-8".to_string()))?;
+    Ok((method.to_string(), target.to_string(), body))
+}
+
+/**
+ * Reads a line without its line break, failing on lines longer than `MAX_LINE_LENGTH` bytes without reading past the limit.
+ */
+fn read_line(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    reader.take(MAX_LINE_LENGTH as u64 + 2).read_line(&mut line)?;
+    let line = line.strip_suffix('\n').map(|line| line.strip_suffix('\r').unwrap_or(line)).unwrap_or(&line);
+    if line.len() > MAX_LINE_LENGTH {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("lines are at most {MAX_LINE_LENGTH} bytes long")))
+    }
+    Ok(line.to_string())
+}
+
+fn route(method: &str, target: &str, body: &str, games: &Games, engine: &AgentFactory) -> Response {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let body: Value = match body.trim() {
+        "" => json!({}),
+        body => match serde_json::from_str(body) {
+            Ok(body) => body,
+            Err(parse_error) => return error(400, &format!("invalid JSON: {parse_error}")),
+        },
+    };
+    let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+    match (method, segments.as_slice()) {
+        ("POST", ["games"]) => {
+            let rules = match body.get("rules").map(|rules| rules.as_str().map(str::parse::<RuleSet>)) {
+                None => RuleSet::default(),
+                Some(Some(Ok(rules))) => rules,
+                Some(Some(Err(parse_error))) => return error(400, &parse_error.to_string()),
+                Some(None) => return error(400, "rules must be a string"),
+            };
+            let mut games = games.lock().expect("games lock poisoned");
+            let id = games.len();
+            let mut record = GameRecord::new();
+            record.set_rules(rules);
+            let response = game_json(id, &record);
+            games.insert(id, record);
+            (201, response)
+        },
+        ("GET", ["legal-moves"]) => {
+            let parameter = |name: &str| query.split('&')
+                .find_map(|parameter| parameter.strip_prefix(name)?.strip_prefix('='))
+                .map(percent_decode);
+            let rules = match parameter("rules").map(|rules| rules.parse::<RuleSet>()) {
+                None => RuleSet::default(),
+                Some(Ok(rules)) => rules,
+                Some(Err(parse_error)) => return error(400, &parse_error.to_string()),
+            };
+            match parameter("position").map(|position| position.parse::<GameBoard>()) {
+                Some(Ok(board)) => (200, board_json(&board.with_rules(rules))),
+                Some(Err(parse_error)) => error(400, &parse_error.to_string()),
+                None => error(400, "missing the position parameter"),
+            }
+        },
+        (_, ["games", id, rest @ ..]) => {
+            let Ok(id) = id.parse::<usize>() else {
+                return error(404, "no such game")
+            };
+            let Some(record) = games.lock().expect("games lock poisoned").get(&id).cloned() else {
+                return error(404, "no such game")
+            };
+            match (method, rest) {
+                ("GET", []) => (200, game_json(id, &record)),
+                ("GET", ["record"]) => (200, record_json(&record)),
+                ("POST", ["moves"]) => {
+                    let turn_move = match body.get("move").and_then(Value::as_str).map(str::parse::<TurnMove>) {
+                        Some(Ok(turn_move)) => turn_move,
+                        Some(Err(parse_error)) => return error(400, &parse_error.to_string()),
+                        None => return error(400, "expected {\"move\": \"<move>\"}"),
+                    };
+                    play(games, id, turn_move, None)
+                },
+                ("POST", ["engine-move"]) => {
+                    let time = match body.get("time_ms") {
+                        None => None,
+                        Some(time) => match time.as_u64() {
+                            Some(millis) => Some(Duration::from_millis(millis).min(MAX_ENGINE_TIME)),
+                            None => return error(400, "time_ms must be a number of milliseconds"),
+                        },
+                    };
+                    let board = current_board(&record);
+                    if board.is_terminal() {
+                        return error(409, "the game is over")
+                    }
+                    //the lock is not held during the search, so the move is only played if the game has not moved on
+                    let (turn_move, score) = match engine_move(engine, &board, time) {
+                        Ok(found) => found,
+                        Err(config_error) => return error(500, &format!("the engine could not be started: {config_error}")),
+                    };
+                    let (status, mut game) = play(games, id, turn_move.clone(), Some(record.moves.len()));
+                    if status == 200 {
+                        game["engine_move"] = json!(turn_move.to_notation());
+                        game["score"] = json!(score);
+                    }
+                    (status, game)
+                },
+                (_, [] | ["record" | "moves" | "engine-move"]) => error(405, "method not allowed"),
+                _ => error(404, "not found"),
+            }
+        },
+        (_, ["games" | "legal-moves"]) => error(405, "method not allowed"),
+        _ => error(404, "not found"),
+    }
+}
+
+/**
+ * Plays a move in a game if it is legal and the game is still running.
+ * With `ply`, the move is only played if the game still has that many moves.
+ */
+fn play(games: &Games, id: usize, turn_move: TurnMove, ply: Option<usize>) -> Response {
+    let mut games = games.lock().expect("games lock poisoned");
+    let record = games.get_mut(&id).expect("games are never removed");
+    if ply.is_some_and(|ply| ply != record.moves.len()) {
+        return error(409, "another move was played during the search")
+    }
+    let board = current_board(record);
+    if board.is_terminal() {
+        return error(409, "the game is over")
+    }
+    let board = match board.try_result(&turn_move) {
+        Ok(board) => board,
+        Err(move_error) => return error(409, &format!("illegal move {}: {move_error}", turn_move.to_notation())),
+    };
+    record.moves.push(turn_move);
+    record.set_outcome(&board.outcome());
+    (200, game_json(id, record))
+}
+
+/**
+ * Searches the board with an agent from the factory. The score is reported by analysing agents only.
+ * A given time wins over the engine's own. Fails if the factory cannot build the agent.
+ */
+fn engine_move(engine: &AgentFactory, board: &GameBoard, time: Option<Duration>) -> Result<(TurnMove, Option<f64>), ConfigError> {
+    Ok(match engine(board, GoLimits { time, depth: None })? {
+        EngineAgent::Plain(mut agent) => (agent.get_move(board), None),
+        EngineAgent::Analyzing(mut agent) => {
+            let analysis = agent.analyze(board, 1, &mut |_| {});
+            let line = analysis.lines.first().expect("Non-terminal boards have moves");
+            (line.turn_move.clone(), Some(line.score))
+        },
+    })
+}
+
+fn current_board(record: &GameRecord) -> GameBoard {
+    record.positions().pop().expect("positions include the final board")
+}
+
+fn player_json(player: Player) -> Value {
+    match player {
+        Player::Player1 => json!("player1"),
+        Player::Player2 => json!("player2"),
+    }
+}
+
+fn board_json(board: &GameBoard) -> Value {
+    let mut legal_moves: Vec<String> = board.actions().iter().map(TurnMove::to_notation).collect();
+    legal_moves.sort();
+    json!({
+        "position": board.to_notation(),
+        "to_move": player_json(board.to_move()),
+        "terminal": board.is_terminal(),
+        "legal_moves": legal_moves,
+    })
+}
+
+fn game_json(id: usize, record: &GameRecord) -> Value {
+    let mut game = board_json(&current_board(record));
+    game["id"] = json!(id);
+    game["moves"] = json!(record.moves.iter().map(TurnMove::to_notation).collect::<Vec<_>>());
+    game["rules"] = json!(record.rules().to_notation());
+    game["result"] = json!(result_to_notation(record.result));
+    game["termination"] = json!(record.outcome().reason().map(|reason| reason.to_notation()));
+    game
+}
+
+fn record_json(record: &GameRecord) -> Value {
+    let tags: serde_json::Map<String, Value> = record.tags.iter().map(|(key, value)| (key.clone(), json!(value))).collect();
+    json!({
+        "tags": tags,
+        "moves": record.moves.iter().map(TurnMove::to_notation).collect::<Vec<_>>(),
+        "result": result_to_notation(record.result),
+        "termination": record.outcome().reason().map(|reason| reason.to_notation()),
+        "text": record.to_string(),
+    })
+}
+
+/**
+ * Decodes `%xx` escapes and `+` in a query parameter.
+ */
+fn percent_decode(value: &str) -> String {
+    let mut bytes = vec![];
+    let mut input = value.bytes();
+    while let Some(byte) = input.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex: Vec<u8> = input.by_ref().take(2).collect();
+                match std::str::from_utf8(&hex).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(decoded) => bytes.push(decoded),
+                    None => {
+                        bytes.push(b'%');
+                        bytes.extend(hex);
+                    },
+                }
+            },
+            _ => bytes.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+    use crate::agents::random_agent::RandomAgent;
+
+    /**
+     * Serves the API with a random engine on a free localhost port.
+     */
+    fn start_server() -> SocketAddr {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let engine: AgentFactory = Arc::new(|_, _| Ok(EngineAgent::Plain(Box::new(RandomAgent::default()))));
+        thread::spawn(move || serve(listener, engine));
+        address
+    }
+
+    /**
+     * Sends a raw request and returns the status and the JSON body of the response.
+     */
+    fn send(address: SocketAddr, request: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response.split_whitespace().nth(1).unwrap().parse().unwrap();
+        let body = response.split_once("\r\n\r\n").unwrap().1;
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    fn request(address: SocketAddr, method: &str, path: &str, body: &str) -> (u16, Value) {
+        send(address, &format!("{method} {path} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{body}", body.len()))
+    }
+
+    #[test]
+    fn games_are_played_through_the_api() {
+        let address = start_server();
+        let (status, game) = request(address, "POST", "/games", "");
+        assert_eq!(status, 201);
+        let id = game["id"].as_u64().unwrap();
+        assert_eq!(game["legal_moves"].as_array().unwrap().len(), GameBoard::default().actions().len());
+
+        let (status, game) = request(address, "POST", &format!("/games/{id}/moves"), r#"{"move": "a1a4"}"#);
+        assert_eq!(status, 200);
+        assert_eq!(game["moves"], json!(["a1a4"]));
+        assert_eq!(game["to_move"], json!("player2"));
+
+        let (status, response) = request(address, "POST", &format!("/games/{id}/moves"), r#"{"move": "c3c5/a1a2"}"#);
+        assert_eq!(status, 409);
+        assert!(response["error"].as_str().unwrap().starts_with("illegal move"));
+
+        let (status, game) = request(address, "POST", &format!("/games/{id}/engine-move"), "");
+        assert_eq!(status, 200);
+        assert_eq!(game["moves"].as_array().unwrap().len(), 2);
+        let (status, record) = request(address, "GET", &format!("/games/{id}/record"), "");
+        assert_eq!(status, 200);
+        assert_eq!(record["moves"], game["moves"]);
+    }
+
+    #[test]
+    fn bad_requests_are_rejected() {
+        let address = start_server();
+        assert_eq!(request(address, "GET", "/games/99", "").0, 404);
+        assert_eq!(request(address, "DELETE", "/games", "").0, 405);
+        assert_eq!(request(address, "POST", "/games", "{not json").0, 400);
+        assert_eq!(request(address, "POST", "/games", r#"{"rules": "size=2"}"#).0, 400);
+        assert_eq!(request(address, "GET", "/legal-moves?position=11111/...../..X../...../22222%201", "").0, 200);
+        assert_eq!(request(address, "GET", "/legal-moves?position=nonsense", "").0, 400);
+        //the body is refused before anything is allocated for it
+        let (status, _) = send(address, "POST /games HTTP/1.1\r\nContent-Length: 999999999999999\r\n\r\n");
+        assert_eq!(status, 413);
+        //so are overlong lines, read only up to the limit
+        let (status, response) = send(address, &"x".repeat(MAX_LINE_LENGTH + 2));
+        assert_eq!((status, response["error"].as_str().unwrap()), (400, "lines are at most 8192 bytes long"));
+        let (status, _) = send(address, &format!("GET /games/0 HTTP/1.1\r\nX-Long: {}", "x".repeat(MAX_LINE_LENGTH - 6)));
+        assert_eq!(status, 431);
+        let (status, _) = send(address, &format!("GET /games/0 HTTP/1.1\r\n{}", "X-Many: 1\r\n".repeat(MAX_HEADERS + 1)));
+        assert_eq!(status, 431);
+        let (status, _) = send(address, &format!("GET /games/0 HTTP/1.1\r\nX-Long: {}\r\n\r\n", "x".repeat(MAX_LINE_LENGTH - 8)));
+        assert_eq!(status, 404);
+    }
+
+    #[test]
+    fn engine_moves_are_not_played_after_another_move() {
+        let games: Games = Arc::new(Mutex::new(HashMap::from([(0, GameRecord::new())])));
+        let turn_move = |notation: &str| notation.parse::<TurnMove>().unwrap();
+        assert_eq!(play(&games, 0, turn_move("a1a4"), Some(0)).0, 200);
+        //a move searched for the start position arrives after a1a4 was played
+        let (status, response) = play(&games, 0, turn_move("b1b4"), Some(0));
+        assert_eq!((status, response["error"].as_str().unwrap()), (409, "another move was played during the search"));
+        assert_eq!(play(&games, 0, turn_move("c3c4/b5b2"), Some(1)).0, 200);
+        assert_eq!(games.lock().unwrap()[&0].moves, [turn_move("a1a4"), turn_move("c3c4/b5b2")]);
+    }
+}
@@ -0,0 +1,71 @@
+//! Neutrino: the board game, search agents and the tools around them.
+//!
+//! Two players move their five pieces and the shared neutrino on a 5x5 board. Every turn the
+//! player to move first slides the neutrino, then one of their own pieces; pieces slide as far
+//! as they can in one of eight directions. The game's first turn moves only a piece, unless a
+//! house rule says otherwise ([`RuleSet`]). Whoever gets the neutrino onto their own home row
+//! wins, no matter who moved it there, and a player who cannot move loses. A position occurring
+//! for the third time draws the game. Variants such as larger
+//! boards (up to 9x9, with as many pieces each as the board is wide), custom start positions or the
+//! neutrino losing on its own row are selected with a [`RuleSet`] as well.
+//!
+//! - [`GameBoard`] holds a position and generates and applies moves ([`TurnMove`]).
+//! - [`agents`] contains the [`Agent`] trait and the random, alpha-beta and MCTS agents,
+//!   as well as agents backed by external engines, humans and network opponents, and a registry
+//!   building agents from TOML or JSON descriptions.
+//! - [`notation`], [`game_record`] and [`opening_book`] read and write positions, games and books,
+//!   and [`self_play`] generates datasets of searched positions.
+//! - [`engine_protocol`], [`network`] and [`http_api`] let other programs play and embed the engine.
+//!
+//! Playing a game between two agents:
+//!
+//! ```
+//! use neutrino::agents::{minimax::MinimaxAgent, random_agent::RandomAgent};
+//! use neutrino::match_runner::play_game;
+//!
+//! let mut random = RandomAgent::default();
+//! let mut alpha_beta = MinimaxAgent::new(1);
+//! let record = play_game(&mut random, &mut alpha_beta, 20);
+//! println!("{record}");
+//! ```
+
+/// The board, its pieces and moves, and the rules of the game.
+pub mod neutrino_board;
+/// Players choosing moves: search agents, humans, external engines and network opponents.
+pub mod agents;
+/// The official rules and the house rules games can be played by.
+pub mod rules;
+/// How games end: the winner or a draw, and why.
+pub mod outcome;
+/// The mirror and colour-flip symmetries of positions, and their canonical forms.
+pub mod symmetry;
+/// The text notation of squares, moves and positions.
+pub mod notation;
+/// Played games and their PGN-like text format.
+pub mod game_record;
+/// Playing games between agents.
+pub mod match_runner;
+/// Opening books built from game records and searches.
+pub mod opening_book;
+/// Self-play games in parallel and the training datasets written from them.
+pub mod self_play;
+/// Marking the inaccuracies, mistakes and blunders of a game.
+pub mod annotation;
+/// The line-based protocol engines speak over stdin and stdout.
+pub mod engine_protocol;
+/// The full-screen terminal UI.
+pub mod tui;
+/// Hosting games over TCP.
+pub mod network;
+/// The HTTP/JSON API.
+pub mod http_api;
+#[cfg(feature = "serde")]
+mod serialization;
+
+pub use agents::agent::Agent;
+pub use engine_protocol::GoLimits;
+pub use neutrino_board::{GameBoard, MoveError, MoveList, MoveType, Piece, Player, Position, Squares, TurnMove, UndoRecord};
+pub use notation::ParseNotationError;
+pub use outcome::{Outcome, Reason};
+pub use rules::RuleSet;
+pub use symmetry::Symmetry;
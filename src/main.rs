@@ -1,39 +1,808 @@
-mod neutrino_board;
-mod agents;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::thread;
 use std::time::Duration;
 
-use crate::agents::agent::Agent;
-use crate::agents::human::Human;
-use crate::agents::minimax::{self, MinimaxAgent};
-use crate::agents::monte_carlo_tree_search::MonteCarloTreeSearch;
-use crate::neutrino_board::{GameBoard, Player};
-use crate::agents::random_agent::RandomAgent;
+use neutrino::agents::agent::Agent;
+use neutrino::agents::analysis::{Analyze, SearchReport};
+use neutrino::agents::book_agent::BookAgent;
+use neutrino::agents::human::{Human, HumanAction};
+use neutrino::agents::minimax::MinimaxAgent;
+use neutrino::agents::monte_carlo_tree_search::{MonteCarloTreeSearch, TreeDump, TreeExportLimits};
+use neutrino::agents::registry::{AgentConfig, AgentRegistry};
+use neutrino::game_record::GameRecord;
+use neutrino::neutrino_board::{GameBoard, Player};
+use neutrino::agents::remote_agent::RemoteAgent;
+use neutrino::network::{ClientMessage, Role, ServerMessage};
+use neutrino::annotation::{Annotator, MoveQuality, Thresholds};
+use neutrino::engine_protocol::{AgentFactory, EngineAgent};
+use neutrino::tui::TuiPlayer;
+use neutrino::opening_book::{BookSelection, OpeningBook, OpeningBookBuilder};
+use neutrino::self_play::{self, DatasetFormat, DatasetWriter, SelfPlayConfig};
+use neutrino::{engine_protocol, http_api, match_runner, network, tui, GoLimits, ParseNotationError, RuleSet};
 
 fn main() {
-    let mut board: GameBoard = GameBoard::default();
-    println!("{board}");
-    //let mut minimax_agent = MinimaxAgent::new(Player::Player1); //stack overflow
-    let mut random_agent = RandomAgent::default();
-    let mut human = Human::new();
-    let time_out = Duration::from_secs(1);
-    
-    let mut num_moves = 0usize;
-
-    while !board.is_terminal() {
-        
-        let agent_move = if num_moves % 2 == 0 {random_agent.get_move(&board)} else {MonteCarloTreeSearch::new(&board, time_out).get_move(&board)};
-        //let agent_move = if num_moves % 2 == 0 {human.get_move(&board)} else {human.get_move(&board)};
-
-        board = board.result(agent_move);
-        num_moves += 1;
-        if num_moves % 1 == 0 {
-            println!("move {num_moves}");
-            println!("{board}\n");
-        }
-    }
-    println!("Terminal state!!!");
-    println!("The utility is {}", board.utility(Player::Player1).unwrap());
-    println!("{board}");
-
-    
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("book") => build_book(&args[1..]),
+        Some("selfplay") => run_self_play(&args[1..]),
+        Some("analyze") => analyze(&args[1..]),
+        Some("annotate") => annotate(&args[1..]),
+        Some("engine") => engine(&args[1..]),
+        Some("match") => play_match(&args[1..]),
+        Some("tui") => run_tui(&args[1..]),
+        Some("serve") => serve(&args[1..]),
+        Some("connect") => connect(&args[1..]),
+        Some("watch") => watch(&args[1..]),
+        Some("http") => serve_http(&args[1..]),
+        _ => play(&args),
+    }
+}
+
+/**
+ * The number given for a command line argument, or prints that the argument expects one and exits.
+ */
+fn number_argument(name: &str, value: Option<&String>) -> u64 {
+    match value.map(|value| value.parse()) {
+        Some(Ok(number)) => number,
+        Some(Err(_)) | None => {
+            eprintln!("{name} expects a number, not {}", value.map_or("nothing", String::as_str));
+            process::exit(2)
+        },
+    }
+}
+
+/**
+ * A participant of an interactive game.
+ */
+enum Participant {
+    Human(Human),
+    Agent(Box<dyn Agent>),
+}
+
+/**
+ * `[--player1 <agent>] [--player2 <agent>] [--time <ms>] [--depth <n>] [--book <file>] [--best] [--rules <rules>]
+ *  [--dump-tree <directory>] [--tree-depth <n>] [--tree-min-visits <n>]`
+ *
+ * Plays a game on the terminal, by default a random agent against MCTS. A player is `human`
+ * or any agent accepted by `make_agent`. Agents use the opening book if one is given; with `--best`
+ * the book's best scoring move is played instead of a weighted random one.
+ * Humans get hints from MCTS and can take back moves, offer draws and resign.
+ * `--rules` plays by house rules, e.g. `full-first-turn` (see `RuleSet`).
+ * With `--dump-tree` MCTS players write their search tree after each move, see `tree_dump`.
+ */
+fn play(args: &[String]) {
+    let option = |name: &str| args.iter().position(|arg| arg == name).and_then(|index| args.get(index + 1));
+    let optional_number = |name: &str| option(name).map(|value| number_argument(name, Some(value)));
+    let number = |name: &str, default: u64| optional_number(name).unwrap_or(default);
+    let search_limits = GoLimits { time: optional_number("--time").map(Duration::from_millis), depth: optional_number("--depth").map(|depth| depth as usize) };
+    let defaults = GoLimits { time: Some(Duration::from_secs(1)), depth: Some(2) };
+    let book = Arc::new(match option("--book") {
+        Some(path) => OpeningBook::load(path).unwrap_or_else(|error| panic!("could not load book {path}: {error}")),
+        None => OpeningBook::default(),
+    });
+    let selection = if args.iter().any(|arg| arg == "--best") {BookSelection::Best} else {BookSelection::Weighted};
+    let limits = TreeExportLimits {
+        max_depth: number("--tree-depth", TreeExportLimits::default().max_depth as u64) as usize,
+        min_visits: number("--tree-min-visits", TreeExportLimits::default().min_visits as u64) as usize,
+    };
+    let participant = |spec: &str, name: &str| -> Result<Participant, String> {
+        if spec == "human" {
+            let hint_engine = Box::new(MonteCarloTreeSearch::new(&GameBoard::default(), search_limits.or(defaults).time.unwrap_or_default()));
+            return Ok(Participant::Human(Human::new().with_hint_engine(hint_engine)))
+        }
+        let agent = make_agent(spec, search_limits, defaults, tree_dump(option("--dump-tree"), name, limits))?;
+        Ok(Participant::Agent(Box::new(BookAgent::new(Arc::clone(&book), agent, selection))))
+    };
+    let participants = participant(option("--player1").map_or("random", String::as_str), "player1")
+        .and_then(|player1| Ok([player1, participant(option("--player2").map_or("mcts", String::as_str), "player2")?]));
+    let mut participants = match participants {
+        Ok(participants) => participants,
+        Err(error) => {
+            eprintln!("{error}");
+            return
+        }
+    };
+
+    let rules = match parse_rules(option("--rules")) {
+        Ok(rules) => rules,
+        Err(error) => {
+            eprintln!("{error}");
+            return
+        }
+    };
+    let mut history: Vec<GameBoard> = vec![GameBoard::start(rules)];
+    println!("{}", history[0]);
+    loop {
+        let board = history.last().expect("history starts with the start position").clone();
+        if board.is_terminal() {
+            println!("Terminal state!!!");
+            println!("{}", board.outcome());
+            break
+        }
+        let (mover, opponent) = match board.to_move() {
+            Player::Player1 => (0, 1),
+            Player::Player2 => (1, 0),
+        };
+        let player_name = format!("{:?}", board.to_move());
+        let turn_move = match &mut participants[mover] {
+            Participant::Agent(agent) => match agent.try_get_move(&board) {
+                Some(turn_move) => turn_move,
+                None => {
+                    println!("{player_name} forfeits");
+                    break
+                }
+            },
+            Participant::Human(human) => match human.get_action(&board) {
+                HumanAction::Move(turn_move) => turn_move,
+                HumanAction::Resign => {
+                    println!("{player_name} resigns");
+                    break
+                },
+                HumanAction::Undo => {
+                    //go back to the position before this player's previous move
+                    if history.len() > 2 {
+                        history.truncate(history.len() - 2);
+                        println!("{player_name} took back their last move");
+                    } else {
+                        println!("there is no move to take back");
+                    }
+                    continue
+                },
+                HumanAction::OfferDraw => {
+                    let accepted = match &mut participants[opponent] {
+                        Participant::Human(opponent) => opponent.accept_draw(),
+                        //engines play on
+                        Participant::Agent(_) => false,
+                    };
+                    if accepted {
+                        println!("Draw agreed");
+                        break
+                    }
+                    println!("The draw offer was declined");
+                    continue
+                },
+            },
+        };
+        let board = board.result(turn_move.clone());
+        println!("move {}: {}", history.len(), turn_move.to_notation());
+        println!("{board}\n");
+        history.push(board);
+    }
+}
+
+/**
+ * `book <output> [--selfplay <games>] [--time <ms>] [--plies <n>] [--min-weight <n>] [--search <ms>] [record files...]`
+ *
+ * Builds an opening book from game record files and/or MCTS self-play games.
+ * With `--search` the start position is also searched deeply and its best moves added with a weight of 10 games.
+ */
+fn build_book(args: &[String]) {
+    let Some(output) = args.first() else {
+        eprintln!("usage: book <output> [--selfplay <games>] [--time <ms>] [--plies <n>] [--min-weight <n>] [--search <ms>] [record files...]");
+        return
+    };
+    let mut self_play_games = 0usize;
+    let mut time_out = Duration::from_millis(200);
+    let mut max_ply = 8usize;
+    let mut min_weight = 1u32;
+    let mut search_time: Option<Duration> = None;
+    let mut record_files: Vec<&String> = vec![];
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        let mut value = || number_argument(arg, rest.next());
+        match arg.as_str() {
+            "--selfplay" => self_play_games = value() as usize,
+            "--time" => time_out = Duration::from_millis(value()),
+            "--plies" => max_ply = value() as usize,
+            "--min-weight" => min_weight = value() as u32,
+            "--search" => search_time = Some(Duration::from_millis(value())),
+            _ => record_files.push(arg),
+        }
+    }
+
+    let mut builder = OpeningBookBuilder::new(max_ply);
+    for file in record_files {
+        match fs::read_to_string(file) {
+            Ok(text) => for (index, record) in GameRecord::parse_each(&text).into_iter().enumerate() {
+                match record {
+                    Ok(record) => builder.add_record(&record),
+                    Err(error) => eprintln!("skipping game {} of {file}: {error}", index + 1),
+                }
+            },
+            Err(error) => eprintln!("skipping {file}: {error}"),
+        }
+    }
+    for game in 0..self_play_games {
+        let board = GameBoard::default();
+        let mut player1 = MonteCarloTreeSearch::new(&board, time_out);
+        let mut player2 = MonteCarloTreeSearch::new(&board, time_out);
+        let mut record = match_runner::play_game(&mut player1, &mut player2, 200);
+        record.set_tag("Event", &format!("self-play {}", game + 1));
+        println!("self-play game {}: {} moves", game + 1, record.moves.len());
+        builder.add_record(&record);
+    }
+    if let Some(search_time) = search_time {
+        let board = GameBoard::default();
+        let analysis = MonteCarloTreeSearch::new(&board, search_time).analyze(&board, 3, &mut |_| {});
+        for line in analysis.lines {
+            builder.add_search_result(&board, line.turn_move, line.score, 10);
+        }
+    }
+
+    let book = builder.build(min_weight);
+    match book.save(output) {
+        Ok(()) => println!("wrote {} positions to {output}", book.len()),
+        Err(error) => eprintln!("could not write {output}: {error}"),
+    }
+}
+
+/**
+ * `selfplay <output> [--games <n>] [--threads <n>] [--player1 <agent>] [--player2 <agent>] [--time <ms>] [--depth <n>]
+ * [--random-plies <n>] [--max-moves <n>] [--format jsonl|binary] [--rules <rules>]`
+ *
+ * Plays games between two agents, by default MCTS against itself, on several threads and writes every
+ * searched position with the search's root visits and the game's result to a dataset, see `self_play`.
+ * The agents are any accepted by `agent_factory`. The first `--random-plies` moves of each game are random.
+ */
+fn run_self_play(args: &[String]) {
+    let Some(output) = args.first() else {
+        eprintln!("usage: selfplay <output> [--games <n>] [--threads <n>] [--player1 <agent>] [--player2 <agent>] [--time <ms>] [--depth <n>] [--random-plies <n>] [--max-moves <n>] [--format jsonl|binary] [--rules <rules>]");
+        return
+    };
+    let option = |name: &str| args.iter().position(|arg| arg == name).and_then(|index| args.get(index + 1));
+    let number = |name: &str, default: u64| option(name).map_or(default, |value| number_argument(name, Some(value)));
+    let defaults = SelfPlayConfig::default();
+    let settings = parse_rules(option("--rules"))
+        .and_then(|rules| {
+            let format = option("--format").map_or(Ok(DatasetFormat::Jsonl), |format| format.parse().map_err(|error: ParseNotationError| error.to_string()))?;
+            let search_defaults = GoLimits { time: Some(Duration::from_millis(200)), depth: Some(2) };
+            let player1 = agent_factory(option("--player1").map_or("mcts", String::as_str), search_defaults)?;
+            let player2 = agent_factory(option("--player2").map_or("mcts", String::as_str), search_defaults)?;
+            Ok((rules, format, player1, player2))
+        });
+    let (rules, format, player1, player2) = match settings {
+        Ok(settings) => settings,
+        Err(error) => {
+            eprintln!("{error}");
+            return
+        }
+    };
+    let config = SelfPlayConfig {
+        games: number("--games", defaults.games as u64) as usize,
+        threads: number("--threads", thread::available_parallelism().map_or(1, |threads| threads.get()) as u64) as usize,
+        random_plies: number("--random-plies", defaults.random_plies as u64) as usize,
+        max_moves: number("--max-moves", defaults.max_moves as u64) as usize,
+        limits: GoLimits { time: option("--time").map(|_| Duration::from_millis(number("--time", 0))), depth: option("--depth").map(|_| number("--depth", 0) as usize) },
+        rules,
+    };
+    let writer = fs::File::create(output).and_then(|file| DatasetWriter::new(io::BufWriter::new(file), format, rules));
+    let mut writer = match writer {
+        Ok(writer) => writer,
+        Err(error) => {
+            eprintln!("could not write {output}: {error}");
+            return
+        }
+    };
+    let mut samples = 0;
+    let mut failed = None;
+    self_play::run(&config, &player1, &player2, |game| {
+        println!("game {}: {} moves, {}", game.index + 1, game.record.moves.len(), game.record.outcome());
+        samples += game.samples.len();
+        if failed.is_none() && let Err(error) = writer.write_game(&game) {
+            failed = Some(error);
+        }
+    });
+    match failed.map_or_else(|| writer.finish().map(|_| ()), Err) {
+        Ok(()) => println!("wrote {samples} samples of {} games to {output}", config.games),
+        Err(error) => eprintln!("could not write {output}: {error}"),
+    }
+}
+
+/**
+ * `analyze <position|start> [--engine mcts|alphabeta] [--time <ms>] [--depth <n>] [--multipv <n>]`
+ *
+ * Prints the top moves for a position given in notation, updating while the search runs.
+ */
+fn analyze(args: &[String]) {
+    let Some(position) = args.first() else {
+        eprintln!("usage: analyze <position|start> [--engine mcts|alphabeta] [--time <ms>] [--depth <n>] [--multipv <n>]");
+        return
+    };
+    let board: GameBoard = if position == "start" {
+        GameBoard::default()
+    } else {
+        match position.parse() {
+            Ok(board) => board,
+            Err(error) => {
+                eprintln!("{error}");
+                return
+            }
+        }
+    };
+    let mut engine = "mcts";
+    let mut time_out = Duration::from_secs(5);
+    let mut max_depth = 2usize;
+    let mut multi_pv = 3usize;
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        if arg == "--engine" {
+            engine = rest.next().map(String::as_str).unwrap_or(engine);
+            continue
+        }
+        let mut value = || number_argument(arg, rest.next());
+        match arg.as_str() {
+            "--time" => time_out = Duration::from_millis(value()),
+            "--depth" => max_depth = value() as usize,
+            "--multipv" => multi_pv = value() as usize,
+            _ => eprintln!("ignoring unknown argument {arg}"),
+        }
+    }
+    if board.is_terminal() {
+        println!("The position is terminal: {}", board.outcome());
+        return
+    }
+
+    println!("{}\n{board}", board.to_notation());
+    let mut agent: Box<dyn Analyze> = match engine {
+        "alphabeta" => Box::new(MinimaxAgent::new(max_depth)),
+        _ => Box::new(MonteCarloTreeSearch::new(&board, time_out)),
+    };
+    let analysis = agent.analyze(&board, multi_pv, &mut |analysis| println!("{analysis}"));
+    println!("final:\n{analysis}");
+}
+
+/**
+ * `annotate <record file> [--engine mcts|alphabeta] [--time <ms>] [--depth <n>] [--win-depth <n>] [--output <file>]`
+ *
+ * Analyses every position of the recorded games and writes them back with comments on the inaccuracies,
+ * mistakes, blunders and missed forced wins.
+ */
+fn annotate(args: &[String]) {
+    let Some(input) = args.first() else {
+        eprintln!("usage: annotate <record file> [--engine mcts|alphabeta] [--time <ms>] [--depth <n>] [--win-depth <n>] [--output <file>]");
+        return
+    };
+    let mut engine = "mcts";
+    let mut output: Option<&String> = None;
+    let mut time_out = Duration::from_millis(500);
+    let mut max_depth = 2usize;
+    let mut win_depth = 1usize;
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--engine" => engine = rest.next().map(String::as_str).unwrap_or(engine),
+            "--output" => output = rest.next(),
+            _ => {
+                let value = number_argument(arg, rest.next());
+                match arg.as_str() {
+                    "--time" => time_out = Duration::from_millis(value),
+                    "--depth" => max_depth = value as usize,
+                    "--win-depth" => win_depth = value as usize,
+                    _ => eprintln!("ignoring unknown argument {arg}"),
+                }
+            }
+        }
+    }
+    let records: Vec<GameRecord> = match fs::read_to_string(input) {
+        Ok(text) => GameRecord::parse_each(&text).into_iter()
+            .enumerate()
+            .filter_map(|(index, record)| record.map_err(|error| eprintln!("skipping game {} of {input}: {error}", index + 1)).ok())
+            .collect(),
+        Err(error) => {
+            eprintln!("could not read {input}: {error}");
+            return
+        }
+    };
+
+    let mut annotated_records = String::new();
+    for record in &records {
+        let (annotated, annotations) = match engine {
+            "alphabeta" => Annotator::new(MinimaxAgent::new(max_depth), Thresholds::default(), win_depth).annotated_record(record),
+            _ => Annotator::new(MonteCarloTreeSearch::new(&GameBoard::default(), time_out), Thresholds::default(), win_depth).annotated_record(record),
+        };
+        for annotation in annotations.iter().filter(|annotation| annotation.quality != MoveQuality::Good) {
+            println!("move {} {}: {}", annotation.ply + 1, annotation.played.to_notation(), annotated.comments[&annotation.ply]);
+        }
+        annotated_records.push_str(&annotated.to_string());
+        annotated_records.push('\n');
+    }
+    match output {
+        Some(output) => if let Err(error) = fs::write(output, annotated_records) {
+            eprintln!("could not write {output}: {error}");
+        },
+        None => print!("{annotated_records}"),
+    }
+}
+
+/**
+ * Creates agents on demand for searches running on other threads from a description (see `make_agent`),
+ * e.g. `mcts`, `alphabeta:depth=4` or a `.toml` file. Limits given with a search win over the description,
+ * and `defaults` apply where neither sets a limit (see `default_limits`).
+ */
+fn agent_factory(spec: &str, defaults: GoLimits) -> Result<AgentFactory, String> {
+    let config = AgentConfig::from_spec(spec).map_err(|error| error.to_string())?;
+    let factory = AgentRegistry::standard().factory(&config).map_err(|error| error.to_string())?;
+    let defaults = default_limits(&config, defaults);
+    Ok(Arc::new(move |board, limits| factory(board, limits.or(defaults))))
+}
+
+/**
+ * The command's default limits that apply to a described agent: limits given on the command line or with a search
+ * win over the description's parameters, as they do when an agent is built (see `BuildAgent`), and the description
+ * wins over the command's defaults. Without any of them MCTS thinks for a second and alpha-beta searches 2 plies.
+ */
+fn default_limits(config: &AgentConfig, defaults: GoLimits) -> GoLimits {
+    GoLimits {
+        time: defaults.time.filter(|_| !config.parameters.contains_key("time")),
+        depth: defaults.depth.filter(|_| !config.parameters.contains_key("depth")),
+    }
+}
+
+/**
+ * `engine [--engine <agent>]`
+ *
+ * Speaks the engine protocol on stdin/stdout, see `engine_protocol`. The agent is any accepted by `agent_factory`.
+ */
+fn engine(args: &[String]) {
+    let engine = args.iter()
+        .position(|arg| arg == "--engine")
+        .and_then(|index| args.get(index + 1))
+        .map_or("mcts", String::as_str)
+        .to_string();
+    let factory = match agent_factory(&engine, GoLimits::default()) {
+        Ok(factory) => factory,
+        Err(error) => {
+            eprintln!("{error}");
+            return
+        }
+    };
+    engine_protocol::run_engine(&format!("neutrino {engine}"), io::stdin().lock(), io::stdout(), factory);
+}
+
+/**
+ * Where an MCTS player called `name` writes its search trees, given the `--dump-tree` directory:
+ * `<directory>/<name>-<n>.dot` and `.json` after its nth move.
+ */
+fn tree_dump(directory: Option<&String>, name: &str, limits: TreeExportLimits) -> Option<TreeDump> {
+    directory.map(|directory| TreeDump { directory: PathBuf::from(directory), name: name.to_string(), limits })
+}
+
+/**
+ * Creates an agent from a command line description, see `AgentConfig::from_spec` and `AgentRegistry`:
+ * `random`, `mcts`, `alphabeta` or `external:<program>[,<argument>...]`, with parameters such as
+ * `mcts:c=1.2,playouts=5000,heavy=true`, or a `.toml` or `.json` file describing the agent.
+ * The `limits` given on the command line win over the description, and `defaults` apply where neither sets a limit.
+ * MCTS writes its search tree after each move if a tree dump is given.
+ */
+fn make_agent(spec: &str, limits: GoLimits, defaults: GoLimits, tree_dump: Option<TreeDump>) -> Result<Box<dyn Agent>, String> {
+    let mut config = AgentConfig::from_spec(spec).map_err(|error| error.to_string())?;
+    if let Some(tree_dump) = tree_dump
+        && config.agent == "mcts" {
+        config = config
+            .with_parameter("tree_dump", tree_dump.directory.display().to_string())
+            .with_parameter("tree_name", tree_dump.name)
+            .with_parameter("tree_depth", tree_dump.limits.max_depth)
+            .with_parameter("tree_min_visits", tree_dump.limits.min_visits);
+    }
+    let limits = limits.or(default_limits(&config, defaults));
+    AgentRegistry::standard()
+        .build(&config, &GameBoard::default(), limits)
+        .map(EngineAgent::into_agent)
+        .map_err(|error| error.to_string())
+}
+
+/**
+ * The rules given with `--rules`, the standard rules without it.
+ */
+fn parse_rules(rules: Option<&String>) -> Result<RuleSet, String> {
+    rules.map_or(Ok(RuleSet::default()), |rules| rules.parse().map_err(|error: ParseNotationError| error.to_string()))
+}
+
+/**
+ * `match <agent> <agent> [--games <n>] [--time <ms>] [--depth <n>] [--max-moves <n>] [--output <file>] [--rules <rules>]
+ *  [--dump-tree <directory>] [--tree-depth <n>] [--tree-min-visits <n>] [--log]`
+ *
+ * Plays games between two agents, alternating colours, and prints the score. See `make_agent` for the agents.
+ * With `--log` every move is printed with the mover's search report, which is also kept as the move's comment.
+ * With `--dump-tree` MCTS agents write their search tree after each move as `first-<n>` and `second-<n>`.
+ */
+fn play_match(args: &[String]) {
+    let [first, second, ..] = args else {
+        eprintln!("usage: match <agent> <agent> [--games <n>] [--time <ms>] [--depth <n>] [--max-moves <n>] [--output <file>] [--rules <rules>] [--dump-tree <directory>] [--tree-depth <n>] [--tree-min-visits <n>] [--log]");
+        return
+    };
+    let mut games = 2usize;
+    let mut search_limits = GoLimits::default();
+    let mut max_moves = 200usize;
+    let mut output: Option<&String> = None;
+    let mut dump_directory: Option<&String> = None;
+    let mut log = false;
+    let mut limits = TreeExportLimits::default();
+    let mut rules = RuleSet::default();
+    let mut rest = args[2..].iter();
+    while let Some(arg) = rest.next() {
+        if arg == "--output" {
+            output = rest.next();
+            continue
+        }
+        if arg == "--dump-tree" {
+            dump_directory = rest.next();
+            continue
+        }
+        if arg == "--log" {
+            log = true;
+            continue
+        }
+        if arg == "--rules" {
+            match parse_rules(rest.next()) {
+                Ok(parsed) => rules = parsed,
+                Err(error) => {
+                    eprintln!("{error}");
+                    return
+                }
+            }
+            continue
+        }
+        let value = number_argument(arg, rest.next());
+        match arg.as_str() {
+            "--games" => games = value as usize,
+            "--time" => search_limits.time = Some(Duration::from_millis(value)),
+            "--depth" => search_limits.depth = Some(value as usize),
+            "--max-moves" => max_moves = value as usize,
+            "--tree-depth" => limits.max_depth = value as usize,
+            "--tree-min-visits" => limits.min_visits = value as usize,
+            _ => eprintln!("ignoring unknown argument {arg}"),
+        }
+    }
+    let defaults = GoLimits { time: Some(Duration::from_millis(500)), depth: Some(2) };
+    let agents = make_agent(first, search_limits, defaults, tree_dump(dump_directory, "first", limits))
+        .and_then(|first| Ok((first, make_agent(second, search_limits, defaults, tree_dump(dump_directory, "second", limits))?)));
+    let (mut first_agent, mut second_agent) = match agents {
+        Ok(agents) => agents,
+        Err(error) => {
+            eprintln!("{error}");
+            return
+        }
+    };
+
+    let mut first_score = 0.0;
+    let mut records = String::new();
+    for game in 0..games {
+        //the agents swap colours every game
+        let first_is_player1 = game % 2 == 0;
+        let mut on_move = |record: &mut GameRecord, board: &GameBoard, report: &SearchReport| {
+            if !log {
+                return
+            }
+            println!("move {}: {:?} {} {report}", record.moves.len() + 1, board.to_move(), report.turn_move.to_notation());
+            //moves found without searching have nothing to comment on
+            if report.score.is_some() {
+                record.comments.insert(record.moves.len(), report.to_string());
+            }
+        };
+        let mut record = if first_is_player1 {
+            match_runner::play_game_with_reports(rules, first_agent.as_mut(), second_agent.as_mut(), max_moves, &mut on_move)
+        } else {
+            match_runner::play_game_with_reports(rules, second_agent.as_mut(), first_agent.as_mut(), max_moves, &mut on_move)
+        };
+        let (player1, player2) = if first_is_player1 {(first, second)} else {(second, first)};
+        record.set_tag("Player1", player1);
+        record.set_tag("Player2", player2);
+        let player = if first_is_player1 {Player::Player1} else {Player::Player2};
+        first_score += record.result_for(player).unwrap_or(0.5);
+        println!("game {}: {player1} vs {player2}, {} moves, {}", game + 1, record.moves.len(), record.outcome());
+        records.push_str(&record.to_string());
+        records.push('\n');
+    }
+    println!("{first} {first_score} - {} {second}", games as f64 - first_score);
+    if let Some(output) = output
+        && let Err(error) = fs::write(output, records) {
+        eprintln!("could not write {output}: {error}");
+    }
+}
+
+/**
+ * `tui [--player1 <agent>] [--player2 <agent>] [--time <ms>] [--clock <seconds>] [--rules <rules>]`
+ *
+ * Plays or watches a game in a full-screen terminal UI, by default a human against MCTS.
+ * A player is `human` or any agent accepted by `agent_factory`; `--time` is the engines' time per move,
+ * over any time their descriptions set, and `--clock` gives each player a time control.
+ */
+fn run_tui(args: &[String]) {
+    let option = |name: &str| args.iter().position(|arg| arg == name).and_then(|index| args.get(index + 1));
+    let number = |name: &str, default: u64| option(name).map_or(default, |value| number_argument(name, Some(value)));
+    let player = |spec: &str| -> Result<TuiPlayer, String> {
+        match spec {
+            "human" => Ok(TuiPlayer::Human),
+            _ => agent_factory(spec, GoLimits::default()).map(TuiPlayer::Engine),
+        }
+    };
+    let players = player(option("--player1").map_or("human", String::as_str))
+        .and_then(|player1| Ok([player1, player(option("--player2").map_or("mcts", String::as_str))?]));
+    let players = match players {
+        Ok(players) => players,
+        Err(error) => {
+            eprintln!("{error}");
+            return
+        }
+    };
+    let move_time = option("--time").map(|_| Duration::from_millis(number("--time", 0)));
+    let time_control = option("--clock").map(|_| Duration::from_secs(number("--clock", 300)));
+    let rules = match parse_rules(option("--rules")) {
+        Ok(rules) => rules,
+        Err(error) => {
+            eprintln!("{error}");
+            return
+        }
+    };
+    if let Err(error) = tui::run(players, agent_factory("mcts", GoLimits::default()).ok(), move_time, time_control, rules) {
+        eprintln!("terminal error: {error}");
+    }
+}
+
+/**
+ * `serve [--port <port>] [--clock <seconds>] [--rules <rules>]`
+ *
+ * Hosts network games on all interfaces, by default on port 7878. With `--clock` each player
+ * gets a time control and loses when it runs out. See `network` for the protocol.
+ */
+fn serve(args: &[String]) {
+    let option = |name: &str| args.iter().position(|arg| arg == name).and_then(|index| args.get(index + 1));
+    let number = |name: &str, default: u64| option(name).map_or(default, |value| number_argument(name, Some(value)));
+    let port = option("--port").map_or(7878, |port| port.parse::<u16>().unwrap_or_else(|_| panic!("--port expects a port number")));
+    let time_control = option("--clock").map(|_| Duration::from_secs(number("--clock", 300)));
+    let rules = match parse_rules(option("--rules")) {
+        Ok(rules) => rules,
+        Err(error) => {
+            eprintln!("{error}");
+            return
+        }
+    };
+    match TcpListener::bind(("0.0.0.0", port)) {
+        Ok(listener) => {
+            println!("serving games on port {port}");
+            network::serve(listener, time_control, rules);
+        },
+        Err(error) => eprintln!("could not listen on port {port}: {error}"),
+    }
+}
+
+/**
+ * `connect <address> <game> player1|player2 [--agent <agent>] [--time <ms>] [--depth <n>]`
+ *
+ * Plays a game hosted by a server, by default as a human. The agent is `human` or any agent accepted by `make_agent`.
+ */
+fn connect(args: &[String]) {
+    let [address, game, side, ..] = args else {
+        eprintln!("usage: connect <address> <game> player1|player2 [--agent <agent>] [--time <ms>] [--depth <n>]");
+        return
+    };
+    let option = |name: &str| args.iter().position(|arg| arg == name).and_then(|index| args.get(index + 1));
+    let number = |name: &str, default: u64| option(name).map_or(default, |value| number_argument(name, Some(value)));
+    let local = match side.parse::<Role>() {
+        Ok(Role::Player(player)) => player,
+        _ => {
+            eprintln!("play as player1 or player2");
+            return
+        }
+    };
+    let search_limits = GoLimits {
+        time: option("--time").map(|_| Duration::from_millis(number("--time", 0))),
+        depth: option("--depth").map(|_| number("--depth", 0) as usize),
+    };
+    let defaults = GoLimits { time: Some(Duration::from_secs(1)), depth: Some(2) };
+    let spec = option("--agent").map_or("human", String::as_str);
+    let local_agent = match spec {
+        "human" => Ok(Box::new(Human::new()) as Box<dyn Agent>),
+        _ => make_agent(spec, search_limits, defaults, None),
+    };
+    let agents = local_agent.and_then(|local_agent| Ok((local_agent, RemoteAgent::connect(address.as_str(), game, local)?)));
+    let (mut local_agent, mut remote) = match agents {
+        Ok(agents) => agents,
+        Err(error) => {
+            eprintln!("{error}");
+            return
+        }
+    };
+    println!("joined {game} as {side}, waiting for the game to start");
+    let mut record = match local {
+        Player::Player1 => match_runner::play_game_with_rules(remote.rules(), local_agent.as_mut(), &mut remote, usize::MAX),
+        Player::Player2 => match_runner::play_game_with_rules(remote.rules(), &mut remote, local_agent.as_mut(), usize::MAX),
+    };
+    let final_board = record.positions().pop().expect("positions include the final board");
+    let result = remote.finish(&final_board);
+    //the server knows best how the game ended, e.g. on time
+    if let Ok(outcome) = &result {
+        record.set_outcome(outcome);
+    }
+    print!("{record}");
+    if let Some((player1, player2)) = remote.clocks() {
+        println!("clocks: {:.1}s {:.1}s", player1.as_secs_f64(), player2.as_secs_f64());
+    }
+    match result {
+        Ok(outcome) => println!("the server ended the game: {outcome}"),
+        Err(error) => eprintln!("{error}"),
+    }
+}
+
+/**
+ * `watch <address> <game>`
+ *
+ * Follows a game hosted by a server as a spectator, printing the board after every move.
+ */
+fn watch(args: &[String]) {
+    let [address, game, ..] = args else {
+        eprintln!("usage: watch <address> <game>");
+        return
+    };
+    let stream = match TcpStream::connect(address.as_str()) {
+        Ok(stream) => stream,
+        Err(error) => {
+            eprintln!("could not connect: {error}");
+            return
+        }
+    };
+    let mut writer = stream.try_clone().expect("tcp streams can be cloned");
+    if let Err(error) = writeln!(writer, "{}", ClientMessage::Join(game.clone(), Role::Spectator)) {
+        eprintln!("connection lost: {error}");
+        return
+    }
+    let mut board = GameBoard::default();
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else { break };
+        match line.parse::<ServerMessage>() {
+            Ok(ServerMessage::Position(position)) => board = *position,
+            Ok(ServerMessage::Start) => println!("the game has started\n{board}"),
+            Ok(ServerMessage::Move(turn_move)) => match board.try_result(&turn_move) {
+                Ok(next) => {
+                    board = next;
+                    println!("{}\n{board}", turn_move.to_notation());
+                },
+                Err(error) => {
+                    eprintln!("the server sent the illegal move {}: {error}", turn_move.to_notation());
+                    break
+                },
+            },
+            Ok(ServerMessage::Clock(player1, player2)) => println!("clocks: {:.1}s {:.1}s", player1.as_secs_f64(), player2.as_secs_f64()),
+            Ok(ServerMessage::GameOver(outcome)) => {
+                println!("game over: {outcome}");
+                break
+            },
+            Ok(ServerMessage::Error(error)) => {
+                eprintln!("{error}");
+                break
+            },
+            _ => {},
+        }
+    }
+}
+
+/**
+ * `http [--port <port>] [--engine <agent>]`
+ *
+ * Serves the JSON API on all interfaces, by default on port 8080 with MCTS as the engine. See `http_api` for the endpoints.
+ */
+fn serve_http(args: &[String]) {
+    let option = |name: &str| args.iter().position(|arg| arg == name).and_then(|index| args.get(index + 1));
+    let port = option("--port").map_or(8080, |port| port.parse::<u16>().unwrap_or_else(|_| panic!("--port expects a port number")));
+    let engine = match agent_factory(option("--engine").map_or("mcts", String::as_str), GoLimits::default()) {
+        Ok(engine) => engine,
+        Err(error) => {
+            eprintln!("{error}");
+            return
+        }
+    };
+    match TcpListener::bind(("0.0.0.0", port)) {
+        Ok(listener) => {
+            println!("serving the API on port {port}");
+            http_api::serve(listener, engine);
+        },
+        Err(error) => eprintln!("could not listen on port {port}: {error}"),
+    }
 }
@@ -0,0 +1,83 @@
+use crate::{agents::{agent::Agent, analysis::SearchReport}, game_record::GameRecord, neutrino_board::{GameBoard, Player}, outcome::{Outcome, Reason}, rules::RuleSet};
+
+/**
+ * Plays a game between two agents from the default start position.
+ * The game is drawn by the move limit after `max_moves` moves.
+ * An agent that forfeits (see `Agent::try_get_move`) or plays an illegal move loses the game.
+ */
+pub fn play_game(player1: &mut dyn Agent, player2: &mut dyn Agent, max_moves: usize) -> GameRecord {
+    play_game_with_rules(RuleSet::default(), player1, player2, max_moves)
+}
+
+/**
+ * Plays a game like `play_game`, by the given rules.
+ */
+pub fn play_game_with_rules(rules: RuleSet, player1: &mut dyn Agent, player2: &mut dyn Agent, max_moves: usize) -> GameRecord {
+    play_game_with_reports(rules, player1, player2, max_moves, &mut |_, _, _| {})
+}
+
+/**
+ * Plays a game like `play_game_with_rules`, calling `on_move` with the record so far, the board
+ * and the mover's `SearchReport` before each move is played, e.g. to log the agents' evaluations.
+ */
+pub fn play_game_with_reports(rules: RuleSet, player1: &mut dyn Agent, player2: &mut dyn Agent, max_moves: usize,
+    on_move: &mut dyn FnMut(&mut GameRecord, &GameBoard, &SearchReport)) -> GameRecord {
+    let mut board = GameBoard::start(rules);
+    let mut record = GameRecord::new();
+    record.set_rules(rules);
+    while !board.is_terminal() && record.moves.len() < max_moves {
+        let report = match board.to_move() {
+            Player::Player1 => player1.search(&board, &mut |_| {}),
+            Player::Player2 => player2.search(&board, &mut |_| {}),
+        };
+        let Some(report) = report else {
+            record.set_outcome(&Outcome::Win(board.to_move().opponent(), Reason::Forfeit));
+            return record
+        };
+        let Ok(next) = board.try_result(&report.turn_move) else {
+            record.set_outcome(&Outcome::Win(board.to_move().opponent(), Reason::Forfeit));
+            return record
+        };
+        on_move(&mut record, &board, &report);
+        board = next;
+        record.moves.push(report.turn_move);
+    }
+    let outcome = match board.outcome() {
+        Outcome::Ongoing => Outcome::Draw(Reason::MoveLimit),
+        outcome => outcome,
+    };
+    record.set_outcome(&outcome);
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::neutrino_board::TurnMove;
+
+    /**
+     * Plays its moves in order, whether they are legal or not.
+     */
+    struct Scripted(Vec<TurnMove>);
+
+    impl Agent for Scripted {
+        fn get_move(&mut self, _board: &GameBoard) -> TurnMove {
+            self.0.remove(0)
+        }
+    }
+
+    #[test]
+    fn illegal_moves_forfeit_the_game() {
+        let scripted = |moves: &[&str]| Scripted(moves.iter().map(|turn_move| turn_move.parse().unwrap()).collect());
+        //Player1's second turn leaves out the neutrino move
+        let (mut player1, mut player2) = (scripted(&["a1a4", "a4a1"]), scripted(&["c3c4/b5b2"]));
+        let mut reported = vec![];
+        let record = play_game_with_reports(RuleSet::default(), &mut player1, &mut player2, 10,
+            &mut |_, _, report| reported.push(report.turn_move.clone()));
+        assert_eq!(record.outcome(), Outcome::Win(Player::Player2, Reason::Forfeit));
+        //the illegal move is neither played nor reported
+        assert_eq!(record.moves.len(), 2);
+        assert_eq!(reported, record.moves);
+        assert!(record.to_string().contains("[Termination \"forfeit\"]"));
+    }
+}
@@ -0,0 +1,568 @@
+use std::{collections::HashMap, fmt::Display, io::{self, BufRead, BufReader, Read, Write}, net::{TcpListener, TcpStream}, str::FromStr, sync::{atomic::{AtomicUsize, Ordering}, mpsc::{self, Sender}, Arc, Mutex}, thread, time::{Duration, Instant}};
+
+use crate::{game_record::result_to_notation, neutrino_board::{GameBoard, Player, TurnMove}, notation::ParseNotationError, outcome::{Outcome, Reason}, rules::RuleSet};
+
+/*
+ * A line-based protocol for playing games over TCP.
+ *
+ * Client to server:
+ *   join <game> player1|player2|spectator   the game is created by the first client joining it
+ *   move <move>
+ *   resign
+ *
+ * Server to client:
+ *   joined <game> <role>
+ *   position [rules <rules>] <position>       the start position, followed by a `move` for every move played so far;
+ *                                             the rules are only sent when they are not the standard rules
+ *   start                                     both players are present, Player1 is to move
+ *   move <move>                               a move was played, sent to everyone including the mover
+ *   clock <ms> <ms>                           the time used by, or left for, Player1 and Player2
+ *   gameover 1-0|0-1 <reason>                 the reason as in the Termination tag of records, e.g. `home-row` or `timeout`
+ *   error <text>
+ *
+ * Lines longer than `MAX_LINE_LENGTH` bytes are rejected and the connection is closed.
+ */
+
+/// The longest line a client may send, in bytes without the line break.
+pub const MAX_LINE_LENGTH: usize = 1024;
+
+/**
+ * How a client takes part in a game.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    Player(Player),
+    Spectator,
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::Player(Player::Player1) => write!(f, "player1"),
+            Role::Player(Player::Player2) => write!(f, "player2"),
+            Role::Spectator => write!(f, "spectator"),
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = ParseNotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "player1" => Ok(Role::Player(Player::Player1)),
+            "player2" => Ok(Role::Player(Player::Player2)),
+            "spectator" => Ok(Role::Spectator),
+            _ => Err(ParseNotationError::new(s, "expected player1, player2 or spectator")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientMessage {
+    Join(String, Role),
+    Move(TurnMove),
+    Resign,
+}
+
+impl Display for ClientMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientMessage::Join(game, role) => write!(f, "join {game} {role}"),
+            ClientMessage::Move(turn_move) => write!(f, "move {}", turn_move.to_notation()),
+            ClientMessage::Resign => write!(f, "resign"),
+        }
+    }
+}
+
+impl FromStr for ClientMessage {
+    type Err = ParseNotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["join", game, role] => Ok(ClientMessage::Join(game.to_string(), role.parse()?)),
+            ["move", turn_move] => Ok(ClientMessage::Move(turn_move.parse()?)),
+            ["resign"] => Ok(ClientMessage::Resign),
+            _ => Err(ParseNotationError::new(s, "unknown message")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ServerMessage {
+    Joined(String, Role),
+    Position(Box<GameBoard>),
+    Start,
+    Move(TurnMove),
+    Clock(Duration, Duration),
+    /// How the game ended; never `Outcome::Ongoing`.
+    GameOver(Outcome),
+    Error(String),
+}
+
+impl Display for ServerMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerMessage::Joined(game, role) => write!(f, "joined {game} {role}"),
+            ServerMessage::Position(board) if board.rules() == RuleSet::STANDARD => write!(f, "position {}", board.to_notation()),
+            ServerMessage::Position(board) => write!(f, "position rules {} {}", board.rules(), board.to_notation()),
+            ServerMessage::Start => write!(f, "start"),
+            ServerMessage::Move(turn_move) => write!(f, "move {}", turn_move.to_notation()),
+            ServerMessage::Clock(player1, player2) => write!(f, "clock {} {}", player1.as_millis(), player2.as_millis()),
+            ServerMessage::GameOver(outcome) => {
+                let reason = outcome.reason().map_or("", |reason| reason.to_notation());
+                write!(f, "gameover {} {reason}", result_to_notation(outcome.utility(Player::Player1)))
+            },
+            ServerMessage::Error(text) => write!(f, "error {text}"),
+        }
+    }
+}
+
+impl FromStr for ServerMessage {
+    type Err = ParseNotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (keyword, rest) = s.trim().split_once(' ').unwrap_or((s.trim(), ""));
+        let millis = |value: &str| value.parse().map(Duration::from_millis).map_err(|_| ParseNotationError::new(s, "expected milliseconds"));
+        match (keyword, rest.split_whitespace().collect::<Vec<_>>().as_slice()) {
+            ("joined", [game, role]) => Ok(ServerMessage::Joined(game.to_string(), role.parse()?)),
+            ("position", ["rules", rules, rows, to_move]) => {
+                let board = format!("{rows} {to_move}").parse::<GameBoard>()?;
+                Ok(ServerMessage::Position(Box::new(board.with_rules(rules.parse()?))))
+            },
+            ("position", _) => Ok(ServerMessage::Position(Box::new(rest.parse()?))),
+            ("start", []) => Ok(ServerMessage::Start),
+            ("move", [turn_move]) => Ok(ServerMessage::Move(turn_move.parse()?)),
+            ("clock", [player1, player2]) => Ok(ServerMessage::Clock(millis(player1)?, millis(player2)?)),
+            ("gameover", [result, reason]) => {
+                let reason: Reason = reason.parse()?;
+                match *result {
+                    "1-0" => Ok(ServerMessage::GameOver(Outcome::Win(Player::Player1, reason))),
+                    "0-1" => Ok(ServerMessage::GameOver(Outcome::Win(Player::Player2, reason))),
+                    "1/2-1/2" => Ok(ServerMessage::GameOver(Outcome::Draw(reason))),
+                    _ => Err(ParseNotationError::new(s, "unknown result")),
+                }
+            },
+            ("error", _) => Ok(ServerMessage::Error(rest.to_string())),
+            _ => Err(ParseNotationError::new(s, "unknown message")),
+        }
+    }
+}
+
+fn player_index(player: Player) -> usize {
+    match player {
+        Player::Player1 => 0,
+        Player::Player2 => 1,
+    }
+}
+
+/**
+ * The sending end of a connection. Messages are queued and written by a thread of their own,
+ * so a slow or stalled client never blocks the server while it holds the games lock.
+ * The queue is closed once the connection fails or every `Outbox` of it is dropped.
+ */
+#[derive(Clone)]
+struct Outbox {
+    id: usize,
+    sender: Sender<String>,
+}
+
+impl Outbox {
+    fn spawn(mut stream: TcpStream) -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::channel::<String>();
+        thread::spawn(move || {
+            for line in receiver {
+                if writeln!(stream, "{line}").is_err() {
+                    break
+                }
+            }
+        });
+        Self { id: NEXT_ID.fetch_add(1, Ordering::Relaxed), sender }
+    }
+
+    /**
+     * Queues a message, returning whether the connection is still open.
+     */
+    fn send(&self, message: &ServerMessage) -> bool {
+        self.sender.send(message.to_string()).is_ok()
+    }
+}
+
+/**
+ * A game hosted by the server and the connections taking part in it.
+ */
+struct HostedGame {
+    board: GameBoard,
+    moves: Vec<TurnMove>,
+    players: [Option<Outbox>; 2],
+    spectators: Vec<Outbox>,
+    /// Time used by each player, or left with a time control, not counting the current turn.
+    clocks: [Duration; 2],
+    time_control: Option<Duration>,
+    turn_started: Option<Instant>,
+    over: bool,
+}
+
+impl HostedGame {
+    fn new(time_control: Option<Duration>, rules: RuleSet) -> Self {
+        Self {
+            board: GameBoard::start(rules),
+            moves: vec![],
+            players: [None, None],
+            spectators: vec![],
+            clocks: [time_control.unwrap_or_default(); 2],
+            time_control,
+            turn_started: None,
+            over: false,
+        }
+    }
+
+    /**
+     * Sends a message to the players and spectators, dropping the spectators whose connection is gone.
+     * Players stay seated until their own connection handler notices and forfeits their game.
+     */
+    fn broadcast(&mut self, message: &ServerMessage) {
+        for player in self.players.iter().flatten() {
+            player.send(message);
+        }
+        self.spectators.retain(|spectator| spectator.send(message));
+    }
+
+    /**
+     * The clock of a player including the running turn.
+     */
+    fn clock(&self, player: Player) -> Duration {
+        let index = player_index(player);
+        let elapsed = match self.turn_started {
+            Some(started) if !self.over && self.board.to_move() == player => started.elapsed(),
+            _ => Duration::ZERO,
+        };
+        match self.time_control {
+            Some(_) => self.clocks[index].saturating_sub(elapsed),
+            None => self.clocks[index] + elapsed,
+        }
+    }
+
+    fn broadcast_clock(&mut self) {
+        let message = ServerMessage::Clock(self.clock(Player::Player1), self.clock(Player::Player2));
+        self.broadcast(&message);
+    }
+
+    fn finish(&mut self, outcome: Outcome) {
+        self.over = true;
+        self.broadcast(&ServerMessage::GameOver(outcome));
+    }
+
+    fn play(&mut self, player: Player, turn_move: TurnMove) -> Result<(), String> {
+        if self.over {
+            return Err("the game is over".to_string())
+        }
+        if self.turn_started.is_none() {
+            return Err("the game has not started".to_string())
+        }
+        if self.board.to_move() != player {
+            return Err("it is not your turn".to_string())
+        }
+        let board = self.board.try_result(&turn_move)
+            .map_err(|error| format!("illegal move {}: {error}", turn_move.to_notation()))?;
+        self.clocks[player_index(player)] = self.clock(player);
+        self.turn_started = Some(Instant::now());
+        self.board = board;
+        self.moves.push(turn_move.clone());
+        self.broadcast(&ServerMessage::Move(turn_move));
+        self.broadcast_clock();
+        let outcome = self.board.outcome();
+        if outcome.is_over() {
+            self.finish(outcome);
+        }
+        Ok(())
+    }
+
+    fn check_flag(&mut self) {
+        if self.over || self.turn_started.is_none() || self.time_control.is_none() {
+            return
+        }
+        let to_move = self.board.to_move();
+        if self.clock(to_move).is_zero() {
+            self.finish(Outcome::Win(to_move.opponent(), Reason::Timeout));
+        }
+    }
+}
+
+type Games = Arc<Mutex<HashMap<String, HostedGame>>>;
+
+/**
+ * Hosts games played by the given rules for the clients connecting to the listener, until the listener fails.
+ * Every client runs on its own thread; clocks are checked every 50ms.
+ */
+pub fn serve(listener: TcpListener, time_control: Option<Duration>, rules: RuleSet) {
+    let games: Games = Arc::new(Mutex::new(HashMap::new()));
+    let flag_games = Arc::clone(&games);
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(50));
+        let mut games = flag_games.lock().expect("games lock poisoned");
+        games.values_mut().for_each(HostedGame::check_flag);
+    });
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else { break };
+        let games = Arc::clone(&games);
+        thread::spawn(move || handle_client(stream, games, time_control, rules));
+    }
+}
+
+/**
+ * Reads a line without its line break, `None` at the end of the stream.
+ * Fails on lines longer than `MAX_LINE_LENGTH` bytes without reading past the limit.
+ */
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if reader.take(MAX_LINE_LENGTH as u64 + 2).read_line(&mut line)? == 0 {
+        return Ok(None)
+    }
+    let line = line.strip_suffix('\n').map(|line| line.strip_suffix('\r').unwrap_or(line)).unwrap_or(&line);
+    if line.len() > MAX_LINE_LENGTH {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("lines are at most {MAX_LINE_LENGTH} bytes long")))
+    }
+    Ok(Some(line.to_string()))
+}
+
+fn handle_client(stream: TcpStream, games: Games, time_control: Option<Duration>, rules: RuleSet) {
+    let Ok(writer) = stream.try_clone() else { return };
+    let outbox = Outbox::spawn(writer);
+    let mut reader = BufReader::new(stream);
+    let mut joined: Option<(String, Role)> = None;
+    loop {
+        let line = match read_line(&mut reader) {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(error) => {
+                outbox.send(&ServerMessage::Error(error.to_string()));
+                break
+            }
+        };
+        if line.trim().is_empty() {
+            continue
+        }
+        let message = match line.parse::<ClientMessage>() {
+            Ok(message) => message,
+            Err(error) => {
+                outbox.send(&ServerMessage::Error(error.to_string()));
+                continue
+            }
+        };
+        let mut games = games.lock().expect("games lock poisoned");
+        let reply = match (message, &joined) {
+            (ClientMessage::Join(name, role), None) => {
+                let game = games.entry(name.clone()).or_insert_with(|| HostedGame::new(time_control, rules));
+                join(game, &name, role, &outbox).map(|_| joined = Some((name, role)))
+            },
+            (ClientMessage::Join(_, _), Some(_)) => Err("already joined a game".to_string()),
+            (ClientMessage::Move(turn_move), Some((name, Role::Player(player)))) => {
+                games.get_mut(name).expect("joined games exist").play(*player, turn_move)
+            },
+            (ClientMessage::Resign, Some((name, Role::Player(player)))) => {
+                let game = games.get_mut(name).expect("joined games exist");
+                if game.over {
+                    Err("the game is over".to_string())
+                } else {
+                    game.finish(Outcome::Win(player.opponent(), Reason::Resignation));
+                    Ok(())
+                }
+            },
+            (_, _) => Err("join a game as a player first".to_string()),
+        };
+        if let Err(error) = reply {
+            outbox.send(&ServerMessage::Error(error));
+        }
+    }
+
+    //a player leaving a running game forfeits it, a spectator is just dropped
+    let Some((name, role)) = joined else { return };
+    let mut games = games.lock().expect("games lock poisoned");
+    let game = games.get_mut(&name).expect("joined games exist");
+    match role {
+        Role::Player(player) => {
+            game.players[player_index(player)] = None;
+            if !game.over && game.turn_started.is_some() {
+                game.finish(Outcome::Win(player.opponent(), Reason::Disconnect));
+            }
+        },
+        Role::Spectator => game.spectators.retain(|spectator| spectator.id != outbox.id),
+    }
+}
+
+/**
+ * Adds a connection to a game, catches it up on the moves played so far and starts the game once both players are there.
+ */
+fn join(game: &mut HostedGame, name: &str, role: Role, connection: &Outbox) -> Result<(), String> {
+    match role {
+        Role::Player(player) => {
+            let seat = &mut game.players[player_index(player)];
+            if seat.is_some() {
+                return Err(format!("{role} is already taken"))
+            }
+            *seat = Some(connection.clone());
+        },
+        Role::Spectator => game.spectators.push(connection.clone()),
+    }
+    connection.send(&ServerMessage::Joined(name.to_string(), role));
+    connection.send(&ServerMessage::Position(Box::new(GameBoard::start(game.board.rules()))));
+    for turn_move in &game.moves {
+        connection.send(&ServerMessage::Move(turn_move.clone()));
+    }
+    if game.turn_started.is_some() {
+        connection.send(&ServerMessage::Start);
+    } else if game.players.iter().all(Option::is_some) {
+        game.turn_started = Some(Instant::now());
+        game.broadcast(&ServerMessage::Start);
+        game.broadcast_clock();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+    use crate::{agents::{random_agent::RandomAgent, remote_agent::RemoteAgent}, match_runner};
+
+    fn start_server(time_control: Option<Duration>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener, time_control, RuleSet::STANDARD));
+        address
+    }
+
+    /**
+     * A raw connection joined to a game, with the server's answers read up to the start position.
+     */
+    fn join_raw(address: SocketAddr, game: &str, role: Role) -> (BufReader<TcpStream>, TcpStream) {
+        let mut writer = TcpStream::connect(address).unwrap();
+        writer.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        let mut reader = BufReader::new(writer.try_clone().unwrap());
+        writeln!(writer, "{}", ClientMessage::Join(game.to_string(), role)).unwrap();
+        assert_eq!(receive(&mut reader), format!("joined {game} {role}"));
+        assert!(receive(&mut reader).starts_with("position "));
+        (reader, writer)
+    }
+
+    fn receive(reader: &mut BufReader<TcpStream>) -> String {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        line.trim_end().to_string()
+    }
+
+    #[test]
+    fn games_are_played_over_localhost() {
+        let address = start_server(None);
+        let (mut spectator, _) = join_raw(address, "local", Role::Spectator);
+        //both players join before either plays, the server rejects moves until the game has started
+        let mut remote_player2 = RemoteAgent::connect(address, "local", Player::Player1).unwrap();
+        let mut remote_player1 = RemoteAgent::connect(address, "local", Player::Player2).unwrap();
+        let player1 = thread::spawn(move || {
+            let mut record = match_runner::play_game_with_rules(RuleSet::STANDARD, &mut RandomAgent::new(rand::rng()), &mut remote_player2, usize::MAX);
+            let outcome = remote_player2.finish(&record.positions().pop().unwrap()).unwrap();
+            record.set_outcome(&outcome);
+            record
+        });
+        let player2 = thread::spawn(move || {
+            let mut record = match_runner::play_game_with_rules(RuleSet::STANDARD, &mut remote_player1, &mut RandomAgent::new(rand::rng()), usize::MAX);
+            let outcome = remote_player1.finish(&record.positions().pop().unwrap()).unwrap();
+            record.set_outcome(&outcome);
+            record
+        });
+        let (player1, player2) = (player1.join().unwrap(), player2.join().unwrap());
+        assert_eq!(player1.moves, player2.moves);
+        assert_eq!(player1.outcome(), player2.outcome());
+        assert!(player1.outcome().is_over());
+
+        let mut watched = vec![];
+        loop {
+            match receive(&mut spectator).parse::<ServerMessage>().unwrap() {
+                ServerMessage::Move(turn_move) => watched.push(turn_move),
+                ServerMessage::GameOver(outcome) => {
+                    assert_eq!(outcome, player1.outcome());
+                    break
+                },
+                _ => {},
+            }
+        }
+        assert_eq!(watched, player1.moves);
+    }
+
+    #[test]
+    fn bad_lines_are_answered_with_errors() {
+        let address = start_server(None);
+        let mut writer = TcpStream::connect(address).unwrap();
+        writer.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        let mut reader = BufReader::new(writer.try_clone().unwrap());
+        writeln!(writer, "hello").unwrap();
+        assert!(receive(&mut reader).starts_with("error "));
+        writeln!(writer, "move a1a4").unwrap();
+        assert_eq!(receive(&mut reader), "error join a game as a player first");
+
+        let (mut reader, mut writer) = join_raw(address, "bad", Role::Player(Player::Player1));
+        writeln!(writer, "move a1a4").unwrap();
+        assert_eq!(receive(&mut reader), "error the game has not started");
+        let (mut opponent, _opponent_writer) = join_raw(address, "bad", Role::Player(Player::Player2));
+        assert_eq!(receive(&mut reader), "start");
+        assert!(receive(&mut reader).starts_with("clock "));
+        writeln!(writer, "move c3c5/a1a2").unwrap();
+        assert!(receive(&mut reader).starts_with("error illegal move c3c5/a1a2"));
+
+        //overlong lines end the connection
+        writeln!(writer, "{}", "x".repeat(MAX_LINE_LENGTH + 1)).unwrap();
+        assert!(receive(&mut reader).starts_with("error lines are at most"));
+        assert_eq!(receive(&mut reader), "");
+        assert_eq!(receive(&mut opponent), "start");
+        assert!(receive(&mut opponent).starts_with("clock "));
+        assert_eq!(receive(&mut opponent), "gameover 0-1 disconnect");
+    }
+
+    #[test]
+    fn games_end_by_resignation_and_on_time() {
+        let address = start_server(Some(Duration::from_millis(200)));
+        for (game, resign) in [("resigned", true), ("flagged", false)] {
+            let (mut player1, mut writer) = join_raw(address, game, Role::Player(Player::Player1));
+            let (mut player2, _writer) = join_raw(address, game, Role::Player(Player::Player2));
+            for reader in [&mut player1, &mut player2] {
+                assert_eq!(receive(reader), "start");
+                assert!(receive(reader).starts_with("clock "));
+            }
+            if resign {
+                writeln!(writer, "resign").unwrap();
+            }
+            //Player1 never moves and runs out of time otherwise
+            let expected = if resign {"gameover 0-1 resignation"} else {"gameover 0-1 timeout"};
+            assert_eq!(receive(&mut player1), expected);
+            assert_eq!(receive(&mut player2), expected);
+        }
+    }
+
+    #[test]
+    fn lines_are_read_up_to_the_limit() {
+        let limit = "x".repeat(MAX_LINE_LENGTH);
+        let mut input = format!("move a1a4\r\n{limit}\n{limit}x\n").into_bytes();
+        let mut reader = BufReader::new(input.as_slice());
+        assert_eq!(read_line(&mut reader).unwrap().as_deref(), Some("move a1a4"));
+        assert_eq!(read_line(&mut reader).unwrap(), Some(limit));
+        assert!(read_line(&mut reader).is_err());
+        input.clear();
+        assert_eq!(read_line(&mut BufReader::new(input.as_slice())).unwrap(), None);
+    }
+
+    #[test]
+    fn disconnected_spectators_are_dropped() {
+        let mut game = HostedGame::new(None, RuleSet::STANDARD);
+        let (sender, connected) = mpsc::channel();
+        game.spectators.push(Outbox { id: 0, sender });
+        let (sender, disconnected) = mpsc::channel();
+        game.spectators.push(Outbox { id: 1, sender });
+        drop(disconnected);
+        game.broadcast(&ServerMessage::Start);
+        assert_eq!(game.spectators.iter().map(|spectator| spectator.id).collect::<Vec<_>>(), [0]);
+        assert_eq!(connected.recv().unwrap(), "start");
+    }
+}
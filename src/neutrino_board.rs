@@ -1,46 +1,159 @@
-use std::fmt::Display;
+use std::{fmt::Display, ops::{Index, IndexMut}, sync::Arc};
 
+use crate::{outcome::{Outcome, Reason}, rules::RuleSet};
 
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum Piece {
+
+/**
+ * What can stand on a square.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Piece {
     Player1,
     Player2,
     Neutrino
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+/**
+ * The two players. Player1 starts on row 1 (index 0) and moves first.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Player {
     Player1,
     Player2,
 }
 
+impl Player {
+    pub fn opponent(self) -> Player {
+        match self {
+            Player::Player1 => Player::Player2,
+            Player::Player2 => Player::Player1,
+        }
+    }
+}
+
+/**
+ * A whole turn: first the neutrino moves, then one of the mover's pieces.
+ * The first turn of a game moves only a piece, unless the rules say otherwise (see `RuleSet`).
+ */
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct TurnMove {
-    pub neutrino_move: MoveType,
+    pub neutrino_move: Option<MoveType>,
     pub piece_move: MoveType
 }
 
 impl TurnMove {
-    fn new(neutrino_move: MoveType, piece_move: MoveType) -> Self {
-        Self { neutrino_move, piece_move}
+    pub fn new(neutrino_move: MoveType, piece_move: MoveType) -> Self {
+        Self { neutrino_move: Some(neutrino_move), piece_move}
+    }
+
+    /**
+     * A turn moving only a piece, as the first turn of a game does.
+     */
+    pub fn piece_only(piece_move: MoveType) -> Self {
+        Self { neutrino_move: None, piece_move }
     }
 }
 
 impl Display for TurnMove {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "({}) ({})", self.neutrino_move,  self.piece_move)
+        match &self.neutrino_move {
+            Some(neutrino_move) => writeln!(f, "({}) ({})", neutrino_move, self.piece_move),
+            None => writeln!(f, "({})", self.piece_move),
+        }
+    }
+}
+
+/// The most moves a board can have: 8 neutrino moves, each followed by 8 slides of each of a player's pieces,
+/// of which there are at most as many as the board is wide.
+pub const MAX_MOVES: usize = 8 * MAX_SIZE * 8;
+
+/**
+ * A list of moves stored inline, so generating moves does not allocate.
+ * The moves are packed into four bytes each, one per square, and unpacked when read.
+ */
+#[derive(Debug, Clone)]
+pub struct MoveList {
+    moves: [u32; MAX_MOVES],
+    len: usize,
+}
+
+impl MoveList {
+    pub fn new() -> Self {
+        Self { moves: [0; MAX_MOVES], len: 0 }
+    }
+
+    /**
+     * Appends a move.
+     *
+     * # Panics
+     * If the list already holds `MAX_MOVES` moves.
+     */
+    pub fn push(&mut self, turn_move: TurnMove) {
+        self.moves[self.len] = pack_move(&turn_move).expect("moves in a list are on the board");
+        self.len += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<TurnMove> {
+        self.moves[..self.len].get(index).map(unpack_move)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = TurnMove> + '_ {
+        self.into_iter()
+    }
+
+    pub fn contains(&self, turn_move: &TurnMove) -> bool {
+        pack_move(turn_move).is_some_and(|packed| self.moves[..self.len].contains(&packed))
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = TurnMove;
+    type IntoIter = std::iter::Map<std::slice::Iter<'a, u32>, fn(&u32) -> TurnMove>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.moves[..self.len].iter().map(unpack_move)
+    }
+}
+
+/**
+ * A move packed into a number, see `TurnMove::to_bytes`, `None` if a square is off every board
+ * (its byte would be that of another square).
+ */
+fn pack_move(turn_move: &TurnMove) -> Option<u32> {
+    let bytes = turn_move.to_bytes();
+    (TurnMove::from_bytes(bytes).as_ref() == Some(turn_move)).then(|| u32::from_be_bytes(bytes))
+}
+
+fn unpack_move(packed: &u32) -> TurnMove {
+    TurnMove::from_bytes(packed.to_be_bytes()).expect("packed moves are on the board")
 }
 
+/**
+ * A single piece, or the neutrino, sliding from one square to another.
+ */
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub(crate) struct MoveType {
-    from_position: Position,
-    to_position: Position,
+pub struct MoveType {
+    pub from_position: Position,
+    pub to_position: Position,
 }
 
 impl MoveType {
-    fn new(from_position: Position, to_position: Position) -> Self {
+    pub fn new(from_position: Position, to_position: Position) -> Self {
         Self {from_position, to_position}
     }
 }
@@ -51,14 +164,63 @@ impl Display for MoveType {
     }
 }
 
+/**
+ * Why a move is illegal on a board, see `GameBoard::try_result`.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    /// The game is over, the neutrino already reached a home row or the game is drawn.
+    GameOver,
+    /// The move starts or ends off the board.
+    OffBoard(Position),
+    /// The neutrino move does not start on the neutrino.
+    NeutrinoNotAtSource(Position),
+    /// The piece move does not start on a piece of the player to move.
+    WrongPiece(Position),
+    /// The move does not follow a row, column or diagonal, or does not move at all.
+    NotInLine(Position, Position),
+    /// A piece stands between the start and the end of the move.
+    BlockedPath(Position),
+    /// The neutrino or the piece would end on an occupied square.
+    DestinationOccupied(Position),
+    /// The move stops on the given square although it could slide further.
+    NotSliding(Position),
+    /// The player has all but one of their pieces on their home row and cannot bring back the last.
+    HomeRowLimit,
+    /// The first turn of the game moves only a piece.
+    NeutrinoMoveOnFirstTurn,
+    /// Every turn but a piece-only first turn moves the neutrino.
+    MissingNeutrinoMove,
+}
+
+impl Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::GameOver => write!(f, "the game is over"),
+            MoveError::OffBoard(position) => write!(f, "{} is off the board", position.to_notation()),
+            MoveError::NeutrinoNotAtSource(position) => write!(f, "the neutrino is not on {}", position.to_notation()),
+            MoveError::WrongPiece(position) => write!(f, "there is no piece of the player to move on {}", position.to_notation()),
+            MoveError::NotInLine(from, to) => write!(f, "{} to {} is not a straight or diagonal line", from.to_notation(), to.to_notation()),
+            MoveError::BlockedPath(position) => write!(f, "the path is blocked on {}", position.to_notation()),
+            MoveError::DestinationOccupied(position) => write!(f, "{} is occupied", position.to_notation()),
+            MoveError::NotSliding(position) => write!(f, "pieces slide as far as they can, they cannot stop on {}", position.to_notation()),
+            MoveError::HomeRowLimit => write!(f, "the last piece off the home row may not return to it"),
+            MoveError::NeutrinoMoveOnFirstTurn => write!(f, "the first turn moves only a piece"),
+            MoveError::MissingNeutrinoMove => write!(f, "the neutrino must be moved first"),
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
 /**
  * A position on the board, use board[row][column]
  *  */
  
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-struct Position {
-    row: usize,
-    column: usize
+pub struct Position {
+    pub row: usize,
+    pub column: usize
 }
 impl From<(usize, usize)> for Position {
     fn from(value: (usize, usize)) -> Self {
@@ -74,75 +236,338 @@ impl Display for Position {
 
 impl Position {
 
-    fn new(row: usize, column: usize) -> Self {
+    pub fn new(row: usize, column: usize) -> Self {
         Self { row, column }
     }
+}
+
+/// The widest board supported, so squares fit in a fixed array and columns are the letters a to i.
+pub const MAX_SIZE: usize = 9;
+/// The narrowest board supported, with room for the neutrino between the home rows.
+pub const MIN_SIZE: usize = 3;
 
-    fn up(&self) -> Self {
-        Self { row: self.row + 1, column: self.column }
+/**
+ * The squares of a square board of any size from `MIN_SIZE` to `MAX_SIZE`, indexed like an array: `squares[row][column]`.
+ * They are stored inline, so boards of every size are copied without allocating.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Squares {
+    cells: [[Option<Piece>; MAX_SIZE]; MAX_SIZE],
+    size: usize,
+}
+
+impl Squares {
+    /**
+     * A board of empty squares.
+     *
+     * # Panics
+     * If the size is not between `MIN_SIZE` and `MAX_SIZE`.
+     */
+    pub fn empty(size: usize) -> Self {
+        assert!((MIN_SIZE..=MAX_SIZE).contains(&size), "boards are {MIN_SIZE}x{MIN_SIZE} to {MAX_SIZE}x{MAX_SIZE}, not {size}x{size}");
+        Self { cells: [[None; MAX_SIZE]; MAX_SIZE], size }
+    }
+
+    /**
+     * The number of rows, and of columns.
+     */
+    pub fn size(&self) -> usize {
+        self.size
     }
 
-    fn down(&self) -> Self {
-        Self { row: self.row - 1, column: self.column }
+    pub fn in_bounds(&self, position: Position) -> bool {
+        position.row < self.size && position.column < self.size
     }
 
-    fn left(&self) -> Self {
-        Self { row: self.row, column: self.column - 1 }
+    /**
+     * The rows from row 1 (index 0) up.
+     */
+    pub fn rows(&self) -> impl Iterator<Item = &[Option<Piece>]> {
+        self.cells[..self.size].iter().map(|row| &row[..self.size])
     }
 
-    fn right(&self) -> Self {
-        Self { row: self.row, column: self.column + 1 }
+    fn count(&self, piece: Piece) -> usize {
+        self.rows().flatten().filter(|square| **square == Some(piece)).count()
     }
-    fn in_bounds(&self) -> bool {
-        if self.row > 4  || self.column > 4 {
-            return false
-        } else {
-            return true
+}
+
+impl Index<usize> for Squares {
+    type Output = [Option<Piece>];
+
+    fn index(&self, row: usize) -> &Self::Output {
+        &self.cells[..self.size][row][..self.size]
+    }
+}
+
+impl IndexMut<usize> for Squares {
+    fn index_mut(&mut self, row: usize) -> &mut Self::Output {
+        &mut self.cells[..self.size][row][..self.size]
+    }
+}
+
+impl<const N: usize> From<[[Option<Piece>; N]; N]> for Squares {
+    fn from(rows: [[Option<Piece>; N]; N]) -> Self {
+        let mut squares = Squares::empty(N);
+        for (row_index, row) in rows.into_iter().enumerate() {
+            squares[row_index].copy_from_slice(&row);
         }
+        squares
     }
 }
 
-#[derive(Debug, Clone)]
-pub(crate) struct GameBoard {
-    board: [[Option<Piece>; 5]; 5],
+/**
+ * A position: the pieces on the board and the player to move.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameBoard {
+    board: Squares,
     to_move: Player,
-    neutrino_position: Position
+    neutrino_position: Position,
+    rules: RuleSet,
+    /// Whether no move has been played yet.
+    first_turn: bool,
+    /// The Zobrist hash, kept up to date by every move.
+    hash: u64,
+    /// The hashes of the earlier positions of the game. Only kept when the rules draw by repetition.
+    history: History,
+    /// The number of turns in a row that left the neutrino on its row.
+    turns_without_progress: usize,
+    /// Why the game is drawn by the rules on repetitions and progress, if it is.
+    draw: Option<Reason>,
+}
+
+/**
+ * The hashes of the earlier positions of a game. Boards share the part of the history before they were cloned,
+ * so cloning a board (e.g. for every node of a search tree) copies no hashes. The moves made on a board since
+ * are stacked on the board itself, so `make_move` and `unmake_move` do not allocate once the stack has grown.
+ */
+#[derive(Default)]
+struct History {
+    shared: Option<Arc<HistoryEntry>>,
+    /// The hashes pushed since the board was cloned, oldest first.
+    own: Vec<u64>,
+}
+
+struct HistoryEntry {
+    hash: u64,
+    previous: Option<Arc<HistoryEntry>>,
+}
+
+impl History {
+    fn push(&mut self, hash: u64) {
+        self.own.push(hash);
+    }
+
+    fn pop(&mut self) {
+        if self.own.pop().is_none() {
+            let entry = self.shared.take().expect("only pushed hashes are popped");
+            self.shared = entry.previous.clone();
+        }
+    }
+
+    /**
+     * Moves the own hashes to the shared part, so that clones of the board share them too.
+     */
+    fn share(&mut self) {
+        for hash in self.own.drain(..) {
+            self.shared = Some(Arc::new(HistoryEntry { hash, previous: self.shared.take() }));
+        }
+    }
+
+    /**
+     * The hashes, newest first.
+     */
+    fn hashes(&self) -> impl Iterator<Item = u64> + '_ {
+        let shared = std::iter::successors(self.shared.as_deref(), |entry| entry.previous.as_deref()).map(|entry| entry.hash);
+        self.own.iter().rev().copied().chain(shared)
+    }
+}
+
+impl Clone for History {
+    fn clone(&self) -> Self {
+        let mut history = Self { shared: self.shared.clone(), own: vec![] };
+        history.own.clone_from(&self.own);
+        history.share();
+        history
+    }
+}
+
+impl PartialEq for History {
+    fn eq(&self, other: &Self) -> bool {
+        self.hashes().eq(other.hashes())
+    }
+}
+
+impl Eq for History {}
+
+impl std::fmt::Debug for History {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut hashes: Vec<u64> = self.hashes().collect();
+        hashes.reverse();
+        f.debug_list().entries(hashes).finish()
+    }
+}
+
+impl Drop for HistoryEntry {
+    /**
+     * Drops the entries no other history shares one by one, as dropping them recursively could overflow the stack.
+     */
+    fn drop(&mut self) {
+        let mut previous = self.previous.take();
+        while let Some(entry) = previous {
+            previous = match Arc::try_unwrap(entry) {
+                Ok(mut entry) => entry.previous.take(),
+                Err(_) => None,
+            };
+        }
+    }
+}
+
+/**
+ * What `GameBoard::unmake_move` needs to take back a move made with `GameBoard::make_move`.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndoRecord {
+    turn_move: TurnMove,
+    first_turn: bool,
+    turns_without_progress: usize,
+    draw: Option<Reason>,
+    /// The hash before the move, to check that unmaking restored it.
+    hash: u64,
 }
 
 impl GameBoard {
-    fn new(board: [[Option<Piece>; 5]; 5], to_move: Player) -> Self {
+    /**
+     * A board with the given pieces, played by the standard rules. Notation does not record the moves
+     * that led to a position, so the start position with Player1 to move is taken to be the first turn,
+     * and the position has no history of earlier positions. The size of the board is the size of the squares.
+     */
+    pub fn new(board: Squares, to_move: Player) -> Self {
         //find neutrino
         let mut neutrino_position: Option<Position> = None;
-        for (row_index, row) in board.iter().enumerate() {
+        for (row_index, row) in board.rows().enumerate() {
             for (column_index, column) in row.iter().enumerate() {
                 if column.is_some_and(|x| x == Piece::Neutrino) {
                     neutrino_position = Some((row_index, column_index).into())
                 }
             }
         }
-        match neutrino_position {
-            Some(neutrino_position) => Self { board, to_move, neutrino_position},
-            None => panic!("Boards must have a neutrino!"),
-        }  
+        let Some(neutrino_position) = neutrino_position else {
+            panic!("Boards must have a neutrino!")
+        };
+        Self {
+            board,
+            to_move,
+            neutrino_position,
+            rules: RuleSet::default(),
+            first_turn: false,
+            hash: 0,
+            history: History::default(),
+            turns_without_progress: 0,
+            draw: None,
+        }.with_rules(RuleSet::default())
     }
+
     /**
-     * Returns the positions of the player's pieces
+     * The start position of a game played by the given rules.
      */
-    fn pieces(&self, player: Player) -> Vec<Position> {
-        let mut positions: Vec<Position> = vec![];
-        let player_piece = match player {
-            Player::Player1 => Piece::Player1,
-            Player::Player2 => Piece::Player2,
-        };
-        for (row_index, row) in self.board.iter().enumerate() {
-            for (column_index, piece) in row.iter().enumerate() {
-                if piece.is_some_and(|x| x == player_piece) {
-                    positions.push(Position::new(row_index, column_index))
-                }
-            }
+    pub fn start(rules: RuleSet) -> Self {
+        Self::new(rules.start_position(), Player::Player1).with_rules(rules)
+    }
+
+    /**
+     * The same position played by other rules.
+     */
+    pub fn with_rules(mut self, rules: RuleSet) -> Self {
+        self.rules = rules;
+        self.first_turn = self.to_move == Player::Player1 && self.board == rules.start_position();
+        self.hash = self.zobrist_hash();
+        self.draw = self.draw_reason();
+        self
+    }
+
+    pub fn rules(&self) -> RuleSet {
+        self.rules
+    }
+
+    /**
+     * Whether the turn to play moves only a piece: the first turn under the standard rules.
+     */
+    pub fn is_piece_only_turn(&self) -> bool {
+        self.first_turn && self.rules.piece_only_first_turn
+    }
+    /**
+     * The pieces on the board, indexed by row and column.
+     */
+    pub fn squares(&self) -> &Squares {
+        &self.board
+    }
+
+    /**
+     * The number of rows, and of columns.
+     */
+    pub fn size(&self) -> usize {
+        self.board.size()
+    }
+
+    /**
+     * The row a player wins by getting the neutrino onto: their own home row, or the opponent's
+     * when the rules make the own row lose.
+     */
+    pub fn winning_row(&self, player: Player) -> usize {
+        match self.rules.own_row_loses {
+            true => self.home_row(player.opponent()),
+            false => self.home_row(player),
         }
-        positions
     }
+
+    /**
+     * The row a player's pieces start on: the first row for Player1 and the last for Player2.
+     */
+    pub fn home_row(&self, player: Player) -> usize {
+        match player {
+            Player::Player1 => 0,
+            Player::Player2 => self.size() - 1,
+        }
+    }
+
+    /**
+     * How often the current position occurred before in the game. Only positions reached by moves on this board
+     * count, and only when the rules draw by repetition.
+     */
+    pub fn repetitions(&self) -> usize {
+        self.history.hashes().filter(|hash| *hash == self.hash).count()
+    }
+
+    /**
+     * The number of turns in a row that left the neutrino on its row, see `RuleSet::no_progress_draw`.
+     */
+    pub fn turns_without_progress(&self) -> usize {
+        self.turns_without_progress
+    }
+
+    /**
+     * The same position after the given number of turns without progress, e.g. for the image of a position.
+     */
+    pub(crate) fn with_turns_without_progress(mut self, turns: usize) -> Self {
+        self.turns_without_progress = turns;
+        self.draw = self.draw_reason();
+        self
+    }
+
+    /**
+     * Why the rules on repetitions and progress draw the game on this board, if they do.
+     */
+    fn draw_reason(&self) -> Option<Reason> {
+        if self.rules.repetition_draw.is_some_and(|occurrences| self.repetitions() + 1 >= occurrences) {
+            return Some(Reason::Repetition)
+        }
+        if self.rules.no_progress_draw.is_some_and(|turns| self.turns_without_progress >= turns) {
+            return Some(Reason::NoProgress)
+        }
+        None
+    }
+
     /**
      * What player's turn it is.
      */
@@ -151,208 +576,405 @@ impl GameBoard {
     }
 
     /**
-     * A list of all possible moves.
+     * The square the neutrino is on.
      */
-    pub fn actions(&self) -> Vec<TurnMove>{
-        //if the game has a winner, don't allow more moves (the case of captured neutrino is handled below)
-        if self.neutrino_in_end_row() {
-            return vec![]
-        }
-        //list neutrino moves
+    pub fn neutrino_position(&self) -> Position {
+        self.neutrino_position
+    }
 
-        let move_in_direction = |board: &GameBoard, start_position: Position, direction: Box<dyn Fn(Position) -> Position>| -> Option<MoveType>{
-            let test_position = direction(Position::new(1,1));
-            let moves_down = test_position.row == 0;
-            let moves_left = test_position.column == 0;
-            if start_position.row == 0 && moves_down {
-                return None
-            }
-            if start_position.column == 0 && moves_left {
-                return None
-            }
-            let position_in_direction = direction(start_position);
-            let mut end_position = None;
-            if position_in_direction.in_bounds() && board.at_position(position_in_direction).is_none()  {
-                end_position = Some(position_in_direction)
-            }
-            if let Some(mut end_position) = end_position {
-                while !(end_position.row == 0 && moves_down) && 
-                    !(end_position.column == 0 && moves_left) && 
-                    direction(end_position).in_bounds() &&
-                    board.at_position(direction(end_position)).is_none() {
-                    end_position = direction(end_position)
-                }
-                return Some(MoveType::new(start_position.clone(), end_position))
-            }
-            return None
-        };
-        let neutrino_start_position: Position = self.neutrino_position.clone();
-        let neutrino_moves: Vec<MoveType> = [
-            move_in_direction(self, neutrino_start_position, Box::new(|x:Position| x.up())),
-            move_in_direction(self, neutrino_start_position, Box::new(|x:Position| x.down())),
-            move_in_direction(self, neutrino_start_position, Box::new(|x:Position| x.left())),
-            move_in_direction(self, neutrino_start_position, Box::new(|x:Position| x.right())),
-            move_in_direction(self, neutrino_start_position, Box::new(|x:Position| x.up().left())),
-            move_in_direction(self, neutrino_start_position, Box::new(|x:Position| x.up().right())),
-            move_in_direction(self, neutrino_start_position, Box::new(|x:Position| x.down().left())),
-            move_in_direction(self, neutrino_start_position, Box::new(|x:Position| x.down().right()))
-        ].into_iter()
-        .flatten() //remove None
-        .collect();
-
-        //generate moves from each possible neutrino move
-        let mut moves: Vec<TurnMove> = vec![];
-        let player_pieces = self.pieces(self.to_move());
-        let start_row = if self.to_move() == Player::Player1 {
-            0
-        } else {
-            4
-        };
-        // can't move into the start row if there are already 4 pieces there
-        let can_move_to_start_row = player_pieces
-            .iter()
-            .filter(|position| position.row == start_row)
-            .count() < 4;
-
-        for neutrino_move in neutrino_moves {
-            let mut moved_neutrino_board = self.clone();
-            moved_neutrino_board.board[neutrino_move.from_position.row][neutrino_move.from_position.column] = None;
-            moved_neutrino_board.board[neutrino_move.to_position.row][neutrino_move.to_position.column] = Some(Piece::Neutrino);
-
-            // generate list of all the player's pieces
-            for player_piece in &player_pieces {
-                let player_moves: Vec<MoveType> = [
-                    move_in_direction(&moved_neutrino_board, *player_piece, Box::new(|x:Position| x.up())),
-                    move_in_direction(&moved_neutrino_board, *player_piece, Box::new(|x:Position| x.down())),
-                    move_in_direction(&moved_neutrino_board, *player_piece, Box::new(|x:Position| x.left())),
-                    move_in_direction(&moved_neutrino_board, *player_piece, Box::new(|x:Position| x.right())),
-                    move_in_direction(&moved_neutrino_board, *player_piece, Box::new(|x:Position| x.up().left())),
-                    move_in_direction(&moved_neutrino_board, *player_piece, Box::new(|x:Position| x.up().right())),
-                    move_in_direction(&moved_neutrino_board, *player_piece, Box::new(|x:Position| x.down().left())),
-                    move_in_direction(&moved_neutrino_board, *player_piece, Box::new(|x:Position| x.down().right()))
-                ].into_iter()
-                    .flatten()
-                    .filter(|piece_move| {
-                        !(!can_move_to_start_row
-                            && piece_move.from_position.row != start_row
-                            && piece_move.to_position.row == start_row)
-                    } )
-                    .collect();
-                player_moves.into_iter()
-                    .for_each(|piece_move| {
-                        let turn_move = TurnMove::new(neutrino_move.clone(), piece_move);
-                        moves.push(turn_move);
-                    });
-            }
+    /**
+     * A list of all possible moves. Searches should prefer `legal_moves` or `moves`, which do not allocate.
+     */
+    pub fn actions(&self) -> Vec<TurnMove> {
+        self.moves().collect()
+    }
+
+    /**
+     * All legal moves in a list on the stack.
+     */
+    pub fn legal_moves(&self) -> MoveList {
+        let mut list = MoveList::new();
+        self.moves().for_each(|turn_move| list.push(turn_move));
+        list
+    }
+
+    /**
+     * Lazily generates all legal moves: the piece moves for the first neutrino move, then for the next, and so on.
+     */
+    pub fn moves(&self) -> impl Iterator<Item = TurnMove> + '_ {
+        self.neutrino_moves().flat_map(|neutrino_move| self.piece_moves(neutrino_move.as_ref()))
+    }
+
+    /**
+     * The first stage of move generation: where the neutrino can slide. Empty once the game is won or drawn,
+     * and a single `None` on a piece-only first turn.
+     */
+    pub fn neutrino_moves(&self) -> impl Iterator<Item = Option<MoveType>> + '_ {
+        let piece_only = self.is_piece_only_turn() && self.draw.is_none();
+        let game_over = self.neutrino_in_end_row() || self.draw.is_some();
+        let from = self.neutrino_position;
+        let slides = DIRECTIONS.iter()
+            .filter(move |_| !game_over && !piece_only)
+            .filter_map(move |&direction| slide(&self.board, from, direction).map(|to| Some(MoveType::new(from, to))));
+        piece_only.then_some(None).into_iter().chain(slides)
+    }
+
+    /**
+     * The second stage of move generation: the turns completing a neutrino move from `neutrino_moves`
+     * with a move of one of the mover's pieces.
+     */
+    pub fn piece_moves(&self, neutrino_move: Option<&MoveType>) -> impl Iterator<Item = TurnMove> + use<> {
+        let mut board = self.board;
+        if let Some(neutrino_move) = neutrino_move {
+            let (neutrino_from, neutrino_to) = (neutrino_move.from_position, neutrino_move.to_position);
+            board[neutrino_from.row][neutrino_from.column] = None;
+            board[neutrino_to.row][neutrino_to.column] = Some(Piece::Neutrino);
         }
 
-        moves
+        let piece = player_piece(self.to_move);
+        let start_row = self.home_row(self.to_move);
+        // can't move into the start row if all pieces but one are already there
+        let can_move_to_start_row = !self.rules.home_row_limit
+            || board[start_row].iter().filter(|square| **square == Some(piece)).count() + 1 < board.count(piece);
+        let neutrino_move = neutrino_move.cloned();
+        let size = self.size();
+        (0..size * size)
+            .map(move |square| Position::new(square / size, square % size))
+            .filter(move |position| board[position.row][position.column] == Some(piece))
+            .flat_map(move |from| DIRECTIONS.iter()
+                .filter_map(move |&direction| slide(&board, from, direction).map(|to| MoveType::new(from, to))))
+            .filter(move |piece_move| can_move_to_start_row
+                || piece_move.from_position.row == start_row
+                || piece_move.to_position.row != start_row)
+            .map(move |piece_move| TurnMove { neutrino_move: neutrino_move.clone(), piece_move })
+    }
+
+    /**
+     * Whether the player to move has a legal move, stopping at the first one found.
+     */
+    pub fn has_any_legal_move(&self) -> bool {
+        self.moves().next().is_some()
     }
 
     /**
      * The GameBoard resulting from a move.
+     *
+     * # Panics
+     * If the move cannot be applied to this board, see `try_result`.
      */
     pub fn result(&self, turn_move: TurnMove) -> Self {
-        //set new to_move
-        let new_player = match self.to_move() {
-            Player::Player1 => Player::Player2,
-            Player::Player2 => Player::Player1,
-        };
-        //set new board state
-        let mut new_board = self.board.clone();
+        self.try_result(&turn_move).unwrap_or_else(|error| panic!("{error}"))
+    }
 
-        //move neutrino
-        assert!(new_board[turn_move.neutrino_move.from_position.row][turn_move.neutrino_move.from_position.column]
-            .is_some_and(|x| x == Piece::Neutrino ));
-        let new_neutrino_position: Position = (turn_move.neutrino_move.to_position.row, turn_move.neutrino_move.to_position.column).into();
-        assert!(new_board[new_neutrino_position.row][new_neutrino_position.column].is_none());
+    /**
+     * The GameBoard resulting from a move, or exactly why the move is illegal:
+     * the neutrino and then one of the mover's pieces must slide in a line as far as they can,
+     * and a player with all but one of their pieces on their home row cannot move the last back onto it.
+     * A piece-only first turn must not move the neutrino.
+     */
+    pub fn try_result(&self, turn_move: &TurnMove) -> Result<Self, MoveError> {
+        //a board without legal moves is over as well, but then no move passes the checks below
+        if self.neutrino_in_end_row() || self.draw.is_some() {
+            return Err(MoveError::GameOver)
+        }
+        match (&turn_move.neutrino_move, self.is_piece_only_turn()) {
+            (Some(_), true) => return Err(MoveError::NeutrinoMoveOnFirstTurn),
+            (None, false) => return Err(MoveError::MissingNeutrinoMove),
+            _ => {},
+        }
+        let squares = turn_move.neutrino_move.iter()
+            .flat_map(|neutrino_move| [neutrino_move.from_position, neutrino_move.to_position])
+            .chain([turn_move.piece_move.from_position, turn_move.piece_move.to_position]);
+        if let Some(position) = squares.into_iter().find(|position| !self.board.in_bounds(*position)) {
+            return Err(MoveError::OffBoard(position))
+        }
+        let mut new_board = self.board;
 
-        new_board[turn_move.neutrino_move.from_position.row][turn_move.neutrino_move.from_position.column] = None;
-        new_board[new_neutrino_position.row][new_neutrino_position.column] = Some(Piece::Neutrino);
+        //move neutrino
+        if let Some(neutrino_move) = &turn_move.neutrino_move {
+            let MoveType { from_position, to_position } = *neutrino_move;
+            if new_board[from_position.row][from_position.column] != Some(Piece::Neutrino) {
+                return Err(MoveError::NeutrinoNotAtSource(from_position))
+            }
+            check_slide(&new_board, neutrino_move)?;
+            new_board[from_position.row][from_position.column] = None;
+            new_board[to_position.row][to_position.column] = Some(Piece::Neutrino);
+        }
 
         //move playerPiece
-        let piece = match self.to_move() {
-            Player::Player1 => Piece::Player1,
-            Player::Player2 => Piece::Player2,
-        };
-        assert!(new_board[turn_move.piece_move.from_position.row][turn_move.piece_move.from_position.column]
-            .is_some_and(|x| x == piece ));
-        assert!(new_board[turn_move.piece_move.to_position.row][turn_move.piece_move.to_position.column].is_none());
+        let piece = player_piece(self.to_move());
+        let MoveType { from_position, to_position } = turn_move.piece_move;
+        if new_board[from_position.row][from_position.column] != Some(piece) {
+            return Err(MoveError::WrongPiece(from_position))
+        }
+        check_slide(&new_board, &turn_move.piece_move)?;
+        let start_row = self.home_row(self.to_move);
+        let pieces_on_start_row = new_board[start_row].iter().filter(|square| **square == Some(piece)).count();
+        if self.rules.home_row_limit && pieces_on_start_row + 1 >= new_board.count(piece) && from_position.row != start_row && to_position.row == start_row {
+            return Err(MoveError::HomeRowLimit)
+        }
 
-        new_board[turn_move.piece_move.from_position.row][turn_move.piece_move.from_position.column] = None;
-        new_board[turn_move.piece_move.to_position.row][turn_move.piece_move.to_position.column] = Some(piece);
+        let mut next = self.clone();
+        next.make_move(turn_move);
+        next.history.share();
+        Ok(next)
+    }
 
-        Self {board: new_board, neutrino_position: new_neutrino_position, to_move: new_player}
+    /**
+     * Plays a legal move in place and returns the record `unmake_move` takes it back with.
+     * Only the moved squares, the neutrino position, the hash and the history are updated. The move is not validated;
+     * use `play` or `try_result` for moves from untrusted sources.
+     */
+    pub fn make_move(&mut self, turn_move: &TurnMove) -> UndoRecord {
+        let undo = UndoRecord {
+            turn_move: turn_move.clone(),
+            first_turn: self.first_turn,
+            turns_without_progress: self.turns_without_progress,
+            draw: self.draw,
+            hash: self.hash,
+        };
+        if self.rules.repetition_draw.is_some() {
+            self.history.push(self.hash);
+        }
+        let progress = turn_move.neutrino_move.as_ref()
+            .is_some_and(|neutrino_move| neutrino_move.from_position.row != neutrino_move.to_position.row);
+        self.turns_without_progress = if progress {0} else {self.turns_without_progress + 1};
+        let piece = player_piece(self.to_move);
+        if let Some(neutrino_move) = &turn_move.neutrino_move {
+            self.move_piece(neutrino_move, Piece::Neutrino);
+            self.neutrino_position = neutrino_move.to_position;
+        }
+        self.move_piece(&turn_move.piece_move, piece);
+        if self.is_piece_only_turn() {
+            self.hash ^= PIECE_ONLY_TURN_KEY;
+        }
+        self.first_turn = false;
+        self.to_move = self.to_move.opponent();
+        self.hash ^= PLAYER2_TO_MOVE_KEY;
+        self.draw = self.draw_reason();
+        undo
     }
 
     /**
-     * Whether the GameBoard is terminal (has a winner). 
+     * Takes back the last move made with `make_move`, restoring the exact board before it.
      */
-    pub fn is_terminal(&self) -> bool {
-        if self.neutrino_in_end_row() {
-            return true
+    pub fn unmake_move(&mut self, undo: UndoRecord) {
+        self.to_move = self.to_move.opponent();
+        self.hash ^= PLAYER2_TO_MOVE_KEY;
+        let TurnMove { neutrino_move, piece_move } = undo.turn_move;
+        self.move_piece(&MoveType::new(piece_move.to_position, piece_move.from_position), player_piece(self.to_move));
+        if let Some(neutrino_move) = neutrino_move {
+            self.move_piece(&MoveType::new(neutrino_move.to_position, neutrino_move.from_position), Piece::Neutrino);
+            self.neutrino_position = neutrino_move.from_position;
+        }
+        self.first_turn = undo.first_turn;
+        if self.is_piece_only_turn() {
+            self.hash ^= PIECE_ONLY_TURN_KEY;
         }
-        if self.actions().len() == 0 {
-            return true
+        if self.rules.repetition_draw.is_some() {
+            self.history.pop();
         }
-        false
+        self.turns_without_progress = undo.turns_without_progress;
+        self.draw = undo.draw;
+        debug_assert_eq!(self.hash, undo.hash, "moves must be unmade in reverse order");
+    }
+
+    fn move_piece(&mut self, slide: &MoveType, piece: Piece) {
+        let (from, to) = (slide.from_position, slide.to_position);
+        self.board[from.row][from.column] = None;
+        self.board[to.row][to.column] = Some(piece);
+        self.hash ^= zobrist_key(from, piece) ^ zobrist_key(to, piece);
+    }
+
+    /**
+     * Plays a move on this board, leaving it unchanged if the move is illegal.
+     */
+    pub fn play(&mut self, turn_move: &TurnMove) -> Result<(), MoveError> {
+        *self = self.try_result(turn_move)?;
+        Ok(())
+    }
+
+    /**
+     * Whether the GameBoard is terminal: won, or drawn by the rules.
+     */
+    pub fn is_terminal(&self) -> bool {
+        self.neutrino_in_end_row() || !self.has_any_legal_move()
     }
 
     fn neutrino_in_end_row(&self) -> bool {
-       self.neutrino_position.row == 0 || self.neutrino_position.row == 4
+       self.neutrino_position.row == 0 || self.neutrino_position.row == self.size() - 1
     }
 
 
     
+    /**
+     * How the game stands on this board: won when the neutrino is on a home row or the player to move
+     * cannot move (drawn instead if the rules say so for an immobilized neutrino), drawn by repetition
+     * or a lack of progress if the rules say so, otherwise ongoing.
+     */
+    pub fn outcome(&self) -> Outcome {
+        for player in [Player::Player1, Player::Player2] {
+            if self.neutrino_position.row == self.winning_row(player) {
+                return Outcome::Win(player, Reason::NeutrinoHomeRow)
+            }
+        }
+        if let Some(reason) = self.draw {
+            return Outcome::Draw(reason)
+        }
+        if self.has_any_legal_move() {
+            return Outcome::Ongoing
+        }
+        if self.neutrino_moves().next().is_some() {
+            return Outcome::Win(self.to_move.opponent(), Reason::OpponentImmobilized)
+        }
+        match self.rules.immobilized_neutrino_draws {
+            true => Outcome::Draw(Reason::NeutrinoImmobilized),
+            false => Outcome::Win(self.to_move.opponent(), Reason::NeutrinoImmobilized),
+        }
+    }
+
+    /**
+     * The player who won on this board, if the game is over.
+     */
+    pub fn winner(&self) -> Option<Player> {
+        self.outcome().winner()
+    }
+
     /**
      * The utility (i.e. score) of the GameBoard from a given player's perspective.
      */
     pub fn utility(&self, player: Player) -> Option<f64> {
-        if !self.is_terminal() {
-            return None
-        }
-        //TODO extract into winner()
-        let neutrino_loss_player1 = self.board[0]
-            .iter()
-            .any(|x| x.is_some_and(|piece| piece == Piece::Neutrino));
-        let neutrino_loss_player2 = self.board[4]
-            .iter()
-            .any(|x| x.is_some_and(|piece| piece == Piece::Neutrino));
-        let captured_neutrino = self.actions().len() == 0;
+        self.outcome().utility(player)
+    }
+
+    /**
+     * The piece on a square, if any.
+     */
+    pub fn at_position(&self, position: Position) -> Option<Piece> {
+        self.board[position.row][position.column]
+    }
 
-        let mut result = 0.5;
+    /**
+     * A Zobrist hash of the position, including the player to move and whether the turn moves only a piece.
+     * The keys are derived deterministically so hashes can be stored on disk, e.g. in an opening book.
+     */
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
 
-        if neutrino_loss_player1 {
-            result = 0.
-        } else if neutrino_loss_player2 {
-            result = 1.
-        } else if captured_neutrino && self.to_move() == Player::Player1 {
-            result = 0.
-        } else if captured_neutrino && self.to_move() == Player::Player2 {
-            result = 1.0
+    /**
+     * The Zobrist hash computed from scratch.
+     */
+    fn zobrist_hash(&self) -> u64 {
+        let mut hash = match self.to_move {
+            Player::Player1 => 0,
+            Player::Player2 => PLAYER2_TO_MOVE_KEY,
+        };
+        if self.is_piece_only_turn() {
+            hash ^= PIECE_ONLY_TURN_KEY;
         }
-        //Adjust perspective if necessary
-        if player == Player::Player2 {
-            result = 1.0 - result
+        for (row_index, row) in self.board.rows().enumerate() {
+            for (column_index, piece) in row.iter().enumerate() {
+                if let Some(piece) = piece {
+                    hash ^= zobrist_key(Position::new(row_index, column_index), *piece);
+                }
+            }
         }
-        Some(result)
+        hash
     }
+}
 
-    fn at_position(&self, position: Position) -> Option<Piece> {
-        self.board[position.row][position.column]
+fn player_piece(player: Player) -> Piece {
+    match player {
+        Player::Player1 => Piece::Player1,
+        Player::Player2 => Piece::Player2,
+    }
+}
+
+/// Toggled in the hash when Player2 is to move.
+const PLAYER2_TO_MOVE_KEY: u64 = splitmix64(u64::MAX);
+/// Toggled in the hash on a piece-only first turn.
+const PIECE_ONLY_TURN_KEY: u64 = splitmix64(u64::MAX - 1);
+
+/// The eight directions pieces slide in as (row, column) steps, up being towards the last row.
+const DIRECTIONS: [(isize, isize); 8] = [(1, 0), (-1, 0), (0, -1), (0, 1), (1, -1), (1, 1), (-1, -1), (-1, 1)];
+
+/**
+ * Where a piece sliding from a square in a direction stops, `None` if it cannot move that way.
+ */
+fn slide(board: &Squares, from: Position, (row_step, column_step): (isize, isize)) -> Option<Position> {
+    let mut end = None;
+    let mut square = from;
+    while let Some(next) = step(board, square, row_step, column_step)
+        && board[next.row][next.column].is_none() {
+        end = Some(next);
+        square = next;
+    }
+    end
+}
+
+/**
+ * The square one step from a position, `None` off the board.
+ */
+fn step(board: &Squares, position: Position, row_step: isize, column_step: isize) -> Option<Position> {
+    let row = position.row.checked_add_signed(row_step)?;
+    let column = position.column.checked_add_signed(column_step)?;
+    let stepped = Position::new(row, column);
+    board.in_bounds(stepped).then_some(stepped)
+}
+
+/**
+ * Checks that a piece can slide from the start to the end of the move:
+ * in a line, over empty squares, and as far as possible.
+ */
+fn check_slide(board: &Squares, slide: &MoveType) -> Result<(), MoveError> {
+    let (from, to) = (slide.from_position, slide.to_position);
+    let row_distance = to.row as isize - from.row as isize;
+    let column_distance = to.column as isize - from.column as isize;
+    let in_line = row_distance == 0 || column_distance == 0 || row_distance.abs() == column_distance.abs();
+    if from == to || !in_line {
+        return Err(MoveError::NotInLine(from, to))
     }
+    let (row_step, column_step) = (row_distance.signum(), column_distance.signum());
+    let occupied = |position: Position| board[position.row][position.column].is_some();
+    let mut square = step(board, from, row_step, column_step).expect("the end of the move is on the board");
+    while square != to {
+        if occupied(square) {
+            return Err(MoveError::BlockedPath(square))
+        }
+        square = step(board, square, row_step, column_step).expect("the end of the move is on the board");
+    }
+    if occupied(to) {
+        return Err(MoveError::DestinationOccupied(to))
+    }
+    match step(board, to, row_step, column_step) {
+        Some(next) if !occupied(next) => Err(MoveError::NotSliding(to)),
+        _ => Ok(()),
+    }
+}
+
+fn zobrist_key(position: Position, piece: Piece) -> u64 {
+    let piece_index = match piece {
+        Piece::Player1 => 0,
+        Piece::Player2 => 1,
+        Piece::Neutrino => 2,
+    };
+    splitmix64(((position.row as u64) << 16) | ((position.column as u64) << 4) | piece_index)
+}
+
+/**
+ * The SplitMix64 mixing function, used to derive Zobrist keys without storing a table.
+ */
+const fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
 }
 
 impl Default for GameBoard {
     fn default() -> Self {
-        let mut board: [[Option<Piece>; 5]; 5] = Default::default();
-        board[2][2] = Some(Piece::Neutrino);
-        
-        for column in 0..5 {
-            board[0][column] = Some(Piece::Player1);
-            board[4][column] = Some(Piece::Player2)
-        }
-        Self { board: board, neutrino_position: (2,2).into(), to_move: Player::Player1 }
+        Self::start(RuleSet::default())
     }
 }
 
@@ -360,7 +982,7 @@ impl Display for GameBoard {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut board_string= String::new();
 
-        for row in &self.board {
+        for row in self.board.rows() {
             let mut row_string = String::new();
             for column in row {
                 row_string.push(
@@ -379,3 +1001,342 @@ impl Display for GameBoard {
         writeln!(f, "{}", board_string)
     }
 }
+
+/*
+ * The compact binary form of squares, moves and positions, used by binary serde formats and self-play datasets.
+ * A square is one byte with the row in the high and the column in the low four bits, a turn four bytes:
+ * the neutrino move and the piece move, the missing neutrino move of a piece-only turn written as a move
+ * from the piece's square to itself. A board is the size, with the high bit set when Player2 is to move,
+ * followed by two bits per square row by row (eight bytes for a 5x5 board). Boards are written without their rules.
+ */
+
+/// The bit of a compact board's first byte set when Player2 is to move.
+const COMPACT_PLAYER2_BIT: u8 = 0x80;
+
+impl Position {
+    pub fn to_byte(self) -> u8 {
+        ((self.row << 4) | self.column) as u8
+    }
+
+    /**
+     * The square of a byte written by `to_byte`, `None` if it is off every board.
+     */
+    pub fn from_byte(byte: u8) -> Option<Position> {
+        let (row, column) = ((byte >> 4) as usize, (byte & 0xF) as usize);
+        (row < MAX_SIZE && column < MAX_SIZE).then_some(Position::new(row, column))
+    }
+}
+
+impl TurnMove {
+    pub fn to_bytes(&self) -> [u8; 4] {
+        let from = self.piece_move.from_position;
+        let neutrino_move = self.neutrino_move.clone().unwrap_or(MoveType::new(from, from));
+        [neutrino_move.from_position, neutrino_move.to_position, self.piece_move.from_position, self.piece_move.to_position]
+            .map(Position::to_byte)
+    }
+
+    /**
+     * The turn written by `to_bytes`, `None` if a square is off every board.
+     */
+    pub fn from_bytes(bytes: [u8; 4]) -> Option<TurnMove> {
+        let [neutrino_from, neutrino_to, piece_from, piece_to] = bytes.map(Position::from_byte);
+        let (neutrino_move, piece_move) = (MoveType::new(neutrino_from?, neutrino_to?), MoveType::new(piece_from?, piece_to?));
+        match neutrino_move.from_position == neutrino_move.to_position {
+            true => Some(TurnMove::piece_only(piece_move)),
+            false => Some(TurnMove::new(neutrino_move, piece_move)),
+        }
+    }
+}
+
+impl GameBoard {
+    /**
+     * The number of bytes of a compact board of the given size.
+     */
+    pub fn compact_len(size: usize) -> usize {
+        1 + (size * size).div_ceil(4)
+    }
+
+    /**
+     * The board in its compact binary form, without its rules.
+     */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let size = self.size();
+        let mut packed = vec![0u8; Self::compact_len(size)];
+        packed[0] = size as u8;
+        if self.to_move == Player::Player2 {
+            packed[0] |= COMPACT_PLAYER2_BIT;
+        }
+        for square in 0..size * size {
+            let bits = match self.board[square / size][square % size] {
+                None => 0,
+                Some(Piece::Player1) => 1,
+                Some(Piece::Player2) => 2,
+                Some(Piece::Neutrino) => 3,
+            };
+            packed[1 + square / 4] |= bits << (2 * (square % 4));
+        }
+        packed
+    }
+
+    /**
+     * Reads a board written by `to_bytes`, played by the standard rules like a board read from notation.
+     */
+    pub fn from_bytes(packed: &[u8]) -> Result<GameBoard, &'static str> {
+        let Some(&header) = packed.first() else {
+            return Err("a compact board starts with its size")
+        };
+        let size = (header & !COMPACT_PLAYER2_BIT) as usize;
+        if !(MIN_SIZE..=MAX_SIZE).contains(&size) || packed.len() != Self::compact_len(size) {
+            return Err("a compact board is as long as its size says")
+        }
+        let mut board = Squares::empty(size);
+        for square in 0..size * size {
+            board[square / size][square % size] = match (packed[1 + square / 4] >> (2 * (square % 4))) & 0b11 {
+                0 => None,
+                1 => Some(Piece::Player1),
+                2 => Some(Piece::Player2),
+                _ => Some(Piece::Neutrino),
+            };
+        }
+        crate::notation::check_pieces(&board)?;
+        let to_move = if header & COMPACT_PLAYER2_BIT == 0 {Player::Player1} else {Player::Player2};
+        Ok(GameBoard::new(board, to_move))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /**
+     * Plays the moves picked by the choices (modulo the number of legal moves) from the start until the game ends,
+     * returning every board before a move together with the move.
+     */
+    fn play_choices(start: GameBoard, choices: &[usize]) -> Vec<(GameBoard, TurnMove)> {
+        let mut board = start;
+        let mut played = vec![];
+        for choice in choices {
+            let actions = board.actions();
+            if actions.is_empty() {
+                break
+            }
+            let turn_move = actions[choice % actions.len()].clone();
+            played.push((board.clone(), turn_move.clone()));
+            board.make_move(&turn_move);
+        }
+        played
+    }
+
+    /// A cycle after `c3a3/a1e1` on `1..../...../..X../...../....2 1`: the neutrino slides between a3 and e3 while the pieces slide along their home rows.
+    const CYCLE: [&str; 4] = ["a3e3/e5a5", "e3a3/e1a1", "a3e3/a5e5", "e3a3/a1e1"];
+
+    /**
+     * The board after `c3a3/a1e1` and the cycle played the given number of times.
+     */
+    fn after_cycles(rules: &str, cycles: usize) -> GameBoard {
+        let mut board = "1..../...../..X../...../....2 1".parse::<GameBoard>().unwrap().with_rules(rules.parse().unwrap());
+        board.play(&"c3a3/a1e1".parse().unwrap()).unwrap();
+        for turn_move in CYCLE.iter().cycle().take(4 * cycles) {
+            board.play(&turn_move.parse().unwrap()).unwrap();
+        }
+        board
+    }
+
+    #[test]
+    fn threefold_repetition_draws() {
+        let board = after_cycles("standard", 1);
+        assert_eq!(board.repetitions(), 1);
+        assert_eq!(board.outcome(), Outcome::Ongoing);
+        let board = after_cycles("standard", 2);
+        assert_eq!(board.outcome(), Outcome::Draw(Reason::Repetition));
+        assert!(board.is_terminal());
+        assert_eq!(board.try_result(&CYCLE[0].parse().unwrap()), Err(MoveError::GameOver));
+        assert_eq!(after_cycles("no-repetition-draw", 2).outcome(), Outcome::Ongoing);
+        assert_eq!(after_cycles("repetition-draw=2", 1).outcome(), Outcome::Draw(Reason::Repetition));
+    }
+
+    #[test]
+    fn cloned_boards_share_their_history() {
+        let mut board = after_cycles("standard", 1);
+        let clone = board.clone();
+        assert!(Arc::ptr_eq(board.history.shared.as_ref().unwrap(), clone.history.shared.as_ref().unwrap()));
+        assert_eq!(clone.repetitions(), 1);
+        //moves made before cloning can be unmade on the clone
+        let undo = board.make_move(&CYCLE[0].parse().unwrap());
+        let mut clone = board.clone();
+        assert_eq!((clone.history.own.len(), clone.repetitions()), (0, 1));
+        clone.unmake_move(undo.clone());
+        board.unmake_move(undo);
+        assert_eq!(clone, board);
+        assert_eq!(clone.repetitions(), 1);
+    }
+
+    #[test]
+    fn turns_without_progress_draw() {
+        let board = after_cycles("no-repetition-draw,no-progress-draw=9", 2);
+        assert_eq!(board.turns_without_progress(), 9);
+        assert_eq!(board.outcome(), Outcome::Draw(Reason::NoProgress));
+        assert_eq!(after_cycles("no-repetition-draw,no-progress-draw=10", 2).outcome(), Outcome::Ongoing);
+    }
+
+    #[test]
+    fn illegal_moves_are_rejected_with_their_reason() {
+        let position = |square: &str| square.parse::<Position>().unwrap();
+        let cases = [
+            ("11111/...../..X../...../22222 2", "c3c4/a1a2", MoveError::WrongPiece(position("a1"))),
+            ("11111/...../..X../...../22222 2", "b3b4/a5a4", MoveError::NeutrinoNotAtSource(position("b3"))),
+            ("11111/...../..X../...../22222 2", "c3c4/c5c3", MoveError::BlockedPath(position("c4"))),
+            ("11111/...../..X../...../22222 2", "c3c4/a5a4", MoveError::NotSliding(position("a4"))),
+            ("11111/...../..X../....2/2222. 2", "c3c2/e4e5", MoveError::HomeRowLimit),
+            ("11111/...../..X../...../22222 2", "c3c4/a5a9", MoveError::OffBoard(position("a9"))),
+            ("11111/...../...../...../22X22 1", "c5c4/a1a4", MoveError::GameOver),
+        ];
+        for (board, turn_move, error) in cases {
+            let board: GameBoard = board.parse().unwrap();
+            assert_eq!(board.try_result(&turn_move.parse().unwrap()), Err(error), "{turn_move} on {}", board.to_notation());
+        }
+    }
+
+    #[test]
+    fn the_first_turn_moves_only_a_piece() {
+        let turn = |notation: &str| notation.parse::<TurnMove>().unwrap();
+        let start = |rules: &str| GameBoard::start(rules.parse().unwrap());
+
+        //the official first turn moves only a piece, every later turn the neutrino too
+        let board = start("standard");
+        assert_eq!(board.try_result(&turn("c3c4/a1a4")), Err(MoveError::NeutrinoMoveOnFirstTurn));
+        let board = board.try_result(&turn("a1a4")).unwrap();
+        assert_eq!(board.try_result(&turn("b5b2")), Err(MoveError::MissingNeutrinoMove));
+        assert!(board.try_result(&turn("c3c4/b5b2")).is_ok());
+        let board = start("full-first-turn");
+        assert_eq!(board.try_result(&turn("a1a4")), Err(MoveError::MissingNeutrinoMove));
+        assert!(board.try_result(&turn("c3c4/a1a4")).is_ok());
+    }
+
+    #[test]
+    fn pieces_slide_across_larger_boards() {
+        let turn = |notation: &str| notation.parse::<TurnMove>().unwrap();
+        let start = |rules: &str| GameBoard::start(rules.parse().unwrap());
+
+        //the neutrino starts in the centre
+        let board = start("size=7");
+        assert_eq!(board.neutrino_position(), Position::new(3, 3));
+        assert!(board.try_result(&turn("a1a6")).is_ok());
+        assert_eq!(board.try_result(&turn("a1a4")), Err(MoveError::NotSliding(Position::new(3, 0))));
+        assert_eq!(start("standard").try_result(&turn("a1a6")), Err(MoveError::OffBoard(Position::new(5, 0))));
+    }
+
+    #[test]
+    fn rule_variants_are_played() {
+        let turn = |notation: &str| notation.parse::<TurnMove>().unwrap();
+        let start = |rules: &str| GameBoard::start(rules.parse().unwrap());
+
+        let board = start("start=1.1.1/.1.1./..X../.2.2./2.2.2");
+        assert!(board.is_piece_only_turn());
+        assert!(board.try_result(&turn("b2b1")).is_ok());
+        assert_eq!(board.try_result(&turn("a1a5")), Err(MoveError::DestinationOccupied(Position::new(4, 0))));
+
+        //the last piece may only come back home without the home row limit
+        let board: GameBoard = "11111/...../..X../....2/2222. 2".parse().unwrap();
+        assert_eq!(board.try_result(&turn("c3c2/e4e5")), Err(MoveError::HomeRowLimit));
+        assert!(board.with_rules("no-home-row-limit".parse().unwrap()).try_result(&turn("c3c2/e4e5")).is_ok());
+
+        //the limit counts the pieces the player has, not the board size
+        let board: GameBoard = "111../...../..X../....2/22... 2".parse().unwrap();
+        assert_eq!(board.try_result(&turn("c3c2/e4e5")), Err(MoveError::HomeRowLimit));
+        assert!(!board.actions().contains(&turn("c3c2/e4e5")));
+        let board: GameBoard = "111../...../..X.2/....2/22... 2".parse().unwrap();
+        assert!(board.try_result(&turn("c3c2/e4e5")).is_ok());
+        assert!(board.actions().contains(&turn("c3c2/e4e5")));
+
+        //the neutrino on the mover's own row wins by the official rules and loses with own-row-loses
+        let board: GameBoard = "11.11/...../..X../...../22222 1".parse().unwrap();
+        let after = board.try_result(&turn("c3c1/a1a4")).unwrap();
+        assert_eq!(after.outcome(), Outcome::Win(Player::Player1, Reason::NeutrinoHomeRow));
+        assert_eq!(after.try_result(&turn("c1c2/b5b2")), Err(MoveError::GameOver));
+        let after = board.with_rules("own-row-loses".parse().unwrap()).try_result(&turn("c3c1/a1a4")).unwrap();
+        assert_eq!(after.outcome(), Outcome::Win(Player::Player2, Reason::NeutrinoHomeRow));
+
+        //a player who cannot move the neutrino loses, or draws with immobilized-draw
+        for (rules, outcome) in [("standard", Outcome::Win(Player::Player1, Reason::NeutrinoImmobilized)),
+                                 ("immobilized-draw", Outcome::Draw(Reason::NeutrinoImmobilized))] {
+            let mut board = start(rules);
+            for turn_move in ["c1e3", "c3d3/c5d4", "d3e4/b1d3"] {
+                board = board.try_result(&turn(turn_move)).unwrap();
+            }
+            assert_eq!(board.outcome(), outcome);
+            assert!(board.legal_moves().is_empty());
+        }
+    }
+
+    #[test]
+    fn off_board_squares_are_not_in_move_lists() {
+        let legal_moves = GameBoard::default().legal_moves();
+        assert!(legal_moves.contains(&"a1a4".parse().unwrap()));
+        //the square past the last column of a3 packs into the byte of a4
+        let off_board = TurnMove::piece_only(MoveType::new(Position::new(0, 0), Position::new(2, 16)));
+        assert_eq!(off_board.to_bytes(), "a1a4".parse::<TurnMove>().unwrap().to_bytes());
+        assert!(!legal_moves.contains(&off_board));
+    }
+
+    proptest! {
+        #[test]
+        fn make_then_unmake_restores_the_board(choices in prop::collection::vec(any::<usize>(), 1..60)) {
+            let played = play_choices(GameBoard::default(), &choices);
+            let mut board = GameBoard::default();
+            let mut undo_records = vec![];
+            for (_, turn_move) in &played {
+                undo_records.push(board.make_move(turn_move));
+            }
+            for ((before, _), undo) in played.iter().zip(undo_records).rev() {
+                board.unmake_move(undo);
+                prop_assert_eq!(&board, before);
+            }
+            prop_assert_eq!(board, GameBoard::default());
+        }
+
+        #[test]
+        fn make_move_agrees_with_try_result(choices in prop::collection::vec(any::<usize>(), 1..60)) {
+            for (before, turn_move) in play_choices(GameBoard::default(), &choices) {
+                let mut made = before.clone();
+                made.make_move(&turn_move);
+                prop_assert_eq!(&made, &before.try_result(&turn_move).unwrap());
+                let neutrino_position = turn_move.neutrino_move.as_ref().map_or(before.neutrino_position(), |neutrino_move| neutrino_move.to_position);
+                prop_assert_eq!(made.neutrino_position(), neutrino_position);
+                prop_assert_eq!(made.hash(), made.zobrist_hash());
+            }
+        }
+
+        #[test]
+        fn staged_generation_yields_the_legal_moves(choices in prop::collection::vec(any::<usize>(), 1..60)) {
+            for (board, _) in play_choices(GameBoard::default(), &choices) {
+                let legal_moves = board.legal_moves();
+                let staged: Vec<TurnMove> = board.neutrino_moves()
+                    .flat_map(|neutrino_move| board.piece_moves(neutrino_move.as_ref()).collect::<Vec<_>>())
+                    .collect();
+                prop_assert_eq!(legal_moves.iter().collect::<Vec<_>>(), staged);
+                prop_assert_eq!(board.has_any_legal_move(), !legal_moves.is_empty());
+                for turn_move in &legal_moves {
+                    prop_assert!(board.try_result(&turn_move).is_ok());
+                }
+            }
+        }
+
+        #[test]
+        fn games_on_every_board_size_are_consistent(size in MIN_SIZE..=MAX_SIZE, choices in prop::collection::vec(any::<usize>(), 1..60)) {
+            let rules = RuleSet { size, ..RuleSet::default() };
+            for (before, turn_move) in play_choices(GameBoard::start(rules), &choices) {
+                let mut made = before.clone();
+                let undo = made.make_move(&turn_move);
+                prop_assert_eq!(&made, &before.try_result(&turn_move).unwrap());
+                prop_assert_eq!(made.hash(), made.zobrist_hash());
+                let parsed: GameBoard = made.to_notation().parse().unwrap();
+                prop_assert_eq!(parsed.squares(), made.squares());
+                made.unmake_move(undo);
+                prop_assert_eq!(made, before);
+            }
+        }
+    }
+}
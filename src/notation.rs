@@ -0,0 +1,259 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::neutrino_board::{GameBoard, MoveType, Piece, Player, Position, Squares, TurnMove, MAX_SIZE, MIN_SIZE};
+
+/**
+ * Error returned when a string is not valid notation.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseNotationError {
+    input: String,
+    reason: &'static str,
+}
+
+impl ParseNotationError {
+    pub fn new(input: &str, reason: &'static str) -> Self {
+        Self { input: input.to_string(), reason }
+    }
+}
+
+impl Display for ParseNotationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid notation '{}': {}", self.input, self.reason)
+    }
+}
+
+impl std::error::Error for ParseNotationError {}
+
+/*
+ * A position is written row by row starting with row 1, rows separated by slashes,
+ * followed by the player to move: the default start position is "11111/...../..X../...../22222 1".
+ * Boards of other sizes have as many rows as columns, from 3x3 to 9x9.
+ *
+ * Squares are written as a column letter followed by a 1-based row number,
+ * so board[0][0] is "a1" and board[4][2] is "c5". The squares of a 9x9 board go up to "i9".
+ * A move is the source square followed by the destination square ("c3c5"),
+ * and a turn is the neutrino move and the piece move separated by a slash ("c3c5/a1a2").
+ * A piece-only first turn is written as just the piece move ("a1a2").
+ */
+
+impl Position {
+    /**
+     * The square in notation, e.g. `c3`.
+     */
+    pub fn to_notation(self) -> String {
+        format!("{}{}", (b'a' + self.column as u8) as char, self.row + 1)
+    }
+
+    /**
+     * Parses a square from the front of the input, returning the position and the remaining input.
+     */
+    fn parse_prefix(input: &str) -> Result<(Position, &str), ParseNotationError> {
+        let mut chars = input.char_indices();
+        let column = match chars.next() {
+            Some((_, letter)) if letter.is_ascii_lowercase() => letter as usize - 'a' as usize,
+            _ => return Err(ParseNotationError::new(input, "expected a column letter")),
+        };
+        let digits_end = input[1..]
+            .find(|c: char| !c.is_ascii_digit())
+            .map_or(input.len(), |index| index + 1);
+        let row: usize = input[1..digits_end]
+            .parse()
+            .map_err(|_| ParseNotationError::new(input, "expected a row number"))?;
+        if row == 0 {
+            return Err(ParseNotationError::new(input, "rows are numbered from 1"));
+        }
+        Ok((Position::new(row - 1, column), &input[digits_end..]))
+    }
+}
+
+impl FromStr for Position {
+    type Err = ParseNotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Position::parse_prefix(s)? {
+            (position, "") => Ok(position),
+            _ => Err(ParseNotationError::new(s, "unexpected characters after square")),
+        }
+    }
+}
+
+impl MoveType {
+    /**
+     * The move in notation, e.g. `c3c5`.
+     */
+    pub fn to_notation(&self) -> String {
+        format!("{}{}", self.from_position.to_notation(), self.to_position.to_notation())
+    }
+}
+
+impl FromStr for MoveType {
+    type Err = ParseNotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (from_position, rest) = Position::parse_prefix(s)?;
+        let to_position: Position = rest.parse().map_err(|_| ParseNotationError::new(s, "expected a destination square"))?;
+        Ok(MoveType::new(from_position, to_position))
+    }
+}
+
+impl TurnMove {
+    /**
+     * The turn in notation, e.g. `c3c5/a1a2`, or just `a1a2` for a piece-only first turn.
+     */
+    pub fn to_notation(&self) -> String {
+        match &self.neutrino_move {
+            Some(neutrino_move) => format!("{}/{}", neutrino_move.to_notation(), self.piece_move.to_notation()),
+            None => self.piece_move.to_notation(),
+        }
+    }
+}
+
+impl FromStr for TurnMove {
+    type Err = ParseNotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('/') {
+            Some((neutrino_move, piece_move)) => Ok(TurnMove::new(neutrino_move.parse()?, piece_move.parse()?)),
+            None => Ok(TurnMove::piece_only(s.parse()?)),
+        }
+    }
+}
+
+/**
+ * The pieces in notation, row by row, e.g. `11111/...../..X../...../22222`.
+ */
+pub fn squares_to_notation(squares: &Squares) -> String {
+    let rows: Vec<String> = squares.rows()
+        .map(|row| row.iter()
+            .map(|square| match square {
+                Some(Piece::Player1) => '1',
+                Some(Piece::Player2) => '2',
+                Some(Piece::Neutrino) => 'X',
+                None => '.',
+            })
+            .collect())
+        .collect();
+    rows.join("/")
+}
+
+/**
+ * Parses the pieces written row by row, checking that there is exactly one neutrino
+ * and that neither player has more pieces than the board is wide.
+ */
+pub fn squares_from_notation(s: &str) -> Result<Squares, ParseNotationError> {
+    let rows: Vec<&str> = s.split('/').collect();
+    let size = rows.len();
+    if !(MIN_SIZE..=MAX_SIZE).contains(&size) || rows.iter().any(|row| row.chars().count() != size) {
+        return Err(ParseNotationError::new(s, "expected 3 to 9 rows of as many squares"));
+    }
+    let mut squares = Squares::empty(size);
+    for (row_index, row) in rows.iter().enumerate() {
+        for (column_index, square) in row.chars().enumerate() {
+            squares[row_index][column_index] = match square {
+                '1' => Some(Piece::Player1),
+                '2' => Some(Piece::Player2),
+                'X' => Some(Piece::Neutrino),
+                '.' => None,
+                _ => return Err(ParseNotationError::new(s, "squares must be 1, 2, X or .")),
+            };
+        }
+    }
+    check_pieces(&squares).map_err(|reason| ParseNotationError::new(s, reason))?;
+    Ok(squares)
+}
+
+/**
+ * Checks the pieces of a board read from outside: exactly one neutrino, and at most as many pieces per player
+ * as the board is wide, which bounds the number of legal moves (see `MAX_MOVES`).
+ */
+pub(crate) fn check_pieces(squares: &Squares) -> Result<(), &'static str> {
+    let count = |piece: Piece| squares.rows().flatten().filter(|square| **square == Some(piece)).count();
+    if count(Piece::Neutrino) != 1 {
+        return Err("the board must have exactly one neutrino")
+    }
+    if count(Piece::Player1) > squares.size() || count(Piece::Player2) > squares.size() {
+        return Err("a player cannot have more pieces than the board is wide")
+    }
+    Ok(())
+}
+
+impl GameBoard {
+    /**
+     * The position in notation, e.g. `11111/...../..X../...../22222 1`.
+     */
+    pub fn to_notation(&self) -> String {
+        let to_move = match self.to_move() {
+            Player::Player1 => '1',
+            Player::Player2 => '2',
+        };
+        format!("{} {}", squares_to_notation(self.squares()), to_move)
+    }
+}
+
+impl FromStr for GameBoard {
+    type Err = ParseNotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (rows, to_move) = s
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| ParseNotationError::new(s, "expected the rows followed by the player to move"))?;
+        let to_move = match to_move.trim() {
+            "1" => Player::Player1,
+            "2" => Player::Player2,
+            _ => return Err(ParseNotationError::new(s, "the player to move must be 1 or 2")),
+        };
+        Ok(GameBoard::new(squares_from_notation(rows)?, to_move))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notation_is_parsed_and_written() {
+        assert_eq!("c3".parse::<Position>().unwrap(), Position::new(2, 2));
+        assert_eq!("i9".parse::<Position>().unwrap(), Position::new(8, 8));
+        //squares off every board parse, moves onto them are rejected when played
+        assert_eq!("a10".parse::<Position>().unwrap(), Position::new(9, 0));
+        assert_eq!("c3c5".parse::<MoveType>().unwrap(), MoveType::new(Position::new(2, 2), Position::new(4, 2)));
+        for turn_move in ["c3c5/a1a2", "a1a4", "e5a1/i9i1"] {
+            assert_eq!(turn_move.parse::<TurnMove>().unwrap().to_notation(), turn_move);
+        }
+        assert_eq!("a1a4".parse::<TurnMove>().unwrap().neutrino_move, None);
+
+        let start = "11111/...../..X../...../22222 1";
+        assert_eq!(start.parse::<GameBoard>().unwrap(), GameBoard::default());
+        assert_eq!(GameBoard::default().to_notation(), start);
+        let board: GameBoard = " 1.1/.X./2.2 2 ".parse().unwrap();
+        assert_eq!((board.size(), board.to_move(), board.neutrino_position()), (3, Player::Player2, Position::new(1, 1)));
+        assert_eq!(board.to_notation(), "1.1/.X./2.2 2");
+    }
+
+    #[test]
+    fn invalid_notation_is_rejected() {
+        for square in ["", "3c", "c", "C3", "c0", "c3x", "c-1"] {
+            assert!(square.parse::<Position>().is_err(), "{square}");
+        }
+        for turn_move in ["c3", "c3c", "c3-c5", "c3c5/", "/a1a2", "c3c5/a1a2/b1b2", "c3c5 a1a2"] {
+            assert!(turn_move.parse::<TurnMove>().is_err(), "{turn_move}");
+        }
+        for board in [
+            "11111/...../..X../...../22222",
+            "11111/...../..X../...../22222 3",
+            "11111/..../..X../...../22222 1",
+            "11111/...../..X../...../22222/..... 1",
+            "11/X. 1",
+            "1111a/...../..X../...../22222 1",
+            "11111/...../..X../..X../22222 1",
+            "11111/...../...../...../22222 1",
+            "111/1X./... 1",
+        ] {
+            assert!(board.parse::<GameBoard>().is_err(), "{board}");
+        }
+        let error = "c0".parse::<Position>().unwrap_err();
+        assert_eq!(error.to_string(), "invalid notation 'c0': rows are numbered from 1");
+    }
+}
@@ -0,0 +1,273 @@
+use std::{collections::HashMap, fmt::Display, fs, io, path::Path, str::FromStr};
+
+use rand::{seq::IndexedRandom, Rng};
+
+use crate::{game_record::GameRecord, neutrino_board::{GameBoard, TurnMove, MAX_SIZE, MIN_SIZE}, notation::ParseNotationError, outcome::{Outcome, Reason}};
+
+/**
+ * How a move is picked when the book has several moves for a position.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookSelection {
+    /// Random move, with probability proportional to how often it was played.
+    Weighted,
+    /// The move with the best average score for the player to move.
+    Best,
+}
+
+/**
+ * A move stored in the book, with the number of games (or weighted search results) it was seen in
+ * and the sum of their scores from the perspective of the player making the move.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookMove {
+    pub turn_move: TurnMove,
+    pub weight: u32,
+    pub score: f64,
+}
+
+impl BookMove {
+    /**
+     * The score of the move per game, from the mover's perspective.
+     */
+    pub fn average_score(&self) -> f64 {
+        self.score / self.weight as f64
+    }
+}
+
+/// The first line of a book file. Books written before positions were keyed by their canonical hash have no such line.
+const BOOK_HEADER: &str = "neutrino-book 2";
+
+/**
+ * Moves for known positions, keyed by the board size and `GameBoard::canonical_hash` so that symmetric positions
+ * share their moves. The moves are stored as played in the canonical position and mapped back to the position looked up.
+ *
+ * The book is stored as text: the line `neutrino-book 2`, then one move per line: `<size> <hash in hex> <move> <weight> <score>`.
+ * Lines starting with `#` are comments.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct OpeningBook {
+    positions: HashMap<(usize, u64), Vec<BookMove>>,
+}
+
+impl OpeningBook {
+    /**
+     * The book moves for a board. Moves that are not legal on the board (i.e. hash collisions) are skipped.
+     */
+    pub fn moves(&self, board: &GameBoard) -> Vec<BookMove> {
+        let (canonical, symmetry) = board.canonical();
+        let Some(book_moves) = self.positions.get(&(board.size(), canonical.hash())) else {
+            return vec![]
+        };
+        let actions = board.legal_moves();
+        book_moves.iter()
+            .map(|book_move| BookMove { turn_move: symmetry.turn_move(&book_move.turn_move, board.size()), ..book_move.clone() })
+            .filter(|book_move| actions.contains(&book_move.turn_move))
+            .collect()
+    }
+
+    /**
+     * Picks a book move for the board, if the position is in the book.
+     */
+    pub fn select<R: Rng + ?Sized>(&self, board: &GameBoard, selection: BookSelection, rng: &mut R) -> Option<TurnMove> {
+        let moves = self.moves(board);
+        let book_move = match selection {
+            BookSelection::Weighted => moves.choose_weighted(rng, |book_move| book_move.weight).ok(),
+            BookSelection::Best => moves.iter().max_by(|x, y| {
+                x.average_score().total_cmp(&y.average_score()).then(x.weight.cmp(&y.weight))
+            }),
+        };
+        book_move.map(|book_move| book_move.turn_move.clone())
+    }
+
+    /**
+     * The number of positions in the book.
+     */
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    /**
+     * Whether the book has no positions.
+     */
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /**
+     * Reads a book written by `save`.
+     */
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    /**
+     * Writes the book in its text format, one move per line.
+     */
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl Display for OpeningBook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{BOOK_HEADER}")?;
+        writeln!(f, "# <size> <hash> <move> <weight> <score>")?;
+        //sorted so that saving the same book twice gives the same file
+        let mut keys: Vec<&(usize, u64)> = self.positions.keys().collect();
+        keys.sort();
+        for key @ (size, hash) in keys {
+            for book_move in &self.positions[key] {
+                writeln!(f, "{} {:016x} {} {} {}", size, hash, book_move.turn_move.to_notation(), book_move.weight, book_move.score)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for OpeningBook {
+    type Err = ParseNotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut book = OpeningBook::default();
+        let mut lines = s.lines().map(str::trim).filter(|line| !line.is_empty());
+        match lines.next() {
+            Some(BOOK_HEADER) => {},
+            Some(line) => return Err(ParseNotationError::new(line, "not a version 2 book, books written by older versions must be rebuilt")),
+            None => return Err(ParseNotationError::new(s, "empty book")),
+        }
+        for line in lines.filter(|line| !line.starts_with('#')) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [size, hash, turn_move, weight, score] = fields[..] else {
+                return Err(ParseNotationError::new(line, "expected <size> <hash> <move> <weight> <score>"))
+            };
+            let size = size.parse().ok()
+                .filter(|size| (MIN_SIZE..=MAX_SIZE).contains(size))
+                .ok_or_else(|| ParseNotationError::new(line, "invalid board size"))?;
+            let hash = u64::from_str_radix(hash, 16).map_err(|_| ParseNotationError::new(line, "invalid hash"))?;
+            let turn_move: TurnMove = turn_move.parse()?;
+            //the symmetries map squares by the board size, which squares off the board would overflow
+            let slides = turn_move.neutrino_move.iter().chain([&turn_move.piece_move]);
+            if slides.flat_map(|slide| [slide.from_position, slide.to_position]).any(|square| square.row >= size || square.column >= size) {
+                return Err(ParseNotationError::new(line, "square off the board"))
+            }
+            let book_move = BookMove {
+                turn_move,
+                weight: weight.parse().map_err(|_| ParseNotationError::new(line, "invalid weight"))?,
+                score: score.parse().map_err(|_| ParseNotationError::new(line, "invalid score"))?,
+            };
+            book.positions.entry((size, hash)).or_default().push(book_move);
+        }
+        Ok(book)
+    }
+}
+
+/**
+ * Collects moves from game records and search results into an `OpeningBook`.
+ */
+pub struct OpeningBookBuilder {
+    max_ply: usize,
+    positions: HashMap<(usize, u64), Vec<BookMove>>,
+}
+
+impl OpeningBookBuilder {
+    /**
+     * A builder that only stores the first `max_ply` moves of each game.
+     */
+    pub fn new(max_ply: usize) -> Self {
+        Self { max_ply, positions: HashMap::new() }
+    }
+
+    /**
+     * Adds the opening moves of a finished game, scored by the game's result.
+     * Unfinished games and games drawn by the move limit are ignored.
+     */
+    pub fn add_record(&mut self, record: &GameRecord) {
+        if matches!(record.outcome(), Outcome::Ongoing | Outcome::Draw(Reason::MoveLimit)) {
+            return
+        }
+        let positions = record.positions();
+        for (board, turn_move) in positions.iter().zip(&record.moves).take(self.max_ply) {
+            let score = record.result_for(board.to_move()).expect("Finished games have a result");
+            self.add(board, turn_move.clone(), 1, score);
+        }
+    }
+
+    /**
+     * Adds a move found by a search, with the search's value for the player to move.
+     * The weight says how many games the result should count as.
+     */
+    pub fn add_search_result(&mut self, board: &GameBoard, turn_move: TurnMove, value: f64, weight: u32) {
+        self.add(board, turn_move, weight, value * weight as f64);
+    }
+
+    fn add(&mut self, board: &GameBoard, turn_move: TurnMove, weight: u32, score: f64) {
+        let (canonical, symmetry) = board.canonical();
+        let turn_move = symmetry.turn_move(&turn_move, board.size());
+        let book_moves = self.positions.entry((board.size(), canonical.hash())).or_default();
+        match book_moves.iter_mut().find(|book_move| book_move.turn_move == turn_move) {
+            Some(book_move) => {
+                book_move.weight += weight;
+                book_move.score += score;
+            },
+            None => book_moves.push(BookMove { turn_move, weight, score }),
+        }
+    }
+
+    /**
+     * Builds the book, dropping moves seen with less than `min_weight` weight.
+     */
+    pub fn build(self, min_weight: u32) -> OpeningBook {
+        let positions = self.positions
+            .into_iter()
+            .map(|(hash, moves)| (hash, moves.into_iter().filter(|book_move| book_move.weight >= min_weight).collect::<Vec<_>>()))
+            .filter(|(_, moves)| !moves.is_empty())
+            .collect();
+        OpeningBook { positions }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /**
+     * A book of the first two moves of a game and of its mirror image.
+     */
+    fn book() -> OpeningBook {
+        let mut builder = OpeningBookBuilder::new(2);
+        for moves in ["a1a4 c3c4/b5b2 c4b5/b1a1", "e1e4 c3c4/d5d2 c4d5/d1e1"] {
+            builder.add_record(&format!("[Result \"0-1\"]\n{moves}\n").parse().unwrap());
+        }
+        builder.build(1)
+    }
+
+    #[test]
+    fn books_are_written_and_read_back() {
+        let book = book();
+        assert_eq!(book.len(), 2);
+        let moves = book.moves(&GameBoard::default());
+        assert_eq!(moves.iter().map(|book_move| (book_move.turn_move.to_notation(), book_move.weight)).collect::<Vec<_>>(),
+            [("a1a4".to_string(), 1), ("e1e4".to_string(), 1)]);
+        let text = book.to_string();
+        assert!(text.starts_with("neutrino-book 2\n"));
+        let read: OpeningBook = text.parse().unwrap();
+        assert_eq!(read.to_string(), text);
+        assert_eq!(read.moves(&GameBoard::default()), moves);
+    }
+
+    #[test]
+    fn bad_books_are_rejected() {
+        let hash = format!("{:016x}", GameBoard::default().canonical().0.hash());
+        assert!(format!("neutrino-book 2\n5 {hash} a1a4 1 1").parse::<OpeningBook>().is_ok());
+        //books keyed by plain hashes have no size or header
+        assert!(format!("# neutrino opening book: <hash> <move> <weight> <score>\n{hash} a1a4 1 1").parse::<OpeningBook>().is_err());
+        assert!("".parse::<OpeningBook>().is_err());
+        for line in [format!("5 {hash} c3c4/z1a2 1 1"), format!("5 {hash} a1f1 1 1"), format!("12 {hash} a1a4 1 1"),
+                     format!("5 {hash} a1a4 1"), format!("5 {hash}0 a1a4 1 1"), format!("5 {hash} a1a4 -1 1")] {
+            assert!(format!("neutrino-book 2\n{line}").parse::<OpeningBook>().is_err(), "{line}");
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{neutrino_board::Player, notation::ParseNotationError};
+
+/**
+ * Why a game ended.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reason {
+    /// The neutrino was moved onto a home row.
+    NeutrinoHomeRow,
+    /// The player to move could not move the neutrino.
+    NeutrinoImmobilized,
+    /// The player to move could move the neutrino, but none of their pieces afterwards.
+    OpponentImmobilized,
+    Resignation,
+    Timeout,
+    /// The game reached the maximum number of moves.
+    MoveLimit,
+    /// The same position occurred as often as the rules allow, see `RuleSet::repetition_draw`.
+    Repetition,
+    /// The neutrino stayed on its row for as many turns as the rules allow, see `RuleSet::no_progress_draw`.
+    NoProgress,
+    /// An agent gave up without a move, see `Agent::try_get_move`.
+    Forfeit,
+    /// A network player disconnected from a running game.
+    Disconnect,
+}
+
+impl Reason {
+    const ALL: [Reason; 10] = [
+        Reason::NeutrinoHomeRow,
+        Reason::NeutrinoImmobilized,
+        Reason::OpponentImmobilized,
+        Reason::Resignation,
+        Reason::Timeout,
+        Reason::MoveLimit,
+        Reason::Repetition,
+        Reason::NoProgress,
+        Reason::Forfeit,
+        Reason::Disconnect,
+    ];
+
+    /**
+     * The reason as a single word, as written in the `Termination` tag of records and by the network protocol.
+     */
+    pub fn to_notation(&self) -> &'static str {
+        match self {
+            Reason::NeutrinoHomeRow => "home-row",
+            Reason::NeutrinoImmobilized => "neutrino-immobilized",
+            Reason::OpponentImmobilized => "immobilized",
+            Reason::Resignation => "resignation",
+            Reason::Timeout => "timeout",
+            Reason::MoveLimit => "move-limit",
+            Reason::Repetition => "repetition",
+            Reason::NoProgress => "no-progress",
+            Reason::Forfeit => "forfeit",
+            Reason::Disconnect => "disconnect",
+        }
+    }
+}
+
+impl Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Reason::NeutrinoHomeRow => "the neutrino reached a home row",
+            Reason::NeutrinoImmobilized => "the neutrino is immobilized",
+            Reason::OpponentImmobilized => "the opponent is immobilized",
+            Reason::Resignation => "resignation",
+            Reason::Timeout => "timeout",
+            Reason::MoveLimit => "move limit",
+            Reason::Repetition => "the position repeated",
+            Reason::NoProgress => "no progress",
+            Reason::Forfeit => "forfeit",
+            Reason::Disconnect => "disconnect",
+        };
+        write!(f, "{text}")
+    }
+}
+
+impl FromStr for Reason {
+    type Err = ParseNotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Reason::ALL.into_iter()
+            .find(|reason| reason.to_notation() == s)
+            .ok_or_else(|| ParseNotationError::new(s, "unknown termination reason"))
+    }
+}
+
+/**
+ * The state of a game: won by a player, drawn, or still running.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    Win(Player, Reason),
+    Draw(Reason),
+    Ongoing,
+}
+
+impl Outcome {
+    pub fn winner(&self) -> Option<Player> {
+        match self {
+            Outcome::Win(player, _) => Some(*player),
+            _ => None,
+        }
+    }
+
+    pub fn reason(&self) -> Option<Reason> {
+        match self {
+            Outcome::Win(_, reason) | Outcome::Draw(reason) => Some(*reason),
+            Outcome::Ongoing => None,
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        *self != Outcome::Ongoing
+    }
+
+    /**
+     * The utility from a player's perspective: 1 for a win, 0.5 for a draw and 0 for a loss.
+     */
+    pub fn utility(&self, player: Player) -> Option<f64> {
+        match self {
+            Outcome::Win(winner, _) => Some(if *winner == player {1.0} else {0.0}),
+            Outcome::Draw(_) => Some(0.5),
+            Outcome::Ongoing => None,
+        }
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Win(player, reason) => write!(f, "{player:?} wins ({reason})"),
+            Outcome::Draw(reason) => write!(f, "draw ({reason})"),
+            Outcome::Ongoing => write!(f, "ongoing"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::{agents::{human::Human, random_agent::RandomAgent}, match_runner, neutrino_board::GameBoard, rules::RuleSet};
+
+    /**
+     * The outcome after playing the moves from a position.
+     */
+    fn outcome_after(board: GameBoard, moves: &str) -> Outcome {
+        let mut board = board;
+        for turn_move in moves.split_whitespace() {
+            assert_eq!(board.outcome(), Outcome::Ongoing, "the game ended before {turn_move}");
+            board.play(&turn_move.parse().unwrap()).unwrap();
+        }
+        board.outcome()
+    }
+
+    #[test]
+    fn games_end_on_the_board() {
+        let start = GameBoard::default();
+        assert_eq!(outcome_after(start.clone(), "a1a4 c3c4/b5b2"), Outcome::Ongoing);
+        assert_eq!(outcome_after(start.clone(), "a1a4 c3c4/b5b2 c4b5/b1a1"), Outcome::Win(Player::Player2, Reason::NeutrinoHomeRow));
+        assert_eq!(outcome_after(start.clone(), "c1e3 c3d3/c5d4 d3e4/b1d3"), Outcome::Win(Player::Player1, Reason::NeutrinoImmobilized));
+        let draws = start.with_rules("immobilized-draw".parse().unwrap());
+        assert_eq!(outcome_after(draws, "c1e3 c3d3/c5d4 d3e4/b1d3"), Outcome::Draw(Reason::NeutrinoImmobilized));
+        //Player2's last piece is boxed in on a5 once b5 is taken
+        let boxed_in: GameBoard = "...../...../....X/11.../2...1 1".parse().unwrap();
+        assert_eq!(outcome_after(boxed_in, "e3e4/e5b5"), Outcome::Win(Player::Player1, Reason::OpponentImmobilized));
+
+        //the neutrino slides between a3 and e3 while the pieces slide along their home rows
+        let cycling: GameBoard = "1..../...../..X../...../....2 1".parse().unwrap();
+        let cycle = "c3a3/a1e1 a3e3/e5a5 e3a3/e1a1 a3e3/a5e5 e3a3/a1e1";
+        assert_eq!(outcome_after(cycling.clone(), &format!("{cycle} a3e3/e5a5 e3a3/e1a1 a3e3/a5e5 e3a3/a1e1")), Outcome::Draw(Reason::Repetition));
+        let without_repetitions = cycling.with_rules("no-repetition-draw,no-progress-draw=5".parse().unwrap());
+        assert_eq!(outcome_after(without_repetitions, cycle), Outcome::Draw(Reason::NoProgress));
+    }
+
+    #[test]
+    fn games_end_in_the_match_runner() {
+        let mut random = RandomAgent::default();
+        let record = match_runner::play_game(&mut random, &mut RandomAgent::default(), 1);
+        assert_eq!(record.outcome(), Outcome::Draw(Reason::MoveLimit));
+        //a human whose input is closed gives up without a move
+        let mut absent = Human::with_io(Box::new(io::empty()), Box::new(io::sink()));
+        let record = match_runner::play_game_with_rules(RuleSet::default(), &mut random, &mut absent, 10);
+        assert_eq!(record.outcome(), Outcome::Win(Player::Player1, Reason::Forfeit));
+    }
+
+    #[test]
+    fn reasons_round_trip() {
+        for reason in Reason::ALL {
+            assert_eq!(reason.to_notation().parse::<Reason>().unwrap(), reason);
+        }
+        assert!("checkmate".parse::<Reason>().is_err());
+        let outcome = Outcome::Win(Player::Player2, Reason::Timeout);
+        assert_eq!((outcome.winner(), outcome.reason(), outcome.utility(Player::Player1)), (Some(Player::Player2), Some(Reason::Timeout), Some(0.0)));
+        assert_eq!(Outcome::Draw(Reason::Resignation).utility(Player::Player1), Some(0.5));
+        assert_eq!((Outcome::Ongoing.reason(), Outcome::Ongoing.utility(Player::Player2), Outcome::Ongoing.is_over()), (None, None, false));
+    }
+}
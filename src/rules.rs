@@ -0,0 +1,216 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{neutrino_board::{Piece, Squares, MAX_SIZE, MIN_SIZE}, notation::{self, ParseNotationError}};
+
+/**
+ * The rules a game is played by: the official rules by default, or variants and house rules.
+ *
+ * In notation a rule set is `standard`, or its options separated by commas:
+ * - `full-first-turn`: Player1's first turn moves the neutrino too, not just a piece.
+ * - `size=<n>`: the game is played on an n by n board with n pieces each, e.g. `size=7`.
+ * - `start=<rows>`: the game starts from these pieces instead, e.g. `start=1.1.1/.1.1./..X../.2.2./2.2.2`.
+ *   The board is as large as the start position.
+ * - `no-home-row-limit`: all pieces may return to their home row.
+ * - `immobilized-draw`: a player who cannot move the neutrino draws instead of losing.
+ * - `own-row-loses`: the neutrino on a player's home row loses the game for that player instead of winning it.
+ * - `repetition-draw=<n>`: the game is drawn when a position occurs for the nth time, instead of the third.
+ * - `no-repetition-draw`: repeating positions never draws the game.
+ * - `no-progress-draw=<n>`: the game is drawn after n turns in a row that leave the neutrino on its row.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RuleSet {
+    /// Whether the first turn of the game moves only a piece, as in the official rules.
+    pub piece_only_first_turn: bool,
+    /// The number of rows and columns of the board, 5 by default. Always the size of the start position if there is one.
+    pub size: usize,
+    /// The pieces the game starts from, the players' home rows and the neutrino in the centre by default.
+    pub start: Option<Squares>,
+    /// Whether a player with all but one of their pieces on their home row is kept from moving the last back.
+    pub home_row_limit: bool,
+    /// Whether a player who cannot move the neutrino draws, rather than loses.
+    pub immobilized_neutrino_draws: bool,
+    /// Whether the neutrino on a player's home row loses the game for them, rather than wins it.
+    pub own_row_loses: bool,
+    /// How often a position must occur with the same player to move to draw the game, three times by default.
+    pub repetition_draw: Option<usize>,
+    /// How many turns in a row leaving the neutrino on its row draw the game, never by default.
+    pub no_progress_draw: Option<usize>,
+}
+
+impl RuleSet {
+    /// The official rules, with threefold repetition drawing so games cannot go on forever.
+    pub const STANDARD: RuleSet = RuleSet {
+        piece_only_first_turn: true,
+        size: 5,
+        start: None,
+        home_row_limit: true,
+        immobilized_neutrino_draws: false,
+        own_row_loses: false,
+        repetition_draw: Some(3),
+        no_progress_draw: None,
+    };
+
+    /**
+     * The pieces of the start position.
+     */
+    pub fn start_position(&self) -> Squares {
+        self.start.unwrap_or_else(|| standard_start(self.size))
+    }
+
+    /**
+     * The rule set in notation, e.g. `standard` or `full-first-turn,own-row-loses`.
+     */
+    pub fn to_notation(&self) -> String {
+        let mut options = vec![];
+        if !self.piece_only_first_turn {
+            options.push("full-first-turn".to_string());
+        }
+        match &self.start {
+            Some(start) => options.push(format!("start={}", notation::squares_to_notation(start))),
+            None if self.size != RuleSet::STANDARD.size => options.push(format!("size={}", self.size)),
+            None => {},
+        }
+        if !self.home_row_limit {
+            options.push("no-home-row-limit".to_string());
+        }
+        if self.immobilized_neutrino_draws {
+            options.push("immobilized-draw".to_string());
+        }
+        if self.own_row_loses {
+            options.push("own-row-loses".to_string());
+        }
+        match self.repetition_draw {
+            None => options.push("no-repetition-draw".to_string()),
+            Some(occurrences) if Some(occurrences) != RuleSet::STANDARD.repetition_draw => options.push(format!("repetition-draw={occurrences}")),
+            Some(_) => {},
+        }
+        if let Some(turns) = self.no_progress_draw {
+            options.push(format!("no-progress-draw={turns}"));
+        }
+        match options.is_empty() {
+            true => "standard".to_string(),
+            false => options.join(","),
+        }
+    }
+}
+
+/**
+ * The pieces of the usual start position: the players' pieces filling their home rows and the neutrino
+ * in the centre, or just above it on boards with an even size.
+ */
+fn standard_start(size: usize) -> Squares {
+    let mut board = Squares::empty(size);
+    board[size / 2][size / 2] = Some(Piece::Neutrino);
+
+    board[0].fill(Some(Piece::Player1));
+    board[size - 1].fill(Some(Piece::Player2));
+    board
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
+impl Display for RuleSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_notation())
+    }
+}
+
+impl FromStr for RuleSet {
+    type Err = ParseNotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = RuleSet::STANDARD;
+        let mut size = None;
+        for option in s.split(',').map(str::trim) {
+            match option {
+                "standard" => {},
+                "full-first-turn" => rules.piece_only_first_turn = false,
+                "no-home-row-limit" => rules.home_row_limit = false,
+                "immobilized-draw" => rules.immobilized_neutrino_draws = true,
+                "own-row-loses" => rules.own_row_loses = true,
+                "no-repetition-draw" => rules.repetition_draw = None,
+                _ => if let Some(rows) = option.strip_prefix("start=") {
+                    let start = notation::squares_from_notation(rows)?;
+                    rules.start = Some(start);
+                    rules.size = start.size();
+                } else if let Some(n) = option.strip_prefix("size=") {
+                    match n.parse() {
+                        Ok(n) if (MIN_SIZE..=MAX_SIZE).contains(&n) => size = Some(n),
+                        _ => return Err(ParseNotationError::new(option, "boards are 3 to 9 squares wide")),
+                    }
+                } else if let Some(n) = option.strip_prefix("repetition-draw=") {
+                    match n.parse() {
+                        Ok(n) if n >= 2 => rules.repetition_draw = Some(n),
+                        _ => return Err(ParseNotationError::new(option, "a position must occur at least twice to repeat")),
+                    }
+                } else if let Some(n) = option.strip_prefix("no-progress-draw=") {
+                    match n.parse() {
+                        Ok(n) if n >= 1 => rules.no_progress_draw = Some(n),
+                        _ => return Err(ParseNotationError::new(option, "expected a number of turns")),
+                    }
+                } else {
+                    return Err(ParseNotationError::new(option, "unknown rule"))
+                },
+            }
+        }
+        match (size, rules.start) {
+            (Some(size), Some(start)) if size != start.size() => Err(ParseNotationError::new(s, "the start position is not as large as the board")),
+            (Some(size), _) => Ok(RuleSet { size, ..rules }),
+            (None, _) => Ok(rules),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rule_sets_round_trip() {
+        for notation in [
+            "standard",
+            "full-first-turn",
+            "size=7",
+            "start=1.1.1/.1.1./..X../.2.2./2.2.2",
+            "no-home-row-limit,immobilized-draw,own-row-loses",
+            "no-repetition-draw",
+            "repetition-draw=2,no-progress-draw=40",
+            "full-first-turn,size=3,own-row-loses,no-progress-draw=1",
+        ] {
+            let rules: RuleSet = notation.parse().unwrap();
+            assert_eq!(rules.to_notation(), notation);
+            assert_eq!(rules.to_string().parse::<RuleSet>().unwrap(), rules);
+        }
+        let rules: RuleSet = " own-row-loses , size=9 ".parse().unwrap();
+        assert_eq!((rules.size, rules.own_row_loses, rules.start_position().size()), (9, true, 9));
+        //options equal to the standard rules are left out
+        assert_eq!("standard,size=5,repetition-draw=3".parse::<RuleSet>().unwrap(), RuleSet::STANDARD);
+        let start: RuleSet = "size=3,start=111/.X./222".parse().unwrap();
+        assert_eq!((start.size, start.to_notation()), (3, "start=111/.X./222".to_string()));
+    }
+
+    #[test]
+    fn invalid_rule_sets_are_rejected() {
+        for notation in [
+            "",
+            "official",
+            "full-first-turn,",
+            "size=2",
+            "size=10",
+            "size=big",
+            "start=11/22",
+            "start=111/.X./2222",
+            "size=5,start=111/.X./222",
+            "repetition-draw=1",
+            "repetition-draw=often",
+            "no-progress-draw=0",
+            "no-progress-draw=-3",
+        ] {
+            assert!(notation.parse::<RuleSet>().is_err(), "{notation}");
+        }
+    }
+}
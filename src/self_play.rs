@@ -0,0 +1,309 @@
+use std::{io::{self, Write}, str::FromStr, sync::{atomic::{AtomicUsize, Ordering}, mpsc}, thread};
+
+use rand::Rng;
+use serde_json::json;
+
+use crate::{agents::registry::ConfigError, engine_protocol::{AgentFactory, EngineAgent, GoLimits}, game_record::{result_to_notation, GameRecord}, neutrino_board::{GameBoard, Player, TurnMove}, notation::ParseNotationError, outcome::{Outcome, Reason}, rules::RuleSet};
+
+/*
+ * Self-play datasets hold one sample per searched move: the position, the move played, how often the
+ * search visited each root move, and the result of the game for the player to move.
+ *
+ * JSONL writes a sample per line:
+ *   {"game": 0, "ply": 2, "rules": "standard", "position": "<position>", "move": "c3c4/a1a2",
+ *    "visits": {"c3c4/a1a2": 120, ...}, "result": 1.0, "outcome": "1-0", "termination": "home-row"}
+ *
+ * The binary format starts with "NTSP", a version byte and the rules in notation after their length as a u16.
+ * Samples follow: the compact board (see `GameBoard::to_bytes`), the move played (`TurnMove::to_bytes`),
+ * the result for the player to move in halves (0 loss, 1 draw, 2 win), the number of visited moves as a u16
+ * and every visited move with its visits as a u32. Numbers are little-endian.
+ */
+
+const BINARY_MAGIC: &[u8; 4] = b"NTSP";
+const BINARY_VERSION: u8 = 1;
+
+/**
+ * How self-play games are played.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SelfPlayConfig {
+    pub games: usize,
+    /// How many games are played at the same time.
+    pub threads: usize,
+    /// The number of moves at the start of each game played at random instead of searched, for diverse openings.
+    /// They are not sampled.
+    pub random_plies: usize,
+    /// Games are drawn by the move limit after this many moves.
+    pub max_moves: usize,
+    /// The search limits of both agents.
+    pub limits: GoLimits,
+    pub rules: RuleSet,
+}
+
+impl Default for SelfPlayConfig {
+    fn default() -> Self {
+        Self { games: 100, threads: 1, random_plies: 0, max_moves: 200, limits: GoLimits::default(), rules: RuleSet::default() }
+    }
+}
+
+/**
+ * A searched move of a self-play game.
+ */
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub board: GameBoard,
+    pub turn_move: TurnMove,
+    /// How often the search visited each root move: the playouts for MCTS and the searched nodes for alpha-beta.
+    /// Empty for agents that do not analyse.
+    pub visits: Vec<(TurnMove, usize)>,
+}
+
+/**
+ * A finished self-play game and its samples, in the order the moves were played.
+ */
+#[derive(Debug, Clone)]
+pub struct SelfPlayGame {
+    /// The number of the game, from 0. Games played in parallel finish out of order.
+    pub index: usize,
+    pub record: GameRecord,
+    pub samples: Vec<Sample>,
+}
+
+/**
+ * Plays `config.games` games between agents from the two factories on `config.threads` threads,
+ * handing each game to `on_game` on the calling thread as soon as it is finished.
+ */
+pub fn run(config: &SelfPlayConfig, player1: &AgentFactory, player2: &AgentFactory, mut on_game: impl FnMut(SelfPlayGame)) {
+    let next_game = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..config.threads.max(1) {
+            let sender = sender.clone();
+            let next_game = &next_game;
+            scope.spawn(move || {
+                let mut rng = rand::rng();
+                loop {
+                    let index = next_game.fetch_add(1, Ordering::Relaxed);
+                    if index >= config.games {
+                        break
+                    }
+                    let game = play_game(index, config, player1, player2, &mut rng);
+                    if sender.send(game).is_err() {
+                        break
+                    }
+                }
+            });
+        }
+        drop(sender);
+        receiver.iter().for_each(&mut on_game);
+    });
+}
+
+/**
+ * Plays one game: the random opening moves, then the agents' searched moves until the game ends or the move limit.
+ */
+pub fn play_game<R: Rng + ?Sized>(index: usize, config: &SelfPlayConfig, player1: &AgentFactory, player2: &AgentFactory, rng: &mut R) -> SelfPlayGame {
+    let mut board = GameBoard::start(config.rules);
+    let mut record = GameRecord::new();
+    record.set_rules(config.rules);
+    record.set_tag("Event", &format!("self-play {}", index + 1));
+    let mut samples = vec![];
+    while !board.is_terminal() && record.moves.len() < config.max_moves {
+        let turn_move = if record.moves.len() < config.random_plies {
+            let moves = board.legal_moves();
+            moves.get(rng.random_range(0..moves.len())).expect("Non-terminal boards have moves").clone()
+        } else {
+            let factory = match board.to_move() {
+                Player::Player1 => player1,
+                Player::Player2 => player2,
+            };
+            //a player whose agent cannot be built forfeits, with the reason in the record
+            let sample = match search(factory, &board, config.limits) {
+                Ok(sample) => sample,
+                Err(error) => {
+                    record.set_tag("Forfeit", &error.to_string());
+                    record.set_outcome(&Outcome::Win(board.to_move().opponent(), Reason::Forfeit));
+                    return SelfPlayGame { index, record, samples }
+                },
+            };
+            let turn_move = sample.turn_move.clone();
+            samples.push(sample);
+            turn_move
+        };
+        board = board.result(turn_move.clone());
+        record.moves.push(turn_move);
+    }
+    let outcome = match board.outcome() {
+        Outcome::Ongoing => Outcome::Draw(Reason::MoveLimit),
+        outcome => outcome,
+    };
+    record.set_outcome(&outcome);
+    SelfPlayGame { index, record, samples }
+}
+
+/**
+ * The move an agent from the factory plays, with the visits of all root moves if it analyses.
+ */
+fn search(factory: &AgentFactory, board: &GameBoard, limits: GoLimits) -> Result<Sample, ConfigError> {
+    let (turn_move, visits) = match factory(board, limits)? {
+        EngineAgent::Plain(mut agent) => (agent.get_move(board), vec![]),
+        EngineAgent::Analyzing(mut agent) => {
+            let analysis = agent.analyze(board, usize::MAX, &mut |_| {});
+            let turn_move = analysis.best_move().expect("Non-terminal boards have moves").clone();
+            (turn_move, analysis.lines.into_iter().map(|line| (line.turn_move, line.visits)).collect())
+        },
+    };
+    Ok(Sample { board: board.clone(), turn_move, visits })
+}
+
+/**
+ * The formats self-play datasets are written in.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatasetFormat {
+    Jsonl,
+    Binary,
+}
+
+impl FromStr for DatasetFormat {
+    type Err = ParseNotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" => Ok(DatasetFormat::Jsonl),
+            "binary" => Ok(DatasetFormat::Binary),
+            _ => Err(ParseNotationError::new(s, "the dataset format must be jsonl or binary")),
+        }
+    }
+}
+
+/**
+ * Writes the samples of self-play games played by the same rules.
+ */
+pub struct DatasetWriter<W: Write> {
+    out: W,
+    format: DatasetFormat,
+}
+
+impl<W: Write> DatasetWriter<W> {
+    /**
+     * A writer for games played by the rules, writing the header of binary datasets.
+     */
+    pub fn new(mut out: W, format: DatasetFormat, rules: RuleSet) -> io::Result<Self> {
+        if format == DatasetFormat::Binary {
+            let rules = rules.to_notation();
+            let rules_len = u16::try_from(rules.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "the rules are too long"))?;
+            out.write_all(BINARY_MAGIC)?;
+            out.write_all(&[BINARY_VERSION])?;
+            out.write_all(&rules_len.to_le_bytes())?;
+            out.write_all(rules.as_bytes())?;
+        }
+        Ok(Self { out, format })
+    }
+
+    /**
+     * Writes every sample of a game.
+     */
+    pub fn write_game(&mut self, game: &SelfPlayGame) -> io::Result<()> {
+        let outcome = game.record.outcome();
+        //random opening moves are not sampled, so the samples are the last moves of the game
+        let first_ply = game.record.moves.len() - game.samples.len();
+        for (ply, sample) in (first_ply..).zip(&game.samples) {
+            let result = outcome.utility(sample.board.to_move()).expect("self-play games are finished");
+            match self.format {
+                DatasetFormat::Jsonl => {
+                    let visits: serde_json::Map<String, serde_json::Value> = sample.visits.iter()
+                        .map(|(turn_move, visits)| (turn_move.to_notation(), json!(visits)))
+                        .collect();
+                    let line = json!({
+                        "game": game.index,
+                        "ply": ply,
+                        "rules": sample.board.rules().to_notation(),
+                        "position": sample.board.to_notation(),
+                        "move": sample.turn_move.to_notation(),
+                        "visits": visits,
+                        "result": result,
+                        "outcome": result_to_notation(game.record.result),
+                        "termination": outcome.reason().map(|reason| reason.to_notation()),
+                    });
+                    writeln!(self.out, "{line}")?;
+                },
+                DatasetFormat::Binary => {
+                    let visited = u16::try_from(sample.visits.len()).expect("boards have at most MAX_MOVES moves");
+                    self.out.write_all(&sample.board.to_bytes())?;
+                    self.out.write_all(&sample.turn_move.to_bytes())?;
+                    self.out.write_all(&[(result * 2.0) as u8])?;
+                    self.out.write_all(&visited.to_le_bytes())?;
+                    for (turn_move, visits) in &sample.visits {
+                        self.out.write_all(&turn_move.to_bytes())?;
+                        self.out.write_all(&u32::try_from(*visits).unwrap_or(u32::MAX).to_le_bytes())?;
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Flushes and returns the output.
+     */
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::*;
+    use crate::agents::{minimax::MinimaxAgent, random_agent::RandomAgent};
+
+    #[test]
+    fn datasets_hold_every_searched_move() {
+        let random: AgentFactory = Arc::new(|_, _| Ok(EngineAgent::Plain(Box::new(RandomAgent::default()))));
+        let alpha_beta: AgentFactory = Arc::new(|_, _| Ok(EngineAgent::Analyzing(Box::new(MinimaxAgent::new(1)))));
+        let config = SelfPlayConfig { games: 4, threads: 2, random_plies: 2, limits: GoLimits { time: Some(Duration::from_millis(10)), depth: Some(1) }, ..Default::default() };
+        let mut games = vec![];
+        run(&config, &random, &alpha_beta, |game| games.push(game));
+        games.sort_by_key(|game| game.index);
+        assert_eq!(games.iter().map(|game| game.index).collect::<Vec<_>>(), [0, 1, 2, 3]);
+
+        let mut jsonl = DatasetWriter::new(vec![], DatasetFormat::Jsonl, config.rules).unwrap();
+        let mut binary = DatasetWriter::new(vec![], DatasetFormat::Binary, config.rules).unwrap();
+        for game in &games {
+            assert!(game.record.outcome().is_over());
+            assert_eq!(game.samples.len(), game.record.moves.len().saturating_sub(2));
+            for sample in &game.samples {
+                match sample.board.to_move() {
+                    Player::Player1 => assert!(sample.visits.is_empty()),
+                    Player::Player2 => assert!(sample.visits.iter().any(|(turn_move, _)| *turn_move == sample.turn_move)),
+                }
+            }
+            jsonl.write_game(game).unwrap();
+            binary.write_game(game).unwrap();
+        }
+        let jsonl = String::from_utf8(jsonl.finish().unwrap()).unwrap();
+        let samples: usize = games.iter().map(|game| game.samples.len()).sum();
+        assert_eq!(jsonl.lines().count(), samples);
+        for line in jsonl.lines() {
+            let sample: serde_json::Value = serde_json::from_str(line).unwrap();
+            let board: GameBoard = sample["position"].as_str().unwrap().parse().unwrap();
+            let turn_move: TurnMove = sample["move"].as_str().unwrap().parse().unwrap();
+            assert!(board.try_result(&turn_move).is_ok());
+        }
+        assert!(binary.finish().unwrap().starts_with(b"NTSP\x01\x08\x00standard"));
+    }
+
+    #[test]
+    fn players_whose_agent_cannot_be_built_forfeit() {
+        let random: AgentFactory = Arc::new(|_, _| Ok(EngineAgent::Plain(Box::new(RandomAgent::default()))));
+        let missing: AgentFactory = Arc::new(|_, _| Err(ConfigError::Build("could not start engine: not found".to_string())));
+        let config = SelfPlayConfig { games: 1, random_plies: 0, ..Default::default() };
+        let game = play_game(0, &config, &random, &missing, &mut rand::rng());
+        assert_eq!(game.record.outcome(), Outcome::Win(Player::Player1, Reason::Forfeit));
+        assert_eq!((game.record.moves.len(), game.samples.len()), (1, 1));
+        assert!(game.record.to_string().contains("[Forfeit \"could not start engine: not found\"]"));
+    }
+}
@@ -0,0 +1,221 @@
+/*
+ * Serde support, enabled by the `serde` feature.
+ *
+ * Human-readable formats such as JSON use the notations: positions as `c3`, moves as `c3c5`,
+ * turn moves as `c3c5/a1a2`, boards as `11111/...../..X../...../22222 1` and players as `player1`.
+ * Rule sets are written in their notation in every format. Boards are serialized without their rules.
+ * Binary formats get the compact form instead (see `Position::to_byte`, `TurnMove::to_bytes` and `GameBoard::to_bytes`),
+ * a board as a byte string.
+ */
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{neutrino_board::{GameBoard, MoveType, Player, Position, TurnMove}, rules::RuleSet};
+
+/**
+ * Deserializes the notation string of a value in human-readable formats.
+ */
+fn parse_notation<'de, D: Deserializer<'de>, T: std::str::FromStr<Err = crate::notation::ParseNotationError>>(deserializer: D) -> Result<T, D::Error> {
+    let notation = String::deserialize(deserializer)?;
+    notation.parse().map_err(D::Error::custom)
+}
+
+impl Serialize for Position {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_notation())
+        } else {
+            serializer.serialize_u8(self.to_byte())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Position {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return parse_notation(deserializer)
+        }
+        let square = u8::deserialize(deserializer)?;
+        Position::from_byte(square).ok_or_else(|| D::Error::custom(format!("square {square:#04x} is off every board")))
+    }
+}
+
+impl Serialize for MoveType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_notation())
+        } else {
+            (self.from_position, self.to_position).serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MoveType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return parse_notation(deserializer)
+        }
+        let (from_position, to_position) = <(Position, Position)>::deserialize(deserializer)?;
+        Ok(MoveType::new(from_position, to_position))
+    }
+}
+
+impl Serialize for TurnMove {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_notation())
+        } else {
+            self.to_bytes().serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TurnMove {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return parse_notation(deserializer)
+        }
+        let bytes = <[u8; 4]>::deserialize(deserializer)?;
+        TurnMove::from_bytes(bytes).ok_or_else(|| D::Error::custom("a square of the move is off every board"))
+    }
+}
+
+impl Serialize for Player {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match (serializer.is_human_readable(), self) {
+            (true, Player::Player1) => serializer.serialize_str("player1"),
+            (true, Player::Player2) => serializer.serialize_str("player2"),
+            (false, Player::Player1) => serializer.serialize_u8(1),
+            (false, Player::Player2) => serializer.serialize_u8(2),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Player {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return match String::deserialize(deserializer)?.as_str() {
+                "player1" => Ok(Player::Player1),
+                "player2" => Ok(Player::Player2),
+                player => Err(D::Error::custom(format!("unknown player {player}"))),
+            }
+        }
+        match u8::deserialize(deserializer)? {
+            1 => Ok(Player::Player1),
+            2 => Ok(Player::Player2),
+            player => Err(D::Error::custom(format!("unknown player {player}"))),
+        }
+    }
+}
+
+impl Serialize for RuleSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_notation())
+    }
+}
+
+impl<'de> Deserialize<'de> for RuleSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        parse_notation(deserializer)
+    }
+}
+
+impl Serialize for GameBoard {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.serialize_str(&self.to_notation())
+        }
+        self.to_bytes().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for GameBoard {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return parse_notation(deserializer)
+        }
+        GameBoard::from_bytes(&Vec::<u8>::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::seq::IndexedRandom;
+
+    use super::*;
+    use crate::game_record::GameRecord;
+
+    /// The boards and moves of a random game.
+    fn random_game() -> Vec<(GameBoard, TurnMove)> {
+        let mut rng = rand::rng();
+        let mut board = GameBoard::default();
+        let mut played = vec![];
+        while !board.is_terminal() && played.len() < 40 {
+            let turn_move = board.actions().choose(&mut rng).expect("Non-terminal boards have moves").clone();
+            played.push((board.clone(), turn_move.clone()));
+            board = board.result(turn_move);
+        }
+        played
+    }
+
+    #[test]
+    fn json_uses_notation() {
+        let board = GameBoard::default();
+        assert_eq!(serde_json::to_string(&board).unwrap(), "\"11111/...../..X../...../22222 1\"");
+        let turn_move: TurnMove = "c3c5/a1a2".parse().unwrap();
+        assert_eq!(serde_json::to_string(&turn_move).unwrap(), "\"c3c5/a1a2\"");
+        assert_eq!(serde_json::to_string(&turn_move.piece_move).unwrap(), "\"a1a2\"");
+        assert_eq!(serde_json::to_string(&Position::new(2, 3)).unwrap(), "\"d3\"");
+        assert_eq!(serde_json::to_string(&Player::Player2).unwrap(), "\"player2\"");
+    }
+
+    #[test]
+    fn json_round_trip() {
+        for (board, turn_move) in random_game() {
+            let json = serde_json::to_string(&board).unwrap();
+            assert_eq!(serde_json::from_str::<GameBoard>(&json).unwrap().to_notation(), board.to_notation());
+            let json = serde_json::to_string(&turn_move).unwrap();
+            assert_eq!(serde_json::from_str::<TurnMove>(&json).unwrap(), turn_move);
+        }
+        for player in [Player::Player1, Player::Player2] {
+            assert_eq!(serde_json::from_str::<Player>(&serde_json::to_string(&player).unwrap()).unwrap(), player);
+        }
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        for (board, turn_move) in random_game() {
+            let bytes = bincode::serialize(&board).unwrap();
+            // bincode writes the length of the byte string as a u64 before the size byte and the 7 bytes of squares
+            assert_eq!(bytes.len(), 16);
+            assert_eq!(bincode::deserialize::<GameBoard>(&bytes).unwrap().to_notation(), board.to_notation());
+            let bytes = bincode::serialize(&turn_move).unwrap();
+            assert_eq!(bytes.len(), 4);
+            assert_eq!(bincode::deserialize::<TurnMove>(&bytes).unwrap(), turn_move);
+        }
+        let board = GameBoard::start("size=7".parse().unwrap());
+        let bytes = bincode::serialize(&board).unwrap();
+        assert_eq!(bincode::deserialize::<GameBoard>(&bytes).unwrap().to_notation(), board.to_notation());
+    }
+
+    #[test]
+    fn game_record_round_trip() {
+        let mut record = GameRecord::new();
+        record.set_tag("Player1", "mcts");
+        record.moves = random_game().into_iter().map(|(_, turn_move)| turn_move).collect();
+        record.comments.insert(0, "opening".to_string());
+        record.result = Some(1.0);
+        let json: GameRecord = serde_json::from_str(&serde_json::to_string(&record).unwrap()).unwrap();
+        let binary: GameRecord = bincode::deserialize(&bincode::serialize(&record).unwrap()).unwrap();
+        for decoded in [json, binary] {
+            assert_eq!(decoded.to_string(), record.to_string());
+        }
+    }
+
+    #[test]
+    fn invalid_input_is_rejected() {
+        assert!(serde_json::from_str::<TurnMove>("\"c3c/a1a2\"").is_err());
+        assert!(serde_json::from_str::<GameBoard>("\"11111/...../...../...../22222 1\"").is_err());
+        assert!(bincode::deserialize::<Position>(&[25]).is_err());
+        assert!(bincode::deserialize::<GameBoard>(&0u64.to_le_bytes()).is_err());
+    }
+}
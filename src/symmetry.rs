@@ -0,0 +1,150 @@
+use crate::neutrino_board::{GameBoard, MoveType, Piece, Player, Position, Squares, TurnMove};
+
+/**
+ * A symmetry of the board: mirroring the columns left to right, flipping the rows while swapping the colours
+ * of the pieces and the player to move, or both. The rules treat both players alike, so a position and its
+ * images are equally good for the corresponding players and have corresponding moves.
+ *
+ * Every symmetry is its own inverse: applying it again maps an image back.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Symmetry {
+    Identity,
+    /// Column a becomes the last column and the other way round.
+    Mirror,
+    /// Row 1 becomes the last row, Player1's pieces become Player2's and the other player is to move.
+    ColourFlip,
+    MirrorColourFlip,
+}
+
+impl Symmetry {
+    pub const ALL: [Symmetry; 4] = [Symmetry::Identity, Symmetry::Mirror, Symmetry::ColourFlip, Symmetry::MirrorColourFlip];
+
+    fn mirrors(self) -> bool {
+        matches!(self, Symmetry::Mirror | Symmetry::MirrorColourFlip)
+    }
+
+    pub fn swaps_colours(self) -> bool {
+        matches!(self, Symmetry::ColourFlip | Symmetry::MirrorColourFlip)
+    }
+
+    /**
+     * The image of a square on a board of the given size.
+     */
+    pub fn position(self, position: Position, size: usize) -> Position {
+        let row = if self.swaps_colours() {size - 1 - position.row} else {position.row};
+        let column = if self.mirrors() {size - 1 - position.column} else {position.column};
+        Position::new(row, column)
+    }
+
+    pub fn move_type(self, slide: &MoveType, size: usize) -> MoveType {
+        MoveType::new(self.position(slide.from_position, size), self.position(slide.to_position, size))
+    }
+
+    pub fn turn_move(self, turn_move: &TurnMove, size: usize) -> TurnMove {
+        TurnMove {
+            neutrino_move: turn_move.neutrino_move.as_ref().map(|neutrino_move| self.move_type(neutrino_move, size)),
+            piece_move: self.move_type(&turn_move.piece_move, size),
+        }
+    }
+
+    pub fn player(self, player: Player) -> Player {
+        if self.swaps_colours() {player.opponent()} else {player}
+    }
+
+    pub fn piece(self, piece: Piece) -> Piece {
+        match (piece, self.swaps_colours()) {
+            (Piece::Player1, true) => Piece::Player2,
+            (Piece::Player2, true) => Piece::Player1,
+            (piece, _) => piece,
+        }
+    }
+
+    /**
+     * The image of the pieces on a board.
+     */
+    pub fn squares(self, squares: &Squares) -> Squares {
+        let size = squares.size();
+        let mut image = Squares::empty(size);
+        for (row_index, row) in squares.rows().enumerate() {
+            for (column_index, square) in row.iter().enumerate() {
+                let position = self.position(Position::new(row_index, column_index), size);
+                image[position.row][position.column] = square.map(|piece| self.piece(piece));
+            }
+        }
+        image
+    }
+}
+
+impl GameBoard {
+    /**
+     * The position a symmetry maps this one to, played by the same rules and with the same turns without progress.
+     * The image has no history of earlier positions. `None` on a piece-only first turn if the image is not the
+     * start position again, as then it is not an equivalent position.
+     */
+    pub fn image(&self, symmetry: Symmetry) -> Option<GameBoard> {
+        let image = GameBoard::new(symmetry.squares(self.squares()), symmetry.player(self.to_move()))
+            .with_rules(self.rules())
+            .with_turns_without_progress(self.turns_without_progress());
+        (image.is_piece_only_turn() == self.is_piece_only_turn()).then_some(image)
+    }
+
+    /**
+     * The image of this position with the smallest hash, and the symmetry mapping this position to it.
+     * Symmetric positions have the same canonical form, so storing results under it shares them between the images;
+     * moves are mapped to the canonical position and back with the symmetry.
+     */
+    pub fn canonical(&self) -> (GameBoard, Symmetry) {
+        Symmetry::ALL.into_iter()
+            .filter_map(|symmetry| Some((self.image(symmetry)?, symmetry)))
+            .min_by_key(|(image, _)| image.hash())
+            .expect("the identity always applies")
+    }
+
+    /**
+     * The hash of the canonical form, the same for all symmetric positions.
+     */
+    pub fn canonical_hash(&self) -> u64 {
+        self.canonical().0.hash()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::outcome::Outcome;
+
+    proptest! {
+        #[test]
+        fn images_are_equivalent_positions(choices in prop::collection::vec(any::<usize>(), 0..40)) {
+            //images have no history, so they cannot be drawn by repetition
+            let mut board = GameBoard::start("no-repetition-draw".parse().unwrap());
+            for choice in choices {
+                let size = board.size();
+                for symmetry in Symmetry::ALL {
+                    let Some(image) = board.image(symmetry) else { continue };
+                    let back = image.image(symmetry).unwrap();
+                    prop_assert_eq!((back.squares(), back.to_move()), (board.squares(), board.to_move()));
+                    prop_assert_eq!(image.canonical_hash(), board.canonical_hash());
+                    let mut moves: Vec<String> = board.moves().map(|turn_move| symmetry.turn_move(&turn_move, size).to_notation()).collect();
+                    let mut image_moves: Vec<String> = image.moves().map(|turn_move| turn_move.to_notation()).collect();
+                    moves.sort();
+                    image_moves.sort();
+                    prop_assert_eq!(moves, image_moves);
+                    let outcome = match board.outcome() {
+                        Outcome::Win(player, reason) => Outcome::Win(symmetry.player(player), reason),
+                        outcome => outcome,
+                    };
+                    prop_assert_eq!(image.outcome(), outcome);
+                }
+                let actions = board.actions();
+                if actions.is_empty() {
+                    break
+                }
+                board.make_move(&actions[choice % actions.len()]);
+            }
+        }
+    }
+}
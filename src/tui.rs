@@ -0,0 +1,663 @@
+use std::{io::{self, Write}, sync::mpsc::{self, Receiver, Sender}, thread, time::{Duration, Instant}};
+
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind, MouseButton, MouseEventKind},
+    execute, queue,
+    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+
+use crate::{agents::{agent::StopHandle, analysis::Analysis}, engine_protocol::{AgentFactory, EngineAgent, GoLimits}, neutrino_board::{GameBoard, MoveType, Piece, Player, Position, TurnMove}, outcome::{Outcome, Reason}, rules::RuleSet};
+
+const BOARD_TOP: u16 = 2;
+const BOARD_LEFT: u16 = 3;
+const CELL_WIDTH: u16 = 3;
+/// The gap between the board and the move list to its right.
+const PANEL_GAP: u16 = 6;
+/// How long the analysis key lets the analysis engine think about the human's position.
+const ANALYSIS_TIME: Duration = Duration::from_secs(3);
+/// An engine still searching when its clock gets this low is stopped and plays its best move so far.
+const CLOCK_RESERVE: Duration = Duration::from_millis(200);
+
+/**
+ * Who plays a side in the terminal UI.
+ */
+pub enum TuiPlayer {
+    Human,
+    Engine(AgentFactory),
+}
+
+/**
+ * What the human is selecting: the neutrino's destination, then the piece, then the piece's destination.
+ * A piece-only first turn starts with the piece.
+ */
+#[derive(Debug, Clone, PartialEq)]
+enum Selection {
+    Neutrino,
+    Piece(Option<MoveType>),
+    Destination(Option<MoveType>, Position),
+}
+
+impl Selection {
+    fn first(board: &GameBoard) -> Self {
+        match board.is_piece_only_turn() {
+            true => Selection::Piece(None),
+            false => Selection::Neutrino,
+        }
+    }
+}
+
+/**
+ * Messages from engine threads. Each carries the hash of the position it is about,
+ * so results for positions that have since been left (e.g. after an undo) are ignored.
+ */
+enum SearchEvent {
+    Update(u64, Analysis),
+    Move(u64, TurnMove),
+    AnalysisDone(u64),
+    /// The engine could not be built.
+    Failed(u64, String),
+}
+
+struct App {
+    players: [TuiPlayer; 2],
+    analysis_engine: Option<AgentFactory>,
+    move_time: Option<Duration>,
+    history: Vec<GameBoard>,
+    moves: Vec<TurnMove>,
+    cursor: Position,
+    selection: Selection,
+    /// Time used by each player, or remaining if there is a time control.
+    clocks: [Duration; 2],
+    time_control: Option<Duration>,
+    turn_started: Instant,
+    events: (Sender<SearchEvent>, Receiver<SearchEvent>),
+    /// Hash of the position an engine is currently choosing a move for.
+    searching: Option<u64>,
+    /// Stops the search of the engine choosing a move.
+    engine_stop: Option<StopHandle>,
+    analysing: Option<u64>,
+    analysis: Option<(u64, Analysis)>,
+    status: String,
+    result: Option<Outcome>,
+    quit: bool,
+}
+
+/**
+ * Restores the terminal when the UI ends, including by a panic.
+ */
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn new() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture, Hide)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, DisableMouseCapture, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn player_index(player: Player) -> usize {
+    match player {
+        Player::Player1 => 0,
+        Player::Player2 => 1,
+    }
+}
+
+/**
+ * Runs a full-screen game between two players until the user quits.
+ * With a time control the clocks count down and a player whose clock runs out loses.
+ * Engines think for `move_time` if given, or for their own time otherwise.
+ */
+pub fn run(players: [TuiPlayer; 2], analysis_engine: Option<AgentFactory>, move_time: Option<Duration>, time_control: Option<Duration>, rules: RuleSet) -> io::Result<()> {
+    let _guard = TerminalGuard::new()?;
+    let mut app = App::new(players, analysis_engine, move_time, time_control, rules);
+    let mut stdout = io::stdout();
+    while !app.quit {
+        app.receive_events();
+        app.check_game_over();
+        app.stop_engine_if_out_of_time();
+        app.start_engine_if_needed();
+        app.draw(&mut stdout)?;
+        if event::poll(Duration::from_millis(50))? {
+            match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => app.handle_key(key),
+                Event::Mouse(mouse) if mouse.kind == MouseEventKind::Down(MouseButton::Left) => {
+                    if let Some(position) = square_at(app.board().size(), mouse.column, mouse.row) {
+                        app.cursor = position;
+                        app.select();
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+    if let Some(stop_handle) = &app.engine_stop {
+        stop_handle.stop();
+    }
+    Ok(())
+}
+
+/**
+ * The square of a board of the given size drawn at a screen cell, if any.
+ */
+fn square_at(size: usize, column: u16, row: u16) -> Option<Position> {
+    if column < BOARD_LEFT || row < BOARD_TOP {
+        return None
+    }
+    let (board_column, screen_row) = ((column - BOARD_LEFT) / CELL_WIDTH, row - BOARD_TOP);
+    if board_column as usize >= size || screen_row as usize >= size {
+        return None
+    }
+    Some(Position::new(size - 1 - screen_row as usize, board_column as usize))
+}
+
+impl App {
+    fn new(players: [TuiPlayer; 2], analysis_engine: Option<AgentFactory>, move_time: Option<Duration>, time_control: Option<Duration>, rules: RuleSet) -> Self {
+        let start = GameBoard::start(rules);
+        Self {
+            players,
+            analysis_engine,
+            move_time,
+            cursor: start.neutrino_position(),
+            selection: Selection::first(&start),
+            history: vec![start],
+            moves: vec![],
+            clocks: [time_control.unwrap_or_default(); 2],
+            time_control,
+            turn_started: Instant::now(),
+            events: mpsc::channel(),
+            searching: None,
+            engine_stop: None,
+            analysing: None,
+            analysis: None,
+            status: String::new(),
+            result: None,
+            quit: false,
+        }
+    }
+
+    fn board(&self) -> &GameBoard {
+        self.history.last().expect("history starts with the start position")
+    }
+
+    fn human_to_move(&self) -> bool {
+        self.result.is_none() && matches!(self.players[player_index(self.board().to_move())], TuiPlayer::Human)
+    }
+
+    /**
+     * The time on the clock of the player to move, including the current turn.
+     */
+    fn clock(&self, player: Player) -> Duration {
+        let index = player_index(player);
+        let running = self.result.is_none() && self.board().to_move() == player;
+        let elapsed = if running {self.turn_started.elapsed()} else {Duration::ZERO};
+        match self.time_control {
+            Some(_) => self.clocks[index].saturating_sub(elapsed),
+            None => self.clocks[index] + elapsed,
+        }
+    }
+
+    fn play(&mut self, turn_move: TurnMove) {
+        let mover = self.board().to_move();
+        self.clocks[player_index(mover)] = self.clock(mover);
+        self.turn_started = Instant::now();
+        let board = self.board().result(turn_move.clone());
+        self.cursor = board.neutrino_position();
+        self.history.push(board);
+        self.moves.push(turn_move);
+        self.selection = Selection::first(self.board());
+        self.status.clear();
+    }
+
+    fn check_game_over(&mut self) {
+        if self.result.is_some() {
+            return
+        }
+        let board = self.board();
+        let outcome = board.outcome();
+        if outcome.is_over() {
+            self.result = Some(outcome);
+        } else if self.time_control.is_some() && self.clock(board.to_move()).is_zero() {
+            self.result = Some(Outcome::Win(board.to_move().opponent(), Reason::Timeout));
+        }
+    }
+
+    fn receive_events(&mut self) {
+        while let Ok(event) = self.events.1.try_recv() {
+            let hash = self.board().hash();
+            match event {
+                SearchEvent::Update(event_hash, analysis) if event_hash == hash => self.analysis = Some((event_hash, analysis)),
+                SearchEvent::Move(event_hash, turn_move) if self.searching == Some(event_hash) => {
+                    self.searching = None;
+                    self.engine_stop = None;
+                    if event_hash == hash && self.result.is_none() {
+                        self.play(turn_move);
+                    }
+                },
+                SearchEvent::AnalysisDone(event_hash) if self.analysing == Some(event_hash) => self.analysing = None,
+                //an engine that cannot be built to choose a move forfeits
+                SearchEvent::Failed(event_hash, error) => {
+                    if self.searching == Some(event_hash) {
+                        self.searching = None;
+                        self.engine_stop = None;
+                        if event_hash == hash && self.result.is_none() {
+                            self.result = Some(Outcome::Win(self.board().to_move().opponent(), Reason::Forfeit));
+                        }
+                    }
+                    if self.analysing == Some(event_hash) {
+                        self.analysing = None;
+                    }
+                    self.status = error;
+                },
+                _ => {},
+            }
+        }
+    }
+
+    fn start_engine_if_needed(&mut self) {
+        if self.result.is_some() || self.searching.is_some() {
+            return
+        }
+        let TuiPlayer::Engine(factory) = &self.players[player_index(self.board().to_move())] else {
+            return
+        };
+        let hash = self.board().hash();
+        self.searching = Some(hash);
+        self.engine_stop = Some(self.spawn_search(factory.clone(), hash, true));
+    }
+
+    /**
+     * Makes an engine move at once, with the best move it has found, when its clock is about to run out.
+     */
+    fn stop_engine_if_out_of_time(&self) {
+        if let Some(stop_handle) = &self.engine_stop
+            && self.time_control.is_some()
+            && self.clock(self.board().to_move()) <= CLOCK_RESERVE {
+            stop_handle.stop();
+        }
+    }
+
+    /**
+     * Runs an engine on the current position on its own thread, sending analysis updates
+     * and, if `play` is set, the chosen move, or why the engine could not be built. The returned handle stops the search.
+     */
+    fn spawn_search(&self, factory: AgentFactory, hash: u64, play: bool) -> StopHandle {
+        let board = self.board().clone();
+        let sender = self.events.0.clone();
+        let limits = GoLimits { time: if play {self.move_time} else {Some(ANALYSIS_TIME)}, depth: None };
+        let stop_handle = StopHandle::new();
+        let thread_stop_handle = stop_handle.clone();
+        thread::spawn(move || {
+            let agent = match factory(&board, limits) {
+                Ok(agent) => agent,
+                Err(error) => {
+                    let _ = sender.send(SearchEvent::Failed(hash, format!("the engine could not be started: {error}")));
+                    return
+                },
+            };
+            let turn_move = match agent {
+                EngineAgent::Plain(mut agent) => {
+                    agent.set_stop_handle(thread_stop_handle);
+                    agent.get_move(&board)
+                },
+                EngineAgent::Analyzing(mut agent) => {
+                    agent.set_stop_handle(thread_stop_handle);
+                    let analysis = agent.analyze(&board, 3, &mut |analysis| {
+                        let _ = sender.send(SearchEvent::Update(hash, analysis.clone()));
+                    });
+                    let _ = sender.send(SearchEvent::Update(hash, analysis.clone()));
+                    analysis.best_move().cloned().expect("Non-terminal boards have moves")
+                },
+            };
+            let _ = sender.send(if play {SearchEvent::Move(hash, turn_move)} else {SearchEvent::AnalysisDone(hash)});
+        });
+        stop_handle
+    }
+
+    fn undo(&mut self) {
+        if self.searching.is_some() {
+            self.status = "wait for the engine to move before taking back".to_string();
+            return
+        }
+        //go back to the last position before this one where a human was to move
+        let target = (0..self.history.len() - 1)
+            .rev()
+            .find(|index| matches!(self.players[player_index(self.history[*index].to_move())], TuiPlayer::Human));
+        let Some(target) = target else {
+            self.status = "there is no move to take back".to_string();
+            return
+        };
+        self.history.truncate(target + 1);
+        self.moves.truncate(target);
+        self.result = None;
+        self.selection = Selection::first(self.board());
+        self.cursor = self.board().neutrino_position();
+        self.turn_started = Instant::now();
+        self.status = "took back".to_string();
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        let (row, column) = (self.cursor.row, self.cursor.column);
+        let last = self.board().size() - 1;
+        match key.code {
+            KeyCode::Char('q') => self.quit = true,
+            KeyCode::Up | KeyCode::Char('k') => self.cursor.row = (row + 1).min(last),
+            KeyCode::Down | KeyCode::Char('j') => self.cursor.row = row.saturating_sub(1),
+            KeyCode::Left | KeyCode::Char('h') => self.cursor.column = column.saturating_sub(1),
+            KeyCode::Right | KeyCode::Char('l') => self.cursor.column = (column + 1).min(last),
+            KeyCode::Enter | KeyCode::Char(' ') => self.select(),
+            KeyCode::Esc => self.selection = Selection::first(self.board()),
+            KeyCode::Char('u') => self.undo(),
+            KeyCode::Char('m') => match &self.engine_stop {
+                Some(stop_handle) => stop_handle.stop(),
+                None => self.status = "no engine is thinking".to_string(),
+            },
+            KeyCode::Char('r') if self.human_to_move() => {
+                self.result = Some(Outcome::Win(self.board().to_move().opponent(), Reason::Resignation));
+            },
+            KeyCode::Char('a') => match (&self.analysis_engine, self.analysing) {
+                (None, _) => self.status = "no analysis engine configured".to_string(),
+                (Some(_), Some(_)) => self.status = "already analysing".to_string(),
+                (Some(factory), None) => if !self.human_to_move() {
+                    self.status = "analysis is available on your turn".to_string();
+                } else {
+                    let hash = self.board().hash();
+                    self.analysing = Some(hash);
+                    self.spawn_search(factory.clone(), hash, false);
+                },
+            },
+            _ => {},
+        }
+    }
+
+    /**
+     * The moves that are still possible with the current selection.
+     */
+    fn candidate_moves(&self) -> Vec<TurnMove> {
+        self.board().actions().into_iter().filter(|turn_move| match &self.selection {
+            Selection::Neutrino => true,
+            Selection::Piece(neutrino_move) => turn_move.neutrino_move == *neutrino_move,
+            Selection::Destination(neutrino_move, from) => turn_move.neutrino_move == *neutrino_move && turn_move.piece_move.from_position == *from,
+        }).collect()
+    }
+
+    /**
+     * The squares that can be chosen in the current selection step.
+     */
+    fn targets(&self) -> Vec<Position> {
+        self.candidate_moves().iter().map(|turn_move| match &self.selection {
+            Selection::Neutrino => turn_move.neutrino_move.as_ref().expect("the neutrino is only selected on turns moving it").to_position,
+            Selection::Piece(_) => turn_move.piece_move.from_position,
+            Selection::Destination(_, _) => turn_move.piece_move.to_position,
+        }).collect()
+    }
+
+    fn select(&mut self) {
+        if !self.human_to_move() {
+            return
+        }
+        if !self.targets().contains(&self.cursor) {
+            self.status = "not a legal square, Esc to start over".to_string();
+            return
+        }
+        let candidates = self.candidate_moves();
+        match self.selection.clone() {
+            Selection::Neutrino => {
+                let turn_move = candidates.iter()
+                    .find(|turn_move| turn_move.neutrino_move.as_ref().is_some_and(|neutrino_move| neutrino_move.to_position == self.cursor))
+                    .expect("target is legal");
+                self.selection = Selection::Piece(turn_move.neutrino_move.clone());
+            },
+            Selection::Piece(neutrino_move) => self.selection = Selection::Destination(neutrino_move, self.cursor),
+            Selection::Destination(_, _) => {
+                let turn_move = candidates.into_iter().find(|turn_move| turn_move.piece_move.to_position == self.cursor).expect("target is legal");
+                self.play(turn_move);
+            },
+        }
+        self.status.clear();
+    }
+
+    /**
+     * The board as it looks during the selection: after choosing the neutrino move, the neutrino is shown on its new square.
+     */
+    fn displayed_piece(&self, position: Position) -> Option<Piece> {
+        let neutrino_move = match &self.selection {
+            Selection::Neutrino => None,
+            Selection::Piece(neutrino_move) | Selection::Destination(neutrino_move, _) => neutrino_move.as_ref(),
+        };
+        match neutrino_move {
+            Some(neutrino_move) if position == neutrino_move.from_position => None,
+            Some(neutrino_move) if position == neutrino_move.to_position => Some(Piece::Neutrino),
+            _ => self.board().at_position(position),
+        }
+    }
+
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        queue!(out, Clear(ClearType::All), MoveTo(0, 0), Print("Neutrino  arrows/mouse: select  enter: choose  esc: cancel  u: undo  m: move now  a: analyse  r: resign  q: quit"))?;
+        let targets = if self.human_to_move() {self.targets()} else {vec![]};
+        let last_move = self.moves.last();
+        let last_move_squares: Vec<Position> = last_move.map_or(vec![], |turn_move| turn_move.neutrino_move.iter()
+            .flat_map(|neutrino_move| [neutrino_move.from_position, neutrino_move.to_position])
+            .chain([turn_move.piece_move.from_position, turn_move.piece_move.to_position])
+            .collect());
+
+        let size = self.board().size();
+        for column in 0..size as u16 {
+            queue!(out, MoveTo(BOARD_LEFT + column * CELL_WIDTH + 1, BOARD_TOP - 1), Print((b'a' + column as u8) as char))?;
+        }
+        for screen_row in 0..size as u16 {
+            let row = size - 1 - screen_row as usize;
+            queue!(out, MoveTo(0, BOARD_TOP + screen_row), Print(row + 1))?;
+            for column in 0..size {
+                let position = Position::new(row, column);
+                let background = if position == self.cursor && self.human_to_move() {
+                    Color::White
+                } else if matches!(&self.selection, Selection::Destination(_, from) if *from == position) {
+                    Color::Magenta
+                } else if targets.contains(&position) {
+                    Color::DarkGreen
+                } else if last_move_squares.contains(&position) {
+                    Color::DarkYellow
+                } else if (row + column).is_multiple_of(2) {
+                    Color::DarkGrey
+                } else {
+                    Color::Black
+                };
+                let (symbol, foreground) = match self.displayed_piece(position) {
+                    Some(Piece::Player1) => ('1', Color::Blue),
+                    Some(Piece::Player2) => ('2', Color::Red),
+                    Some(Piece::Neutrino) => ('X', Color::Yellow),
+                    None => ('.', Color::Grey),
+                };
+                queue!(out,
+                    MoveTo(BOARD_LEFT + column as u16 * CELL_WIDTH, BOARD_TOP + screen_row),
+                    SetBackgroundColor(background), SetForegroundColor(foreground),
+                    Print(format!(" {symbol} ")), ResetColor)?;
+            }
+        }
+
+        let mut line = BOARD_TOP + size as u16 + 1;
+        for player in [Player::Player1, Player::Player2] {
+            let clock = self.clock(player);
+            let marker = if self.result.is_none() && self.board().to_move() == player {">"} else {" "};
+            let kind = match self.players[player_index(player)] {
+                TuiPlayer::Human => "human",
+                TuiPlayer::Engine(_) => "engine",
+            };
+            queue!(out, MoveTo(0, line), Print(format!("{marker} {player:?} ({kind}) {:02}:{:02}", clock.as_secs() / 60, clock.as_secs() % 60)))?;
+            line += 1;
+        }
+        let status = match (&self.result, self.searching) {
+            (Some(result), _) => format!("Game over: {result}"),
+            (None, Some(_)) => "engine is thinking...".to_string(),
+            (None, None) => self.status.clone(),
+        };
+        queue!(out, MoveTo(0, line + 1), Print(status))?;
+
+        let panel_left = BOARD_LEFT + size as u16 * CELL_WIDTH + PANEL_GAP;
+        queue!(out, MoveTo(panel_left, BOARD_TOP - 1), Print("Moves"))?;
+        let visible = 12usize;
+        let first = self.moves.len().saturating_sub(visible);
+        for (offset, turn_move) in self.moves[first..].iter().enumerate() {
+            queue!(out, MoveTo(panel_left, BOARD_TOP + offset as u16), Print(format!("{:>3}. {}", first + offset + 1, turn_move.to_notation())))?;
+        }
+
+        let analysis_top = line + 6;
+        queue!(out, MoveTo(0, analysis_top), Print("Analysis"))?;
+        if let Some((hash, analysis)) = &self.analysis
+            && *hash == self.board().hash() {
+            for (offset, text) in analysis.to_string().lines().enumerate() {
+                queue!(out, MoveTo(0, analysis_top + 1 + offset as u16), Print(text))?;
+            }
+        }
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::agents::{random_agent::RandomAgent, registry::ConfigError};
+
+    fn humans() -> App {
+        App::new([TuiPlayer::Human, TuiPlayer::Human], None, Some(Duration::from_secs(1)), None, RuleSet::STANDARD)
+    }
+
+    fn press(app: &mut App, keys: &[KeyCode]) {
+        for key in keys {
+            app.handle_key(KeyEvent::from(*key));
+        }
+    }
+
+    fn position(notation: &str) -> Position {
+        notation.parse().unwrap()
+    }
+
+    fn screen(app: &App) -> String {
+        let mut out = vec![];
+        app.draw(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn squares_are_found_under_the_mouse() {
+        assert_eq!(square_at(5, BOARD_LEFT, BOARD_TOP), Some(position("a5")));
+        assert_eq!(square_at(5, BOARD_LEFT + 2 * CELL_WIDTH + 2, BOARD_TOP + 2), Some(position("c3")));
+        assert_eq!(square_at(5, BOARD_LEFT + 4 * CELL_WIDTH, BOARD_TOP + 4), Some(position("e1")));
+        assert_eq!(square_at(7, BOARD_LEFT + 6 * CELL_WIDTH, BOARD_TOP + 6), Some(position("g1")));
+        for (column, row) in [(BOARD_LEFT - 1, BOARD_TOP), (BOARD_LEFT, BOARD_TOP - 1), (BOARD_LEFT + 5 * CELL_WIDTH, BOARD_TOP), (BOARD_LEFT, BOARD_TOP + 5)] {
+            assert_eq!(square_at(5, column, row), None);
+        }
+    }
+
+    #[test]
+    fn moves_are_entered_with_keys() {
+        let mut app = humans();
+        assert_eq!((app.cursor, app.selection.clone()), (position("c3"), Selection::Piece(None)));
+        //the cursor stays on the board
+        press(&mut app, &[KeyCode::Down, KeyCode::Char('j'), KeyCode::Down, KeyCode::Left, KeyCode::Char('h'), KeyCode::Left]);
+        assert_eq!(app.cursor, position("a1"));
+
+        //only pieces that can move are chosen on the piece-only first turn
+        press(&mut app, &[KeyCode::Up, KeyCode::Enter]);
+        assert_eq!(app.selection, Selection::Piece(None));
+        assert_eq!(app.status, "not a legal square, Esc to start over");
+        press(&mut app, &[KeyCode::Down, KeyCode::Enter]);
+        assert_eq!(app.selection, Selection::Destination(None, position("a1")));
+        assert_eq!(app.targets(), [position("a4"), position("b2")]);
+        press(&mut app, &[KeyCode::Esc]);
+        assert_eq!(app.selection, Selection::Piece(None));
+        press(&mut app, &[KeyCode::Char(' '), KeyCode::Up, KeyCode::Char('k'), KeyCode::Up, KeyCode::Enter]);
+        assert_eq!(app.moves, ["a1a4".parse::<TurnMove>().unwrap()]);
+        assert_eq!((app.board().to_move(), app.cursor, app.selection.clone()), (Player::Player2, position("c3"), Selection::Neutrino));
+
+        //later turns choose the neutrino's square first, and the board shows it there while the piece is chosen
+        press(&mut app, &[KeyCode::Up, KeyCode::Enter]);
+        let neutrino_move = "c3c4".parse::<MoveType>().unwrap();
+        assert_eq!(app.selection, Selection::Piece(Some(neutrino_move.clone())));
+        assert_eq!((app.displayed_piece(position("c3")), app.displayed_piece(position("c4"))), (None, Some(Piece::Neutrino)));
+        press(&mut app, &[KeyCode::Up, KeyCode::Left, KeyCode::Enter, KeyCode::Down, KeyCode::Down, KeyCode::Down, KeyCode::Enter]);
+        assert_eq!(app.moves.last(), Some(&"c3c4/b5b2".parse().unwrap()));
+        assert_eq!(app.board(), &GameBoard::default().result("a1a4".parse().unwrap()).result("c3c4/b5b2".parse().unwrap()));
+
+        press(&mut app, &[KeyCode::Char('u')]);
+        assert_eq!((app.moves.len(), app.board().to_move(), app.status.as_str()), (1, Player::Player2, "took back"));
+        press(&mut app, &[KeyCode::Char('r')]);
+        assert_eq!(app.result, Some(Outcome::Win(Player::Player1, Reason::Resignation)));
+        //nothing is selected once the game is over
+        press(&mut app, &[KeyCode::Enter]);
+        assert_eq!(app.moves.len(), 1);
+        press(&mut app, &[KeyCode::Char('a'), KeyCode::Char('m')]);
+        assert_eq!(app.status, "no engine is thinking");
+        assert!(!app.quit);
+        press(&mut app, &[KeyCode::Char('q')]);
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn the_board_and_moves_are_drawn() {
+        let mut app = humans();
+        let drawn = screen(&app);
+        assert_eq!((drawn.matches(" 1 ").count(), drawn.matches(" 2 ").count(), drawn.matches(" X ").count()), (5, 5, 1));
+        assert!(drawn.contains("> Player1 (human) 00:00"));
+        assert!(drawn.contains("  Player2 (human) 00:00"));
+
+        app.play("a1a4".parse().unwrap());
+        app.play("c3c4/b5b2".parse().unwrap());
+        let drawn = screen(&app);
+        assert!(drawn.contains("  1. a1a4") && drawn.contains("  2. c3c4/b5b2"));
+        assert!(drawn.contains("> Player1 (human)"));
+        press(&mut app, &[KeyCode::Char('r')]);
+        assert!(screen(&app).contains("Game over: Player2 wins (resignation)"));
+    }
+
+    #[test]
+    fn engines_move_on_their_turn() {
+        let factory: AgentFactory = Arc::new(|_: &GameBoard, _| Ok(EngineAgent::Plain(Box::new(RandomAgent::default()))));
+        let mut app = App::new([TuiPlayer::Human, TuiPlayer::Engine(factory)], None, Some(Duration::from_secs(1)), Some(Duration::from_secs(60)), RuleSet::STANDARD);
+        app.start_engine_if_needed();
+        assert_eq!(app.searching, None);
+        press(&mut app, &[KeyCode::Down, KeyCode::Down, KeyCode::Left, KeyCode::Left, KeyCode::Enter, KeyCode::Up, KeyCode::Up, KeyCode::Up, KeyCode::Enter]);
+        assert_eq!(app.moves.len(), 1);
+
+        app.start_engine_if_needed();
+        assert!(app.searching.is_some() && !app.human_to_move());
+        //a human cannot enter a move or take back while the engine thinks
+        press(&mut app, &[KeyCode::Enter, KeyCode::Char('u')]);
+        assert_eq!(app.status, "wait for the engine to move before taking back");
+        let started = Instant::now();
+        while app.moves.len() < 2 {
+            assert!(started.elapsed() < Duration::from_secs(10), "the engine did not move");
+            thread::sleep(Duration::from_millis(10));
+            app.receive_events();
+        }
+        assert_eq!((app.searching, app.board().to_move()), (None, Player::Player1));
+        assert!(app.clock(Player::Player2) < Duration::from_secs(60));
+    }
+
+    #[test]
+    fn engines_that_cannot_start_forfeit() {
+        let factory: AgentFactory = Arc::new(|_: &GameBoard, _| Err(ConfigError::Build("not found".to_string())));
+        let mut app = App::new([TuiPlayer::Engine(factory), TuiPlayer::Human], None, Some(Duration::from_secs(1)), None, RuleSet::STANDARD);
+        app.start_engine_if_needed();
+        let started = Instant::now();
+        while app.result.is_none() {
+            assert!(started.elapsed() < Duration::from_secs(10), "the engine did not fail");
+            thread::sleep(Duration::from_millis(10));
+            app.receive_events();
+        }
+        assert_eq!(app.result, Some(Outcome::Win(Player::Player2, Reason::Forfeit)));
+        assert_eq!((app.searching, app.status.as_str()), (None, "the engine could not be started: not found"));
+        assert!(app.moves.is_empty());
+    }
+}